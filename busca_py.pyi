@@ -12,22 +12,58 @@ class FileComparison:
     Attributes
     ----------
     path : pathlib.Path
-        Path to the candidate file.
+        Path to the candidate file, or for a file inside an archive a virtual
+        path such as `release.tar.gz!/src/main.py`.
     similarity_ratio : float
         `similar::TextDiff::ratio()` between the reference and the candidate,
         a Ratcliff/Obershelp similarity in [0.0, 1.0] over the line sequences.
         See ADR-0001.
     content : str
-        Full contents of the candidate file.
+        Full contents of the candidate file, decoded to text, or the
+        extracted text of an HTML, DOCX, or PDF document or a notebook.
+    encoding : str
+        Name of the encoding the content was decoded from, such as `UTF-8`,
+        `UTF-16LE`, or `windows-1252`. `binary` for a binary file compared
+        with `binary="compare"`, whose `content` is empty.
+    cell : int, optional
+        For a Jupyter notebook, the index in its `cells` array of the compared
+        cell most similar to the reference. `None` for other files.
     """
 
     path: Path
     similarity_ratio: float
     content: str
+    encoding: str
+    cell: Optional[int]
     def __new__(
-        cls, path: StrPath, similarity_ratio: float, content: str
+        cls,
+        path: StrPath,
+        similarity_ratio: float,
+        content: str,
+        encoding: Optional[str] = None,
+        cell: Optional[int] = None,
     ) -> FileComparison: ...
 
+class Candidate:
+    """
+    A file that a search would score, listed without scoring it.
+
+    Attributes
+    ----------
+    path : pathlib.Path
+        Path to the candidate file.
+    line_count : int
+        Number of lines in the candidate, counted as the `max_file_lines`
+        filter counts them. 0 for a binary file.
+    size_bytes : int
+        Size of the file on disk, or of an archive member uncompressed,
+        before any decoding or text extraction.
+    """
+
+    path: Path
+    line_count: int
+    size_bytes: int
+
 def search(
     reference_string: str,
     search_path: StrPath,
@@ -36,8 +72,63 @@ def search(
     min_similarity_ratio: Optional[float] = None,
     include_glob: Optional[Union[str, list[str]]] = None,
     exclude_glob: Optional[Union[str, list[str]]] = None,
+    config_path: Optional[StrPath] = None,
+    profile: Optional[str] = None,
+    search_archives: bool = False,
+    encoding: Optional[str] = None,
+    lossy: bool = False,
+    binary: Optional[str] = None,
+    extract_text: bool = False,
+    notebook_markdown: bool = False,
 ) -> list[FileComparison]:
     """Walk `search_path` and return a `FileComparison` for each candidate that
     survives the include/exclude globs and `max_file_lines` filter and whose
     `similarity_ratio` is at least `min_similarity_ratio` (when set), ranked by
-    descending `similarity_ratio`."""
+    descending `similarity_ratio`.
+
+    `config_path` names a `.busca.toml`-style file whose values (overlaid with
+    the `[profile.<name>]` table when `profile` is set) fill any kwarg left as
+    `None`. Config files are never discovered implicitly from Python.
+
+    With `search_archives`, the files inside `.zip`, `.tar`, `.tar.gz`, and
+    `.tgz` archives and standalone `.gz` files are candidates too, with paths
+    such as `release.tar.gz!/src/main.py`; the globs apply to those paths.
+
+    Candidates with a byte order mark are decoded as UTF-8 or UTF-16. Others
+    that are not valid UTF-8 are decoded with `encoding` (a label such as
+    `"latin1"` or `"utf-16le"`) when it is set, and otherwise skipped, unless
+    `lossy` replaces their malformed sequences with U+FFFD.
+
+    Files with a NUL byte in their first 8000 bytes are binary. `binary` is
+    `"skip"` (the default), `"report"` to name each one on stderr, or
+    `"compare"` to score them by byte-level chunk similarity with the UTF-8
+    bytes of `reference_string`.
+
+    With `extract_text`, HTML and DOCX candidates (and PDF candidates when the
+    extension was built with the `pdf` feature) are compared as their plain
+    text, which `FileComparison.content` then holds. Jupyter notebooks are
+    compared by the source of their code cells, plus their markdown cells with
+    `notebook_markdown`, and `FileComparison.cell` names the best match.
+    `reference_string` is compared as given, so pass the text of a reference
+    document rather than its markup."""
+
+def list_candidates(
+    search_path: StrPath,
+    max_file_lines: Optional[int] = None,
+    include_glob: Optional[Union[str, list[str]]] = None,
+    exclude_glob: Optional[Union[str, list[str]]] = None,
+    config_path: Optional[StrPath] = None,
+    profile: Optional[str] = None,
+    search_archives: bool = False,
+    encoding: Optional[str] = None,
+    lossy: bool = False,
+    binary: Optional[str] = None,
+    extract_text: bool = False,
+    notebook_markdown: bool = False,
+) -> list[Candidate]:
+    """Walk `search_path` and return a `Candidate` for each file that survives
+    the include/exclude globs and `max_file_lines` filter, in walk order,
+    without scoring it. This is the set of files `search` would compare.
+    `config_path`, `profile`, `search_archives`, `encoding`, `lossy`,
+    `binary`, `extract_text`, and `notebook_markdown` behave as in
+    `search`."""
@@ -1,5 +1,9 @@
 import inspect
+import json
+import os
+import tempfile
 import unittest
+import zipfile
 from pathlib import Path
 from time import perf_counter
 
@@ -46,7 +50,7 @@ class TestExampleUsage(unittest.TestCase):
 
 class TestSignatures(unittest.TestCase):
     def test_module_contains_functions(self):
-        expected_functions = {"search"}
+        expected_functions = {"search", "list_candidates"}
         module_attributes = set(dir(busca))
         self.assertTrue(expected_functions.issubset(module_attributes))
 
@@ -150,6 +154,251 @@ class TestSearchResults(unittest.TestCase):
         )
 
 
+class TestListCandidates(unittest.TestCase):
+    def test_lists_what_search_scores(self):
+        with open("./sample_dir_hello_world/file_1.py", "r") as file:
+            ref_str = file.read()
+        searched = busca.search(
+            reference_string=ref_str,
+            search_path="./sample_dir_hello_world",
+            max_file_lines=8,
+            include_glob="*.py",
+        )
+        listed = busca.list_candidates(
+            search_path="./sample_dir_hello_world",
+            max_file_lines=8,
+            include_glob="*.py",
+        )
+        self.assertEqual(
+            sorted(c.path for c in listed), sorted(fc.path for fc in searched)
+        )
+
+    def test_reports_line_count_and_size(self):
+        listed = busca.list_candidates(search_path="./sample_dir_hello_world/file_1.py")
+        self.assertEqual(len(listed), 1)
+        self.assertEqual(listed[0].path, Path("./sample_dir_hello_world/file_1.py"))
+        self.assertEqual(listed[0].line_count, 9)
+        self.assertEqual(listed[0].size_bytes, 140)
+
+
+class TestConfigFile(unittest.TestCase):
+    def setUp(self):
+        handle, self.config_path = tempfile.mkstemp(suffix=".toml")
+        with os.fdopen(handle, "w") as file:
+            file.write('include_glob = "*.json"\n\n[profile.py]\ninclude_glob = "*.py"\ncount = 2\n')
+
+    def tearDown(self):
+        os.remove(self.config_path)
+
+    def test_config_fills_unset_kwargs(self):
+        listed = busca.list_candidates(
+            search_path="./sample_dir_hello_world", config_path=self.config_path
+        )
+        self.assertEqual(
+            [c.path.name for c in listed], ["sample_json.json"]
+        )
+
+    def test_profile_and_explicit_kwargs(self):
+        results = busca.search(
+            reference_string="x",
+            search_path="./sample_dir_hello_world",
+            config_path=self.config_path,
+            profile="py",
+        )
+        self.assertEqual(len(results), 2)
+        self.assertTrue(all(fc.path.suffix == ".py" for fc in results))
+
+        results = busca.search(
+            reference_string="x",
+            search_path="./sample_dir_hello_world",
+            config_path=self.config_path,
+            profile="py",
+            count=1,
+        )
+        self.assertEqual(len(results), 1)
+
+    def test_profile_without_config_path_raises(self):
+        with self.assertRaises(ValueError):
+            busca.search(
+                reference_string="x",
+                search_path="./sample_dir_hello_world",
+                profile="py",
+            )
+
+
+class TestSearchArchives(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        with zipfile.ZipFile(os.path.join(self.dir.name, "bundle.zip"), "w") as archive:
+            archive.writestr("src/a.py", "print('Hello')\n")
+
+    def tearDown(self):
+        self.dir.cleanup()
+
+    def test_archive_members_are_candidates_only_when_asked(self):
+        self.assertEqual(busca.list_candidates(search_path=self.dir.name), [])
+
+        results = busca.search(
+            reference_string="print('Hello')\n",
+            search_path=self.dir.name,
+            search_archives=True,
+        )
+        self.assertEqual(len(results), 1)
+        self.assertEqual(results[0].path.name, "a.py")
+        self.assertIn("bundle.zip!", str(results[0].path))
+        self.assertEqual(results[0].similarity_ratio, 1.0)
+        self.assertEqual(results[0].content, "print('Hello')\n")
+
+
+class TestEncoding(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        with open(os.path.join(self.dir.name, "latin1.py"), "wb") as file:
+            file.write("café = 1\n".encode("latin-1"))
+
+    def tearDown(self):
+        self.dir.cleanup()
+
+    def test_non_utf8_files_are_skipped_by_default(self):
+        results = busca.search(reference_string="café = 1\n", search_path=self.dir.name)
+        self.assertEqual(results, [])
+
+    def test_fallback_encoding_decodes(self):
+        results = busca.search(
+            reference_string="café = 1\n",
+            search_path=self.dir.name,
+            encoding="latin1",
+        )
+        self.assertEqual(len(results), 1)
+        self.assertEqual(results[0].content, "café = 1\n")
+        self.assertEqual(results[0].encoding, "windows-1252")
+        self.assertEqual(results[0].similarity_ratio, 1.0)
+
+    def test_lossy_replaces_malformed_sequences(self):
+        results = busca.search(
+            reference_string="café = 1\n",
+            search_path=self.dir.name,
+            lossy=True,
+        )
+        self.assertEqual(len(results), 1)
+        self.assertEqual(results[0].content, "caf\ufffd = 1\n")
+
+    def test_unknown_encoding_raises(self):
+        with self.assertRaises(ValueError):
+            busca.search(
+                reference_string="x",
+                search_path=self.dir.name,
+                encoding="no-such-encoding",
+            )
+
+
+class TestBinary(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        self.text = "\0" + "".join(f"line {i}\n" for i in range(200))
+        with open(os.path.join(self.dir.name, "blob.bin"), "wb") as file:
+            file.write(self.text.encode())
+
+    def tearDown(self):
+        self.dir.cleanup()
+
+    def test_binary_files_are_skipped_by_default(self):
+        self.assertEqual(busca.list_candidates(search_path=self.dir.name), [])
+        self.assertEqual(
+            busca.list_candidates(search_path=self.dir.name, binary="report"), []
+        )
+
+    def test_compare_scores_the_bytes(self):
+        results = busca.search(
+            reference_string=self.text,
+            search_path=self.dir.name,
+            binary="compare",
+        )
+        self.assertEqual(len(results), 1)
+        self.assertEqual(results[0].encoding, "binary")
+        self.assertEqual(results[0].content, "")
+        self.assertEqual(results[0].similarity_ratio, 1.0)
+
+    def test_unknown_policy_raises(self):
+        with self.assertRaises(ValueError):
+            busca.search(
+                reference_string="x",
+                search_path=self.dir.name,
+                binary="no-such-policy",
+            )
+
+
+class TestExtractText(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        with open(os.path.join(self.dir.name, "page.html"), "w") as file:
+            file.write("<html><body><p>Hello World</p></body></html>\n")
+
+    def tearDown(self):
+        self.dir.cleanup()
+
+    def test_documents_are_compared_as_markup_by_default(self):
+        results = busca.search(reference_string="Hello World\n", search_path=self.dir.name)
+        self.assertEqual(len(results), 1)
+        self.assertIn("<p>", results[0].content)
+        self.assertLess(results[0].similarity_ratio, 1.0)
+
+    def test_extract_text_compares_the_text(self):
+        results = busca.search(
+            reference_string="Hello World\n",
+            search_path=self.dir.name,
+            extract_text=True,
+        )
+        self.assertEqual(len(results), 1)
+        self.assertNotIn("<p>", results[0].content)
+        self.assertEqual(results[0].similarity_ratio, 1.0)
+
+
+class TestNotebookMarkdown(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        notebook = {
+            "cells": [
+                {"cell_type": "markdown", "metadata": {}, "source": ["# Hello World\n"]},
+                {
+                    "cell_type": "code",
+                    "metadata": {},
+                    "execution_count": 1,
+                    "outputs": [],
+                    "source": ["print('Hello')\n"],
+                },
+            ],
+            "metadata": {},
+            "nbformat": 4,
+            "nbformat_minor": 5,
+        }
+        with open(os.path.join(self.dir.name, "notes.ipynb"), "w") as file:
+            json.dump(notebook, file)
+
+    def tearDown(self):
+        self.dir.cleanup()
+
+    def search(self, **kwargs):
+        results = busca.search(
+            reference_string="# Hello World\n",
+            search_path=self.dir.name,
+            extract_text=True,
+            **kwargs,
+        )
+        self.assertEqual(len(results), 1)
+        return results[0]
+
+    def test_notebook_markdown_compares_markdown_cells(self):
+        code_only = self.search()
+        self.assertEqual(code_only.cell, 1)
+        self.assertNotIn("# Hello World", code_only.content)
+
+        with_markdown = self.search(notebook_markdown=True)
+        self.assertEqual(with_markdown.cell, 0)
+        self.assertIn("# Hello World", with_markdown.content)
+        self.assertGreater(with_markdown.similarity_ratio, code_only.similarity_ratio)
+
+
 class TestSearchDuration(unittest.TestCase):
     def setUp(self):
         with open("./sample_dir_hello_world/file_1.py", "r") as file:
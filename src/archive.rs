@@ -0,0 +1,290 @@
+//! Archive traversal for [`Args::search_archives`]: the members of `.zip`,
+//! `.tar`, `.tar.gz`/`.tgz`, and standalone `.gz` files are read as candidates
+//! of their own, named by a virtual path such as `release.tar.gz!/src/main.py`.
+//! Archives inside archives are not opened.
+
+use crate::{Args, ReadCandidate};
+use flate2::read::GzDecoder;
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Separates an archive's path from the member's path inside it.
+const MEMBER_SEPARATOR: &str = "!/";
+
+/// Members that decompress to more than this are skipped, so a small
+/// compressed file cannot expand without bound.
+pub(crate) const MAX_MEMBER_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    Zip,
+    Tar,
+    TarGz,
+    Gz,
+}
+
+impl Format {
+    fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Format::Zip)
+        } else if name.ends_with(".tar") {
+            Some(Format::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if name.ends_with(".gz") {
+            Some(Format::Gz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether `path` names an archive that [`read_members`] can open.
+pub(crate) fn is_archive(path: &Path) -> bool {
+    Format::of(path).is_some()
+}
+
+/// The regular-file members of the archive at `path` whose virtual paths pass
+/// the globs, in archive order, decoded as files are. Members that cannot be
+/// decoded or are too large are skipped; an archive that cannot be read is
+/// skipped with a warning, like an unreadable file.
+pub(crate) fn read_members(path: &Path, args: &Args) -> Vec<ReadCandidate> {
+    read_matching(path, args, |member_path| {
+        crate::matches_globs(member_path, args)
+    })
+}
+
+/// The member named by the virtual path `path`, decoded as files are. `None`
+/// when `path` does not name a member of an archive on disk.
+pub(crate) fn read_member_at(path: &Path, args: &Args) -> Option<ReadCandidate> {
+    let virtual_path = path.to_str()?;
+    // An archive's own path may contain the separator, so try each split.
+    virtual_path
+        .match_indices(MEMBER_SEPARATOR)
+        .map(|(index, _)| Path::new(&virtual_path[..index]))
+        .find(|archive| is_archive(archive) && archive.is_file())
+        .and_then(|archive| {
+            read_matching(archive, args, |member_path| member_path == path)
+                .into_iter()
+                .next()
+        })
+}
+
+/// The members of the archive at `path` whose virtual paths satisfy `keep`.
+fn read_matching(path: &Path, args: &Args, keep: impl Fn(&Path) -> bool) -> Vec<ReadCandidate> {
+    let Some(format) = Format::of(path) else {
+        return Vec::new();
+    };
+    let mut members = Vec::new();
+    let mut add = |name: &str, reader: &mut dyn Read| {
+        let member_path = member_path(path, name);
+        if keep(&member_path) {
+            let candidate = read_member(reader).and_then(|bytes| {
+                let size_bytes = bytes.len() as u64;
+                args.decoding
+                    .decode(&member_path, bytes)
+                    .map(|decoded| (decoded, size_bytes))
+            });
+            if let Some((decoded, size_bytes)) = candidate {
+                members.push(ReadCandidate {
+                    path: member_path,
+                    decoded,
+                    size_bytes,
+                });
+            }
+        }
+    };
+    let read = File::open(path).and_then(|file| {
+        let file = BufReader::new(file);
+        match format {
+            Format::Zip => read_zip(file, &mut add),
+            Format::Tar => read_tar(file, &mut add),
+            Format::TarGz => read_tar(GzDecoder::new(file), &mut add),
+            Format::Gz => {
+                // A standalone `.gz` holds one file, named by dropping the
+                // extension.
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                add(&name, &mut GzDecoder::new(file));
+                Ok(())
+            }
+        }
+    });
+    if let Err(error) = read {
+        eprintln!("busca: skipping {}: {}", path.display(), error);
+    }
+    members
+}
+
+fn read_zip(
+    file: impl Read + io::Seek,
+    add: &mut dyn FnMut(&str, &mut dyn Read),
+) -> io::Result<()> {
+    let mut archive = zip::ZipArchive::new(file).map_err(io::Error::other)?;
+    for index in 0..archive.len() {
+        let mut member = archive.by_index(index).map_err(io::Error::other)?;
+        if member.is_file() {
+            let name = member.name().to_owned();
+            add(&name, &mut member);
+        }
+    }
+    Ok(())
+}
+
+fn read_tar(file: impl Read, add: &mut dyn FnMut(&str, &mut dyn Read)) -> io::Result<()> {
+    let mut archive = tar::Archive::new(file);
+    for member in archive.entries()? {
+        let mut member = member?;
+        if member.header().entry_type().is_file() {
+            let name = member.path()?.to_string_lossy().into_owned();
+            add(name.trim_start_matches("./"), &mut member);
+        }
+    }
+    Ok(())
+}
+
+/// Reads one member, or `None` when it is over [`MAX_MEMBER_BYTES`] or cannot
+/// be decompressed.
+pub(crate) fn read_member(reader: &mut dyn Read) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader
+        .take(MAX_MEMBER_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .ok()?;
+    if bytes.len() as u64 > MAX_MEMBER_BYTES {
+        return None;
+    }
+    Some(bytes)
+}
+
+/// The virtual path of the member `name` of the archive at `archive`.
+fn member_path(archive: &Path, name: &str) -> PathBuf {
+    let mut path = OsString::from(archive.as_os_str());
+    path.push(MEMBER_SEPARATOR);
+    path.push(name);
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod test_archive {
+    use super::*;
+    use crate::decode::Decoded;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::Write;
+
+    fn args(include_glob: Vec<String>) -> Args {
+        Args::new(
+            String::new(),
+            PathBuf::from("."),
+            None,
+            None,
+            None,
+            include_glob,
+            vec![],
+        )
+        .unwrap()
+    }
+
+    fn tar_bytes(members: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, content) in members {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, name, *content).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn text(content: &str) -> Decoded {
+        Decoded::Text {
+            content: content.to_owned(),
+            encoding: "UTF-8",
+        }
+    }
+
+    fn names(members: &[ReadCandidate]) -> Vec<String> {
+        members
+            .iter()
+            .map(|member| member.path.display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn formats_are_recognised_by_extension() {
+        assert_eq!(Format::of(Path::new("a.zip")), Some(Format::Zip));
+        assert_eq!(Format::of(Path::new("a.tar")), Some(Format::Tar));
+        assert_eq!(Format::of(Path::new("a.TAR.GZ")), Some(Format::TarGz));
+        assert_eq!(Format::of(Path::new("a.tgz")), Some(Format::TarGz));
+        assert_eq!(Format::of(Path::new("a.py.gz")), Some(Format::Gz));
+        assert_eq!(Format::of(Path::new("a.py")), None);
+    }
+
+    #[test]
+    fn members_get_virtual_paths_and_globs_apply_to_them() {
+        let dir = std::env::temp_dir().join(format!("busca_archive_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let tar_gz = dir.join("release.tar.gz");
+        fs::write(
+            &tar_gz,
+            gzip(&tar_bytes(&[
+                ("src/main.py", b"print(1)\n"),
+                ("README.md", b"# x\n"),
+                ("logo.bin", &[0xff, 0xfe, 0x00]),
+            ])),
+        )
+        .unwrap();
+        let gz = dir.join("notes.py.gz");
+        fs::write(&gz, gzip(b"print(2)\n")).unwrap();
+        let zip_path = dir.join("bundle.zip");
+        let mut writer = zip::ZipWriter::new(fs::File::create(&zip_path).unwrap());
+        writer
+            .start_file("pkg/util.py", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"print(3)\n").unwrap();
+        writer.finish().unwrap();
+        let broken = dir.join("broken.zip");
+        fs::write(&broken, b"not a zip").unwrap();
+
+        let all = read_members(&tar_gz, &args(vec![]));
+        let py = read_members(&tar_gz, &args(vec!["*.py".into()]));
+        let gz_members = read_members(&gz, &args(vec![]));
+        let zip_members = read_members(&zip_path, &args(vec![]));
+        let broken_members = read_members(&broken, &args(vec![]));
+        let readme = read_member_at(&member_path(&tar_gz, "README.md"), &args(vec![]));
+        let _ = fs::remove_dir_all(&dir);
+
+        let virtual_path = |archive: &Path, name: &str| format!("{}!/{name}", archive.display());
+        // The member that is not UTF-8 is skipped.
+        assert_eq!(
+            names(&all),
+            vec![
+                virtual_path(&tar_gz, "src/main.py"),
+                virtual_path(&tar_gz, "README.md")
+            ]
+        );
+        assert_eq!(names(&py), vec![virtual_path(&tar_gz, "src/main.py")]);
+        assert_eq!(py[0].decoded, text("print(1)\n"));
+        assert_eq!(names(&gz_members), vec![virtual_path(&gz, "notes.py")]);
+        assert_eq!(
+            zip_members[0].path,
+            PathBuf::from(virtual_path(&zip_path, "pkg/util.py"))
+        );
+        assert_eq!(zip_members[0].decoded, text("print(3)\n"));
+        assert!(broken_members.is_empty());
+        assert_eq!(readme.unwrap().decoded, text("# x\n"));
+    }
+}
@@ -0,0 +1,124 @@
+//! `busca batch`: runs many searches in one process. Each line of stdin is a
+//! JSON query, the body of `busca serve`'s `POST /search` plus an optional
+//! `id`, and each query produces one JSON line on stdout, in input order. All
+//! queries share a snapshot [`ContentCache`], so queries over the same search
+//! root walk it and read its files once (see ADR-0007).
+
+use crate::serve::SearchRequest;
+use crate::{graceful_panic, JsonComparison};
+use busca::ContentCache;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{self, BufRead};
+
+/// One line of output: the query's `id` (`null` when it had none), and either
+/// its ranked results or the reason it failed.
+#[derive(Serialize)]
+struct BatchResult {
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    results: Option<Vec<JsonComparison>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Handles `busca batch`. Exits `0` when every query succeeded and `2` when
+/// any failed; a failed query does not stop the ones after it. Blank lines
+/// are skipped.
+pub fn run() {
+    let cache = ContentCache::snapshot();
+    let mut failed = false;
+    for line in io::stdin().lock().lines() {
+        let line = line.unwrap_or_else(|e| graceful_panic(&format!("cannot read stdin: {e}")));
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = answer(&line, &cache);
+        failed |= result.error.is_some();
+        println!(
+            "{}",
+            serde_json::to_string(&result).expect("JSON serialization of results cannot fail")
+        );
+    }
+    if failed {
+        std::process::exit(2);
+    }
+}
+
+fn answer(line: &str, cache: &ContentCache) -> BatchResult {
+    let mut query: Value = match serde_json::from_str(line) {
+        Ok(query) => query,
+        Err(e) => return failure(Value::Null, format!("invalid query: {e}")),
+    };
+    let id = match query.as_object_mut() {
+        Some(object) => object.remove("id").unwrap_or(Value::Null),
+        None => return failure(Value::Null, "a query must be a JSON object".to_owned()),
+    };
+    let results = serde_json::from_value::<SearchRequest>(query)
+        .map_err(|e| format!("invalid query: {e}"))
+        .and_then(|request| request.run(cache));
+    match results {
+        Ok(results) => BatchResult {
+            id,
+            results: Some(results),
+            error: None,
+        },
+        Err(error) => failure(id, error),
+    }
+}
+
+fn failure(id: Value, error: String) -> BatchResult {
+    BatchResult {
+        id,
+        results: None,
+        error: Some(error),
+    }
+}
+
+#[cfg(test)]
+mod test_batch {
+    use super::*;
+
+    fn answer_json(line: &str, cache: &ContentCache) -> Value {
+        serde_json::to_value(answer(line, cache)).unwrap()
+    }
+
+    #[test]
+    fn each_query_is_answered_with_its_id() {
+        let cache = ContentCache::snapshot();
+        let answered = answer_json(
+            r#"{"id": "q1", "ref_file_path": "sample_dir_hello_world/file_1.py",
+                "search_path": "sample_dir_hello_world", "globs": ["*.py"], "count": 2}"#,
+            &cache,
+        );
+        assert_eq!(answered["id"], "q1");
+        assert_eq!(answered["results"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            answered["results"][0]["path"],
+            "sample_dir_hello_world/file_1.py"
+        );
+        assert!(answered.get("error").is_none());
+
+        let again = answer_json(
+            r#"{"id": 2, "reference": "print(\"Hello World 1\")\n",
+                "search_path": "sample_dir_hello_world", "count": 1}"#,
+            &cache,
+        );
+        assert_eq!(again["id"], 2);
+        assert_eq!(again["results"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn bad_queries_report_an_error_and_keep_their_id() {
+        let cache = ContentCache::snapshot();
+        let missing = answer_json(r#"{"id": 7, "search_path": "missing_dir"}"#, &cache);
+        assert_eq!(missing["id"], 7);
+        assert!(missing["error"].is_string());
+        assert!(missing.get("results").is_none());
+
+        assert_eq!(answer_json("[1]", &cache)["id"], Value::Null);
+        assert!(answer_json("{", &cache)["error"].is_string());
+        let unknown = answer_json(r#"{"id": 1, "reference": "x", "colour": 1}"#, &cache);
+        assert!(unknown["error"].as_str().unwrap().contains("colour"));
+    }
+}
@@ -0,0 +1,516 @@
+//! [`ContentCache`] and [`run_search_cached`]: candidate contents and line
+//! multisets kept in memory between searches, for long-running callers such as
+//! `busca serve`. See ADR-0007. Also [`rank_contents`], which ranks contents
+//! that are not files in the tree with the same scoring.
+
+use crate::chunks::Fingerprint;
+use crate::decode::{self, Decoded, Decoding, NotebookCell};
+use crate::{
+    archive, best_cell, exceeds_line_limit, get_similarity_ratio, is_file_entry, matches_filters,
+    quick_ratio_bound, read_file, read_path_candidates, real_quick_ratio, Args, Error,
+    FileComparison, ReferenceIndex, TopN, UTF_8,
+};
+use rayon::prelude::*;
+use similar::DiffableStr;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::SystemTime;
+use walkdir::WalkDir;
+
+/// Candidate files read by earlier searches: each file's content, line count,
+/// and line multiset, or a binary file's chunk fingerprint. An entry is reused
+/// while the file's modification time and length are unchanged, and reread
+/// otherwise.
+///
+/// The cache is shared by reference, so concurrent searches can use one cache
+/// from several threads; their scoring shares rayon's global pool.
+///
+/// A cache made with [`ContentCache::snapshot`] instead trusts what it has
+/// seen for its whole lifetime, for batch jobs over a tree that does not change
+/// while they run.
+///
+/// # Examples
+///
+/// ```
+/// let cache = busca::ContentCache::new();
+/// let args = busca::Args::new(
+///     "print(\"Hello World\")\n".to_string(),
+///     std::path::PathBuf::from("sample_dir_hello_world"),
+///     None,
+///     Some(3),
+///     None,
+///     vec!["*.py".to_string()],
+///     vec![],
+/// )
+/// .unwrap();
+/// let first = busca::run_search_cached(&args, &cache).unwrap();
+/// assert!(!cache.is_empty());
+/// // The second search reads nothing that has not changed since the first.
+/// assert_eq!(busca::run_search_cached(&args, &cache).unwrap(), first);
+/// ```
+#[derive(Debug, Default)]
+pub struct ContentCache {
+    files: RwLock<HashMap<PathBuf, Arc<CachedFile>>>,
+    /// The files found by the first walk of each search root. Only a snapshot
+    /// cache keeps walks; otherwise every search walks its root again.
+    walks: Option<RwLock<HashMap<PathBuf, Arc<[PathBuf]>>>>,
+}
+
+#[derive(Debug)]
+struct CachedFile {
+    modified: SystemTime,
+    len: u64,
+    /// The options the content was decoded with. A search with other options
+    /// decodes the file again.
+    decoding: Decoding,
+    content: String,
+    encoding: &'static str,
+    /// `str::lines()` count, for `max_file_lines`.
+    line_count: usize,
+    /// The line multiset and token count from `similar`'s tokenizer, for the
+    /// top-N upper bounds.
+    counts: HashMap<Box<str>, u32>,
+    token_len: usize,
+    /// The chunks of a binary candidate, whose `content` is empty.
+    fingerprint: Option<Fingerprint>,
+    /// The compared cells of a notebook, empty for other candidates.
+    cells: Vec<NotebookCell>,
+}
+
+impl AsRef<CachedFile> for CachedFile {
+    fn as_ref(&self) -> &CachedFile {
+        self
+    }
+}
+
+impl CachedFile {
+    fn new(modified: SystemTime, len: u64, decoding: Decoding, decoded: Decoded) -> Self {
+        let (content, encoding, fingerprint, cells) = match decoded {
+            Decoded::Text { content, encoding } => (content, encoding, None, Vec::new()),
+            Decoded::Notebook { content, cells } => (content, UTF_8, None, cells),
+            Decoded::Binary(bytes) => (
+                String::new(),
+                decode::BINARY,
+                Some(Fingerprint::new(&bytes)),
+                Vec::new(),
+            ),
+        };
+        let mut counts: HashMap<Box<str>, u32> = HashMap::new();
+        let mut token_len = 0;
+        for token in content.tokenize_lines() {
+            *counts.entry(token.into()).or_insert(0) += 1;
+            token_len += 1;
+        }
+        Self {
+            modified,
+            len,
+            decoding,
+            line_count: content.lines().count(),
+            content,
+            encoding,
+            counts,
+            token_len,
+            fingerprint,
+            cells,
+        }
+    }
+
+    /// Whether `max_file_lines` leaves this candidate out. It does not apply
+    /// to binary candidates.
+    fn exceeds_line_limit(&self, args: &Args) -> bool {
+        self.fingerprint.is_none() && exceeds_line_limit(self.line_count, args)
+    }
+
+    /// Whether the top-N bounds leave room for this candidate in `heap`.
+    fn may_enter(&self, reference: &ReferenceIndex, heap: &TopN) -> bool {
+        match &self.fingerprint {
+            Some(fingerprint) => {
+                heap.should_compute(real_quick_ratio(reference.bytes.len(), fingerprint.len))
+            }
+            None => {
+                heap.should_compute(real_quick_ratio(reference.len, self.token_len))
+                    && heap.should_compute(quick_ratio_bound(
+                        &reference.counts,
+                        reference.len,
+                        &self.counts,
+                        self.token_len,
+                    ))
+            }
+        }
+    }
+
+    fn score(&self, reference: &ReferenceIndex) -> f32 {
+        match &self.fingerprint {
+            Some(fingerprint) => reference.fingerprint().ratio(fingerprint),
+            None => get_similarity_ratio(reference.text, &self.content),
+        }
+    }
+}
+
+impl ContentCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A cache that reuses the first walk of each search root and the first
+    /// read of each file without checking them again, so searches after the
+    /// first touch the file system only for roots and files not seen before.
+    /// Changes made to the tree after they were seen are not picked up.
+    pub fn snapshot() -> Self {
+        Self {
+            walks: Some(RwLock::default()),
+            ..Self::default()
+        }
+    }
+
+    /// The number of cached files.
+    pub fn len(&self) -> usize {
+        read(&self.files).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The files under `root`, in walk order.
+    fn walk(&self, root: &Path) -> Arc<[PathBuf]> {
+        let Some(walks) = &self.walks else {
+            return walk_files(root);
+        };
+        if let Some(files) = read(walks).get(root) {
+            return Arc::clone(files);
+        }
+        let files = walk_files(root);
+        write(walks).insert(root.to_path_buf(), Arc::clone(&files));
+        files
+    }
+
+    /// The cached entry for `path`, reread if the file changed or was decoded
+    /// with other options. `None` when the file cannot be decoded.
+    fn load(&self, path: &Path, decoding: Decoding) -> Option<Arc<CachedFile>> {
+        if self.walks.is_some() {
+            if let Some(cached) = read(&self.files).get(path) {
+                if cached.decoding == decoding {
+                    return Some(Arc::clone(cached));
+                }
+            }
+        }
+        let metadata = fs::metadata(path).ok();
+        let stamp = metadata
+            .as_ref()
+            .and_then(|metadata| Some((metadata.modified().ok()?, metadata.len())));
+        if let Some((modified, len)) = stamp {
+            if let Some(cached) = read(&self.files).get(path) {
+                if cached.modified == modified && cached.len == len && cached.decoding == decoding {
+                    return Some(Arc::clone(cached));
+                }
+            }
+        }
+
+        let Some(decoded) = read_file(path, decoding) else {
+            // A file that became unreadable must not be scored from a stale
+            // copy.
+            write(&self.files).remove(path);
+            return None;
+        };
+        let Some((modified, len)) = stamp else {
+            // Without a modification time a cached copy could never be
+            // revalidated, so score the file without caching it.
+            return Some(Arc::new(CachedFile::new(
+                SystemTime::UNIX_EPOCH,
+                0,
+                decoding,
+                decoded,
+            )));
+        };
+        let cached = Arc::new(CachedFile::new(modified, len, decoding, decoded));
+        write(&self.files).insert(path.to_path_buf(), Arc::clone(&cached));
+        Some(cached)
+    }
+
+    /// The candidates at `path`: the file, or with
+    /// [`Args::search_archives`] the members of an archive. Archive members
+    /// are not cached, so an archive is reread by every search.
+    fn candidates<'a>(&self, path: &'a Path, args: &Args) -> Vec<(Cow<'a, Path>, Arc<CachedFile>)> {
+        if args.search_archives && archive::is_archive(path) {
+            return read_path_candidates(path.to_path_buf(), args)
+                .into_iter()
+                .map(|member| {
+                    let cached =
+                        CachedFile::new(SystemTime::UNIX_EPOCH, 0, args.decoding, member.decoded);
+                    (Cow::Owned(member.path), Arc::new(cached))
+                })
+                .collect();
+        }
+        if !matches_filters(path, args) {
+            return Vec::new();
+        }
+        self.load(path, args.decoding)
+            .map(|cached| (Cow::Borrowed(path), cached))
+            .into_iter()
+            .collect()
+    }
+
+    /// Drops the entries under `root` that the latest walk of it did not
+    /// find: deleted files, and files that moved.
+    fn prune(&self, root: &Path, walked: &HashSet<&Path>) {
+        write(&self.files)
+            .retain(|path, _| !path.starts_with(root) || walked.contains(path.as_path()));
+    }
+}
+
+fn walk_files(root: &Path) -> Arc<[PathBuf]> {
+    WalkDir::new(root)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(is_file_entry)
+        .map(|dir_entry| dir_entry.into_path())
+        .collect()
+}
+
+// A panic while holding a lock cannot leave an entry half-written, since
+// entries are replaced whole, so a poisoned lock is still usable.
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|e| e.into_inner())
+}
+
+/// [`run_search`](crate::run_search) backed by a [`ContentCache`]. The results
+/// are the same; only files that changed since they were cached are read.
+///
+/// # Errors
+///
+/// As for [`run_search`](crate::run_search).
+pub fn run_search_cached(args: &Args, cache: &ContentCache) -> Result<Vec<FileComparison>, Error> {
+    let files = cache.walk(&args.search_path);
+    let loaded: Vec<(Cow<Path>, Arc<CachedFile>)> = files
+        .par_iter()
+        .flat_map_iter(|path| cache.candidates(path, args))
+        .collect();
+    // Indexed after flattening, so an archive's members keep their order.
+    let loaded: Vec<(usize, Cow<Path>, Arc<CachedFile>)> = loaded
+        .into_iter()
+        .enumerate()
+        .map(|(index, (path, cached))| (index, path, cached))
+        .collect();
+    if cache.walks.is_none() {
+        let walked: HashSet<&Path> = files.iter().map(PathBuf::as_path).collect();
+        cache.prune(&args.search_path, &walked);
+    }
+
+    Ok(rank(args, &loaded))
+}
+
+/// Ranks in-memory candidates against the reference, for contents that are
+/// not files in the tree, such as old versions in version control. `args`
+/// supplies the reference, `max_file_lines`, `count`, and
+/// `min_similarity_ratio`. Its search path, globs, and candidate paths are not
+/// used, since the paths need not exist; filter `candidates` beforehand with
+/// [`Args::matches_path`]. Ties keep the order of `candidates`.
+///
+/// # Examples
+///
+/// ```
+/// let args = busca::Args::new(
+///     "a\nb\n".to_string(),
+///     std::path::PathBuf::from("."),
+///     None,
+///     Some(1),
+///     None,
+///     vec![],
+///     vec![],
+/// )
+/// .unwrap();
+/// let ranked = busca::rank_contents(
+///     &args,
+///     vec![
+///         ("v1:file.txt".into(), "a\nx\n".to_string()),
+///         ("v2:file.txt".into(), "a\nb\n".to_string()),
+///     ],
+/// );
+/// assert_eq!(ranked[0].path, std::path::PathBuf::from("v2:file.txt"));
+/// ```
+pub fn rank_contents(args: &Args, candidates: Vec<(PathBuf, String)>) -> Vec<FileComparison> {
+    let loaded: Vec<(usize, PathBuf, CachedFile)> = candidates
+        .into_par_iter()
+        .enumerate()
+        .map(|(index, (path, content))| {
+            (
+                index,
+                path,
+                CachedFile::new(
+                    SystemTime::UNIX_EPOCH,
+                    0,
+                    Decoding::default(),
+                    Decoded::Text {
+                        content,
+                        encoding: UTF_8,
+                    },
+                ),
+            )
+        })
+        .collect();
+    rank(args, &loaded)
+}
+
+/// Scores loaded candidates as [`run_search`](crate::run_search) scores files:
+/// the line limit, then the floor, and with a count the top-N bounds. The
+/// index orders ties.
+fn rank<P, C>(args: &Args, loaded: &[(usize, P, C)]) -> Vec<FileComparison>
+where
+    P: AsRef<Path> + Sync,
+    C: AsRef<CachedFile> + Sync,
+{
+    let candidates = loaded
+        .par_iter()
+        .filter(|(_, _, cached)| !cached.as_ref().exceeds_line_limit(args));
+    let reference = ReferenceIndex::new(args);
+    let comparison = |path: &P, cached: &C, similarity_ratio| FileComparison {
+        path: path.as_ref().to_path_buf(),
+        similarity_ratio,
+        content: cached.as_ref().content.clone(),
+        encoding: cached.as_ref().encoding,
+        cell: best_cell(
+            reference.text,
+            &cached.as_ref().content,
+            &cached.as_ref().cells,
+        ),
+    };
+
+    match args.count {
+        None => {
+            let mut file_comparisons: Vec<FileComparison> = candidates
+                .filter_map(|(_, path, cached)| {
+                    let similarity_ratio = cached.as_ref().score(&reference);
+                    let floor = args.min_similarity_ratio.unwrap_or(0.0);
+                    (similarity_ratio >= floor).then(|| comparison(path, cached, similarity_ratio))
+                })
+                .collect();
+            file_comparisons.sort_by(|a, b| {
+                b.similarity_ratio
+                    .partial_cmp(&a.similarity_ratio)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+            file_comparisons
+        }
+        Some(count) => {
+            let floor = args.min_similarity_ratio.unwrap_or(0.0);
+            let collected = candidates
+                .fold(
+                    || TopN::new(count, floor),
+                    |mut heap, (index, path, cached)| {
+                        let cached_file = cached.as_ref();
+                        if cached_file.may_enter(&reference, &heap) {
+                            let similarity_ratio = cached_file.score(&reference);
+                            heap.push((*index, 0), comparison(path, cached, similarity_ratio));
+                        }
+                        heap
+                    },
+                )
+                .reduce(|| TopN::new(count, floor), TopN::merge);
+            collected.into_sorted_vec()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_run_search_cached {
+    use super::*;
+    use crate::run_search;
+
+    fn args(search_path: PathBuf, count: Option<usize>, min: Option<f32>) -> Args {
+        Args::new(
+            fs::read_to_string("sample_dir_hello_world/file_1.py").unwrap(),
+            search_path,
+            Some(10_000),
+            count,
+            min,
+            vec![],
+            vec![],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn matches_run_search() {
+        let cache = ContentCache::new();
+        for (count, min) in [(None, None), (Some(2), None), (Some(10), Some(0.3))] {
+            let args = args(PathBuf::from("sample_dir_hello_world"), count, min);
+            let expected = run_search(&args).unwrap();
+            assert_eq!(run_search_cached(&args, &cache).unwrap(), expected);
+            // Again from a warm cache.
+            assert_eq!(run_search_cached(&args, &cache).unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn changed_and_deleted_files_are_reread_and_dropped() {
+        let root = std::env::temp_dir().join(format!("busca_cache_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let file = root.join("a.py");
+        fs::write(&file, "x = 1\n").unwrap();
+        fs::write(root.join("b.py"), "y = 2\n").unwrap();
+
+        let cache = ContentCache::new();
+        let args = Args::new(
+            "x = 1\n".to_owned(),
+            root.clone(),
+            None,
+            None,
+            None,
+            vec![],
+            vec![],
+        )
+        .unwrap();
+        let first = run_search_cached(&args, &cache).unwrap();
+        assert_eq!(first[0].similarity_ratio, 1.0);
+        assert_eq!(cache.len(), 2);
+
+        // A different length invalidates the entry even if the modification
+        // time has a coarse resolution.
+        fs::write(&file, "x = 100\n").unwrap();
+        fs::remove_file(root.join("b.py")).unwrap();
+        let second = run_search_cached(&args, &cache).unwrap();
+        let _ = fs::remove_dir_all(&root);
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].content, "x = 100\n");
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn a_snapshot_reuses_its_walk_and_reads() {
+        let root = std::env::temp_dir().join(format!("busca_snapshot_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("a.py"), "x = 1\n").unwrap();
+
+        let cache = ContentCache::snapshot();
+        let args = Args::new(
+            "x = 1\n".to_owned(),
+            root.clone(),
+            None,
+            Some(5),
+            None,
+            vec![],
+            vec![],
+        )
+        .unwrap();
+        let first = run_search_cached(&args, &cache).unwrap();
+
+        // Neither the new file nor the edit is seen by the same snapshot.
+        fs::write(root.join("a.py"), "x = 100\n").unwrap();
+        fs::write(root.join("b.py"), "x = 1\n").unwrap();
+        let second = run_search_cached(&args, &cache).unwrap();
+        let fresh = run_search_cached(&args, &ContentCache::new()).unwrap();
+        let _ = fs::remove_dir_all(&root);
+        assert_eq!(second, first);
+        assert_eq!(fresh.len(), 2);
+    }
+}
@@ -0,0 +1,319 @@
+//! `busca check`: a duplicate-code gate for CI. Where a search succeeds when it
+//! finds something, `check` fails when it does: any file more similar to the
+//! reference than `--fail-above` is a violation and the exit code is `1` (see
+//! ADR-0006).
+//!
+//! Known duplicates can be accepted in a baseline file committed next to the
+//! code. Each entry records both paths and the SHA-256 of both contents, so a
+//! pair stays accepted only until either file changes. `--update-baseline`
+//! rewrites the file to accept every current violation.
+
+use crate::terminal::PathDisplay;
+use crate::{cli_run_search, graceful_panic, parse_similarity_ratio, same_file, QueryArgs, BINARY};
+use busca::{Args, FileComparison};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Baseline read when `--baseline` is not given, relative to the working
+/// directory. A missing default file is an empty baseline.
+const DEFAULT_BASELINE_PATH: &str = ".busca-baseline.json";
+
+const BASELINE_VERSION: u32 = 1;
+
+#[derive(clap::Args, Debug)]
+pub struct CheckArgs {
+    #[command(flatten)]
+    query: QueryArgs,
+
+    /// Fail when a file's similarity ratio to the reference is above this
+    /// value (in [0.0, 1.0]). The reference file itself is never a violation
+    #[arg(long, value_parser = parse_similarity_ratio)]
+    fail_above: f32,
+
+    /// Baseline file of accepted pairs. Defaults to .busca-baseline.json in the
+    /// current directory, if it exists
+    #[arg(long)]
+    baseline: Option<PathBuf>,
+
+    /// Replace the baseline with every current violation and exit 0
+    #[arg(long)]
+    update_baseline: bool,
+}
+
+/// The committed baseline file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Baseline {
+    version: u32,
+    accepted: Vec<AcceptedPair>,
+}
+
+/// A reference/candidate pair that is allowed to exceed the threshold while
+/// both contents hash to the recorded values. `reference` is `None` for piped
+/// input, which is then identified by its hash alone.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct AcceptedPair {
+    reference: Option<String>,
+    reference_sha256: String,
+    path: String,
+    sha256: String,
+}
+
+/// Handles `busca check`. Baseline entries always record paths as the search
+/// walked them, so `--path-display` only changes what is printed. Exits `0`
+/// when no comparison exceeds the threshold beyond those the baseline accepts,
+/// `1` when one does, and `2` on error.
+pub fn run(check_args: CheckArgs, path_display: Option<PathDisplay>) {
+    let CheckArgs {
+        query,
+        fail_above,
+        baseline,
+        update_baseline,
+    } = check_args;
+
+    let reference_path = query.ref_file_path.clone();
+    let args = into_args(query, fail_above).unwrap_or_else(|e| graceful_panic(&e));
+    let (file_comparisons, _) = cli_run_search(&args).unwrap_or_else(|e| graceful_panic(&e));
+    let violations = violations(&file_comparisons, reference_path.as_deref(), fail_above);
+
+    let reference = reference_path.as_deref().map(display_path);
+    let reference_sha256 = sha256_hex(args.reference_bytes());
+    let pairs: Vec<(AcceptedPair, &FileComparison)> = violations
+        .into_iter()
+        .map(|fc| {
+            let pair = AcceptedPair {
+                reference: reference.clone(),
+                reference_sha256: reference_sha256.clone(),
+                path: display_path(&fc.path),
+                sha256: content_sha256(fc).unwrap_or_else(|e| graceful_panic(&e)),
+            };
+            (pair, fc)
+        })
+        .collect();
+
+    let baseline_path = baseline
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_BASELINE_PATH));
+
+    if update_baseline {
+        let mut accepted: Vec<AcceptedPair> = pairs.into_iter().map(|(pair, _)| pair).collect();
+        accepted.sort();
+        let count = accepted.len();
+        if let Err(e) = write_baseline(&baseline_path, accepted) {
+            graceful_panic(&format!(
+                "cannot write baseline '{}': {e}",
+                baseline_path.display()
+            ));
+        }
+        eprintln!(
+            "Wrote {count} accepted pair{} to '{}'.",
+            if count == 1 { "" } else { "s" },
+            baseline_path.display()
+        );
+        return;
+    }
+
+    let accepted = match read_baseline(&baseline_path, baseline.is_some()) {
+        Ok(accepted) => accepted,
+        Err(e) => graceful_panic(&e),
+    };
+    let (suppressed, failing): (Vec<_>, Vec<_>) = pairs
+        .into_iter()
+        .partition(|(pair, _)| accepted.contains(pair));
+
+    let threshold = format!("{:.1}%", fail_above * 100.0);
+    let suppressed_note = match suppressed.len() {
+        0 => String::new(),
+        n => format!(" ({n} more accepted by the baseline)"),
+    };
+    if failing.is_empty() {
+        eprintln!("No files are more similar than {threshold} to the reference{suppressed_note}.");
+        return;
+    }
+
+    for (_, fc) in &failing {
+        let path = match path_display {
+            Some(path_display) => path_display.apply(&fc.path),
+            None => fc.path.clone(),
+        };
+        println!("{}  {:.1}%", path.display(), fc.similarity_ratio * 100.0);
+    }
+    eprintln!(
+        "{} file{} more similar than {threshold} to the reference{suppressed_note}.",
+        failing.len(),
+        if failing.len() == 1 { " is" } else { "s are" },
+    );
+    std::process::exit(1);
+}
+
+/// Builds [`Args`] that return every comparison at or above the threshold:
+/// `--fail-above` is the floor and there is no count limit, so the config
+/// file's `count` and `min_similarity_ratio` do not apply.
+fn into_args(query: QueryArgs, fail_above: f32) -> Result<Args, String> {
+    let reference_string = query.read_reference()?;
+    let (search_path, config) = query.resolve()?;
+    Args::new(
+        reference_string,
+        search_path,
+        Some(query.max_file_lines(&config)),
+        None,
+        Some(fail_above),
+        query
+            .include_glob
+            .or(config.include_glob)
+            .unwrap_or_default(),
+        query
+            .exclude_glob
+            .or(config.exclude_glob)
+            .unwrap_or_default(),
+    )
+    .map_err(|e| e.to_string())
+    .and_then(|args| query.read.apply(args, query.ref_file_path.as_deref()))
+    .and_then(|args| query.git.restrict(args))
+}
+
+/// The comparisons strictly above the threshold, leaving out the reference
+/// file itself when the search root contains it.
+fn violations<'a>(
+    file_comparisons: &'a [FileComparison],
+    reference_path: Option<&Path>,
+    fail_above: f32,
+) -> Vec<&'a FileComparison> {
+    file_comparisons
+        .iter()
+        .filter(|fc| fc.similarity_ratio > fail_above)
+        .filter(|fc| reference_path.is_none_or(|reference| !same_file(&fc.path, reference)))
+        .collect()
+}
+
+/// Reads the accepted pairs. A missing file is an empty baseline unless it was
+/// named explicitly with `--baseline`.
+fn read_baseline(path: &Path, explicit: bool) -> Result<Vec<AcceptedPair>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && !explicit => return Ok(Vec::new()),
+        Err(e) => return Err(format!("cannot read baseline '{}': {e}", path.display())),
+    };
+    let baseline: Baseline = serde_json::from_str(&text)
+        .map_err(|e| format!("invalid baseline '{}': {e}", path.display()))?;
+    if baseline.version != BASELINE_VERSION {
+        return Err(format!(
+            "unsupported baseline version {} in '{}' (expected {BASELINE_VERSION})",
+            baseline.version,
+            path.display()
+        ));
+    }
+    Ok(baseline.accepted)
+}
+
+fn write_baseline(path: &Path, accepted: Vec<AcceptedPair>) -> io::Result<()> {
+    let baseline = Baseline {
+        version: BASELINE_VERSION,
+        accepted,
+    };
+    let mut text = serde_json::to_string_pretty(&baseline).map_err(io::Error::other)?;
+    text.push('\n');
+    fs::write(path, text)
+}
+
+/// Paths are stored with `/` separators so a baseline written on Windows
+/// matches on Unix.
+fn display_path(path: &Path) -> String {
+    path.display().to_string().replace('\\', "/")
+}
+
+/// The hash a pair records for a candidate: of its text, or of its bytes for
+/// a binary file compared under `--binary compare`, whose content is empty. A
+/// binary archive member is hashed as its whole archive.
+fn content_sha256(fc: &FileComparison) -> Result<String, String> {
+    if fc.encoding != BINARY {
+        return Ok(sha256_hex(fc.content.as_bytes()));
+    }
+    let path = fc.path.to_string_lossy();
+    let bytes = fs::read(&fc.path).or_else(|e| match path.split_once("!/") {
+        Some((archive, _)) => fs::read(archive),
+        None => Err(e),
+    });
+    bytes
+        .map(|bytes| sha256_hex(&bytes))
+        .map_err(|e| format!("cannot read '{}': {e}", fc.path.display()))
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    Sha256::digest(content)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod test_check {
+    use super::*;
+
+    #[test]
+    fn sha256_is_lowercase_hex() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn violations_are_strictly_above_and_skip_the_reference() {
+        let comparisons = vec![
+            FileComparison::new(
+                PathBuf::from("sample_dir_hello_world/file_1.py"),
+                1.0,
+                String::new(),
+            ),
+            FileComparison::new(
+                PathBuf::from("sample_dir_hello_world/file_2.py"),
+                0.9,
+                String::new(),
+            ),
+            FileComparison::new(
+                PathBuf::from("sample_dir_hello_world/file_3.py"),
+                0.8,
+                String::new(),
+            ),
+        ];
+        let paths: Vec<&Path> = violations(
+            &comparisons,
+            Some(Path::new("sample_dir_hello_world/file_1.py")),
+            0.8,
+        )
+        .iter()
+        .map(|fc| fc.path.as_path())
+        .collect();
+        assert_eq!(paths, vec![Path::new("sample_dir_hello_world/file_2.py")]);
+
+        assert_eq!(violations(&comparisons, None, 0.8).len(), 2);
+    }
+
+    #[test]
+    fn missing_default_baseline_is_empty_but_explicit_is_an_error() {
+        let path = Path::new("definitely/not/a/baseline.json");
+        assert_eq!(read_baseline(path, false), Ok(Vec::new()));
+        assert!(read_baseline(path, true).is_err());
+    }
+
+    #[test]
+    fn baseline_round_trips() {
+        let path = std::env::temp_dir().join(format!(
+            "busca_baseline_round_trip_{}.json",
+            std::process::id()
+        ));
+        let pair = AcceptedPair {
+            reference: Some("ref.py".to_owned()),
+            reference_sha256: sha256_hex(b"a"),
+            path: "dup.py".to_owned(),
+            sha256: sha256_hex(b"b"),
+        };
+        write_baseline(&path, vec![pair.clone()]).unwrap();
+        let read = read_baseline(&path, true);
+        let _ = fs::remove_file(&path);
+        assert_eq!(read, Ok(vec![pair]));
+    }
+}
@@ -0,0 +1,160 @@
+//! Byte-level similarity for binary candidates. Each input is cut into
+//! content-defined chunks with a gear rolling hash, so an insertion shifts
+//! only the chunks around it, and the ratio is the share of bytes in chunks
+//! the two inputs have in common.
+
+use std::collections::HashMap;
+
+/// Chunks are at least this long, except the last one.
+const MIN_CHUNK: usize = 256;
+/// A cut needs this many low hash bits to be zero, for chunks of about
+/// `MIN_CHUNK + 2^10` bytes on average.
+const CUT_MASK: u64 = (1 << 10) - 1;
+/// Chunks are cut here at the latest.
+const MAX_CHUNK: usize = 8 * 1024;
+
+/// A random value per byte for the rolling hash, fixed so that chunk
+/// boundaries are the same in every run.
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64
+    let mut table = [0; 256];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut index = 0;
+    while index < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[index] = z ^ (z >> 31);
+        index += 1;
+    }
+    table
+}
+
+/// The chunk multiset of an input: for each distinct chunk, how often it
+/// occurs and its length.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Fingerprint {
+    chunks: HashMap<u64, (u32, u32)>,
+    pub(crate) len: usize,
+}
+
+impl Fingerprint {
+    pub(crate) fn new(bytes: &[u8]) -> Self {
+        let mut chunks: HashMap<u64, (u32, u32)> = HashMap::new();
+        for chunk in Chunks(bytes) {
+            chunks
+                .entry(fnv1a(chunk))
+                .or_insert((0, chunk.len() as u32))
+                .0 += 1;
+        }
+        Self {
+            chunks,
+            len: bytes.len(),
+        }
+    }
+
+    /// Twice the bytes in shared chunks over the total length, in `[0.0, 1.0]`.
+    /// Two empty inputs are identical.
+    pub(crate) fn ratio(&self, other: &Fingerprint) -> f32 {
+        let total = self.len + other.len;
+        if total == 0 {
+            return 1.0;
+        }
+        let (small, large) = match self.chunks.len() <= other.chunks.len() {
+            true => (&self.chunks, &other.chunks),
+            false => (&other.chunks, &self.chunks),
+        };
+        let shared: usize = small
+            .iter()
+            .filter_map(|(hash, &(count, len))| {
+                let &(other_count, _) = large.get(hash)?;
+                Some(count.min(other_count) as usize * len as usize)
+            })
+            .sum();
+        2.0 * shared as f32 / total as f32
+    }
+}
+
+/// Splits its input at content-defined boundaries.
+struct Chunks<'a>(&'a [u8]);
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let limit = self.0.len().min(MAX_CHUNK);
+        let mut end = limit;
+        let mut hash: u64 = 0;
+        for (index, &byte) in self.0[..limit].iter().enumerate() {
+            hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+            if index + 1 >= MIN_CHUNK && hash & CUT_MASK == 0 {
+                end = index + 1;
+                break;
+            }
+        }
+        let (chunk, rest) = self.0.split_at(end);
+        self.0 = rest;
+        Some(chunk)
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+#[cfg(test)]
+mod test_chunks {
+    use super::*;
+
+    /// Deterministic pseudo-random bytes, so chunk boundaries fall inside.
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunks_cover_the_input_within_their_bounds() {
+        let bytes = noise(100_000, 1);
+        let chunks: Vec<&[u8]> = Chunks(&bytes).collect();
+        assert_eq!(chunks.concat(), bytes);
+        assert!(chunks.len() > 10);
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(rest
+            .iter()
+            .all(|chunk| (MIN_CHUNK..=MAX_CHUNK).contains(&chunk.len())));
+        assert!(last.len() <= MAX_CHUNK);
+    }
+
+    #[test]
+    fn an_insertion_changes_only_nearby_chunks() {
+        let original = noise(64 * 1024, 7);
+        let mut patched = original.clone();
+        patched.splice(30_000..30_000, *b"patch");
+
+        let ratio = Fingerprint::new(&original).ratio(&Fingerprint::new(&patched));
+        assert!(ratio > 0.9, "ratio {ratio}");
+        assert!(ratio < 1.0);
+        let unrelated = Fingerprint::new(&noise(64 * 1024, 8));
+        assert_eq!(Fingerprint::new(&original).ratio(&unrelated), 0.0);
+        assert_eq!(
+            Fingerprint::new(&original).ratio(&Fingerprint::new(&original)),
+            1.0
+        );
+        assert_eq!(Fingerprint::new(b"").ratio(&Fingerprint::new(b"")), 1.0);
+    }
+}
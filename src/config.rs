@@ -0,0 +1,342 @@
+//! Search defaults read from `.busca.toml` files.
+//!
+//! A config file holds the same options as the CLI flags, keyed by their
+//! `Args` field names, plus optional `[profile.<name>]` tables that overlay the
+//! top-level values:
+//!
+//! ```toml
+//! max_file_lines = 5000
+//! include_glob = ["*.py"]
+//! exclude_glob = ["**/migrations/*"]
+//!
+//! [profile.strict]
+//! min_similarity_ratio = 0.8
+//! count = 3
+//! ```
+//!
+//! Parsing walks a `toml::Table` by hand instead of deriving `Deserialize`, as
+//! the amendment to ADR-0002 describes.
+
+use crate::Error;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// File name looked up in the search root and each of its ancestors.
+pub const PROJECT_CONFIG_FILE_NAME: &str = ".busca.toml";
+
+/// Search options loaded from one or more config files. Every field is
+/// optional; an unset field leaves the value to a lower-precedence layer or the
+/// built-in default.
+#[non_exhaustive]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub max_file_lines: Option<usize>,
+    pub count: Option<usize>,
+    pub min_similarity_ratio: Option<f32>,
+    pub include_glob: Option<Vec<String>>,
+    pub exclude_glob: Option<Vec<String>>,
+}
+
+impl Config {
+    /// Reads one config file and applies `profile` on top of its top-level
+    /// values.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::ConfigRead`] if the file cannot be read.
+    /// - [`Error::ConfigParse`] if it is not valid TOML, has an unknown key, or a
+    ///   value of the wrong type.
+    /// - [`Error::UnknownProfile`] if `profile` is set and the file has no such
+    ///   `[profile.<name>]` table.
+    pub fn from_path(path: &Path, profile: Option<&str>) -> Result<Self, Error> {
+        let file = ConfigFile::read(path)?;
+        match profile {
+            Some(name) => file
+                .with_profile(name)
+                .ok_or_else(|| Error::UnknownProfile(name.to_owned())),
+            None => Ok(file.base),
+        }
+    }
+
+    /// Loads the user-level config and the nearest `.busca.toml` at or above
+    /// `search_path`, and merges them with the project file taking precedence.
+    /// Within each file the selected `profile` overrides the top-level values,
+    /// so the full order, lowest to highest, is: user top-level, user profile,
+    /// project top-level, project profile.
+    ///
+    /// The user-level file is `$XDG_CONFIG_HOME/busca/config.toml`, falling
+    /// back to `$HOME/.config/busca/config.toml`. Missing files are skipped.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::ConfigRead`] or [`Error::ConfigParse`] if a discovered file
+    ///   cannot be read or parsed.
+    /// - [`Error::UnknownProfile`] if `profile` is set and neither file defines
+    ///   it.
+    pub fn discover(search_path: &Path, profile: Option<&str>) -> Result<Self, Error> {
+        let files = [user_config_path(), find_project_config(search_path)]
+            .into_iter()
+            .flatten()
+            .filter(|path| path.is_file())
+            .map(|path| ConfigFile::read(&path))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut config = Config::default();
+        let mut profile_found = false;
+        for file in files {
+            let layer = match profile {
+                Some(name) => match file.with_profile(name) {
+                    Some(layer) => {
+                        profile_found = true;
+                        layer
+                    }
+                    None => file.base,
+                },
+                None => file.base,
+            };
+            config = config.merge(layer);
+        }
+
+        match profile {
+            Some(name) if !profile_found => Err(Error::UnknownProfile(name.to_owned())),
+            _ => Ok(config),
+        }
+    }
+
+    /// Returns `self` with every field that `overrides` sets replaced. Glob
+    /// lists are replaced wholesale, not concatenated, so a higher layer can
+    /// narrow the globs a lower layer set.
+    pub fn merge(self, overrides: Config) -> Config {
+        Config {
+            max_file_lines: overrides.max_file_lines.or(self.max_file_lines),
+            count: overrides.count.or(self.count),
+            min_similarity_ratio: overrides.min_similarity_ratio.or(self.min_similarity_ratio),
+            include_glob: overrides.include_glob.or(self.include_glob),
+            exclude_glob: overrides.exclude_glob.or(self.exclude_glob),
+        }
+    }
+}
+
+/// A parsed config file: its top-level values and its named profiles.
+struct ConfigFile {
+    base: Config,
+    profiles: Vec<(String, Config)>,
+}
+
+impl ConfigFile {
+    fn read(path: &Path) -> Result<Self, Error> {
+        let text = fs::read_to_string(path).map_err(|source| Error::ConfigRead {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&text).map_err(|message| Error::ConfigParse {
+            path: path.to_path_buf(),
+            message,
+        })
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let table: Table = text.parse().map_err(|e: toml::de::Error| e.to_string())?;
+
+        let mut base = Config::default();
+        let mut profiles = Vec::new();
+        for (key, value) in &table {
+            if key == "profile" {
+                let profile_tables = value
+                    .as_table()
+                    .ok_or("'profile' must be a table of [profile.<name>] tables")?;
+                for (name, profile_value) in profile_tables {
+                    let profile_table = profile_value
+                        .as_table()
+                        .ok_or_else(|| format!("'profile.{name}' must be a table"))?;
+                    let mut profile = Config::default();
+                    for (key, value) in profile_table {
+                        set_field(&mut profile, key, value)
+                            .map_err(|e| format!("in profile '{name}': {e}"))?;
+                    }
+                    profiles.push((name.clone(), profile));
+                }
+            } else {
+                set_field(&mut base, key, value)?;
+            }
+        }
+        Ok(Self { base, profiles })
+    }
+
+    /// The top-level values overlaid with the named profile, or `None` if the
+    /// file does not define that profile.
+    fn with_profile(&self, name: &str) -> Option<Config> {
+        self.profiles
+            .iter()
+            .find(|(profile_name, _)| profile_name == name)
+            .map(|(_, profile)| self.base.clone().merge(profile.clone()))
+    }
+}
+
+fn set_field(config: &mut Config, key: &str, value: &Value) -> Result<(), String> {
+    match key {
+        "max_file_lines" => config.max_file_lines = Some(positive_integer(key, value)?),
+        "count" => config.count = Some(positive_integer(key, value)?),
+        "min_similarity_ratio" => {
+            let ratio = value
+                .as_float()
+                .or_else(|| value.as_integer().map(|i| i as f64))
+                .ok_or_else(|| format!("'{key}' must be a number"))?;
+            config.min_similarity_ratio = Some(ratio as f32);
+        }
+        "include_glob" => config.include_glob = Some(string_list(key, value)?),
+        "exclude_glob" => config.exclude_glob = Some(string_list(key, value)?),
+        _ => return Err(format!("unknown key '{key}'")),
+    }
+    Ok(())
+}
+
+fn positive_integer(key: &str, value: &Value) -> Result<usize, String> {
+    value
+        .as_integer()
+        .filter(|&i| i >= 1)
+        .and_then(|i| usize::try_from(i).ok())
+        .ok_or_else(|| format!("'{key}' must be a positive integer"))
+}
+
+/// Accepts a single string or an array of strings, like the Python glob kwargs.
+fn string_list(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    if let Some(s) = value.as_str() {
+        return Ok(vec![s.to_owned()]);
+    }
+    value
+        .as_array()
+        .and_then(|items| {
+            items
+                .iter()
+                .map(|item| item.as_str().map(str::to_owned))
+                .collect::<Option<Vec<String>>>()
+        })
+        .ok_or_else(|| format!("'{key}' must be a string or an array of strings"))
+}
+
+fn user_config_path() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("busca").join("config.toml"))
+}
+
+/// The nearest `.busca.toml` in `search_path` (or its parent directory, when it
+/// is a file) or any ancestor.
+fn find_project_config(search_path: &Path) -> Option<PathBuf> {
+    let absolute = search_path.canonicalize().ok()?;
+    let start = if absolute.is_file() {
+        absolute.parent()?
+    } else {
+        absolute.as_path()
+    };
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE_NAME))
+        .find(|candidate| candidate.is_file())
+}
+
+#[cfg(test)]
+mod test_config {
+    use super::*;
+
+    #[test]
+    fn parses_top_level_and_profiles() {
+        let file = ConfigFile::parse(
+            r#"
+            max_file_lines = 500
+            include_glob = "*.py"
+            exclude_glob = ["*.json", "*.yml"]
+
+            [profile.strict]
+            min_similarity_ratio = 0.8
+            count = 3
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            file.base,
+            Config {
+                max_file_lines: Some(500),
+                include_glob: Some(vec!["*.py".into()]),
+                exclude_glob: Some(vec!["*.json".into(), "*.yml".into()]),
+                ..Config::default()
+            }
+        );
+        assert_eq!(
+            file.with_profile("strict"),
+            Some(Config {
+                max_file_lines: Some(500),
+                count: Some(3),
+                min_similarity_ratio: Some(0.8),
+                include_glob: Some(vec!["*.py".into()]),
+                exclude_glob: Some(vec!["*.json".into(), "*.yml".into()]),
+            })
+        );
+        assert_eq!(file.with_profile("missing"), None);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_bad_types() {
+        for text in [
+            "max_lines = 5",
+            "count = 0",
+            "count = \"ten\"",
+            "min_similarity_ratio = \"high\"",
+            "include_glob = [1, 2]",
+            "[profile.fast]\ncount = -1",
+            "profile = 3",
+        ] {
+            assert!(ConfigFile::parse(text).is_err(), "accepted {text:?}");
+        }
+    }
+
+    #[test]
+    fn merge_prefers_overrides_and_replaces_globs() {
+        let lower = Config {
+            max_file_lines: Some(100),
+            count: Some(5),
+            include_glob: Some(vec!["*.py".into(), "*.rs".into()]),
+            ..Config::default()
+        };
+        let upper = Config {
+            count: Some(2),
+            include_glob: Some(vec!["*.md".into()]),
+            ..Config::default()
+        };
+        assert_eq!(
+            lower.merge(upper),
+            Config {
+                max_file_lines: Some(100),
+                count: Some(2),
+                include_glob: Some(vec!["*.md".into()]),
+                ..Config::default()
+            }
+        );
+    }
+
+    #[test]
+    fn from_path_reports_unknown_profile() {
+        let path = std::env::temp_dir().join(format!(
+            "busca_config_unknown_profile_{}.toml",
+            std::process::id()
+        ));
+        fs::write(&path, "count = 4\n").unwrap();
+        let result = Config::from_path(&path, Some("nope"));
+        let _ = fs::remove_file(&path);
+        match result {
+            Err(Error::UnknownProfile(name)) => assert_eq!(name, "nope"),
+            other => panic!("expected UnknownProfile, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_path_reports_missing_file() {
+        let result = Config::from_path(Path::new("/definitely/not/a/config.toml"), None);
+        assert!(matches!(result, Err(Error::ConfigRead { .. })));
+    }
+}
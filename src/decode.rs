@@ -0,0 +1,308 @@
+//! Turning candidate bytes into text. A byte order mark selects UTF-8,
+//! UTF-16LE, or UTF-16BE. Without one, a candidate with a NUL byte near its
+//! start is binary and handled by the [`BinaryPolicy`]. Otherwise it is read
+//! as UTF-8, then with the fallback encoding from
+//! [`Args::with_fallback_encoding`](crate::Args::with_fallback_encoding), and
+//! with [`Args::with_lossy_decoding`](crate::Args::with_lossy_decoding)
+//! malformed sequences become U+FFFD instead of dropping the candidate.
+
+use crate::extract;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use std::fmt;
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+
+/// How far into a candidate to look for a NUL byte, as git does.
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/// The encoding name reported for binary candidates.
+pub(crate) const BINARY: &str = "binary";
+
+/// What a search does with binary candidates: files without a byte order mark
+/// that have a NUL byte in their first 8000 bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BinaryPolicy {
+    /// Leave them out. The default.
+    #[default]
+    Skip,
+    /// Leave them out, naming each one on stderr.
+    Report,
+    /// Score them by byte-level similarity with
+    /// [`Args::reference_bytes`](crate::Args::reference_bytes); see
+    /// [`get_chunk_similarity_ratio`](crate::get_chunk_similarity_ratio).
+    Compare,
+}
+
+impl FromStr for BinaryPolicy {
+    type Err = crate::Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "skip" => Ok(BinaryPolicy::Skip),
+            "report" => Ok(BinaryPolicy::Report),
+            "compare" => Ok(BinaryPolicy::Compare),
+            _ => Err(crate::Error::UnknownBinaryPolicy(name.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for BinaryPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinaryPolicy::Skip => "skip",
+            BinaryPolicy::Report => "report",
+            BinaryPolicy::Compare => "compare",
+        })
+    }
+}
+
+/// The decoding options of a search.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct Decoding {
+    pub(crate) fallback: Option<&'static Encoding>,
+    pub(crate) lossy: bool,
+    pub(crate) binary: BinaryPolicy,
+    /// Whether documents are converted to text by [`crate::extract`].
+    pub(crate) extract_text: bool,
+    /// Whether the markdown cells of a notebook are compared along with its
+    /// code cells.
+    pub(crate) notebook_markdown: bool,
+}
+
+/// A candidate's content: decoded text and the name of its encoding, the
+/// compared cells of a Jupyter notebook, or the raw bytes of a binary
+/// candidate kept by [`BinaryPolicy::Compare`].
+#[derive(Debug, PartialEq)]
+pub(crate) enum Decoded {
+    Text {
+        content: String,
+        encoding: &'static str,
+    },
+    #[cfg_attr(not(feature = "ipynb"), allow(dead_code))]
+    Notebook {
+        /// The cell sources one after another, each ending in a newline.
+        content: String,
+        cells: Vec<NotebookCell>,
+    },
+    Binary(Vec<u8>),
+}
+
+/// Where one notebook cell's source sits in [`Decoded::Notebook`]'s content.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct NotebookCell {
+    /// The cell's position among all the notebook's cells.
+    pub(crate) index: usize,
+    /// The byte range of its source.
+    pub(crate) source: Range<usize>,
+}
+
+impl Decoded {
+    /// The text that is scored, or `None` for a binary candidate.
+    pub(crate) fn text(&self) -> Option<&str> {
+        match self {
+            Decoded::Text { content, .. } | Decoded::Notebook { content, .. } => Some(content),
+            Decoded::Binary(_) => None,
+        }
+    }
+}
+
+impl Decoding {
+    /// The content of the candidate at `path`, or `None` when it cannot be
+    /// decoded under these options or is binary and not compared. Documents
+    /// are converted to their text first.
+    pub(crate) fn decode(&self, path: &Path, bytes: Vec<u8>) -> Option<Decoded> {
+        if !self.extract_text {
+            return self.decode_text(path, bytes);
+        }
+        match extract::extract(path, bytes, self) {
+            Ok(extracted) => extracted,
+            Err(bytes) => self.decode_text(path, bytes),
+        }
+    }
+
+    /// [`Decoding::decode`] without text extraction. A byte order mark is not
+    /// part of the text.
+    pub(crate) fn decode_text(&self, path: &Path, bytes: Vec<u8>) -> Option<Decoded> {
+        if let Some((encoding, bom_len)) = Encoding::for_bom(&bytes) {
+            return self.decode_as(encoding, &bytes[bom_len..]);
+        }
+        if self.is_binary(&bytes) {
+            return match self.binary {
+                BinaryPolicy::Skip => None,
+                BinaryPolicy::Report => {
+                    eprintln!("busca: skipping binary file {}", path.display());
+                    None
+                }
+                BinaryPolicy::Compare => Some(Decoded::Binary(bytes)),
+            };
+        }
+        let bytes = match String::from_utf8(bytes) {
+            Ok(content) => {
+                return Some(Decoded::Text {
+                    content,
+                    encoding: UTF_8.name(),
+                })
+            }
+            Err(error) => error.into_bytes(),
+        };
+        self.decode_as(self.fallback.unwrap_or(UTF_8), &bytes)
+    }
+
+    /// The text of a reference file, decoded and extracted as a candidate
+    /// would be. Under
+    /// [`BinaryPolicy::Compare`] the reference is also compared by its bytes,
+    /// so text that does not decode is read lossily rather than refused, and a
+    /// binary reference has empty text. Otherwise a binary reference is `None`.
+    pub(crate) fn decode_reference(&self, path: &Path, bytes: Vec<u8>) -> Option<String> {
+        let decoding = Decoding {
+            lossy: self.lossy || self.binary == BinaryPolicy::Compare,
+            binary: BinaryPolicy::Compare,
+            ..*self
+        };
+        match decoding.decode(path, bytes)? {
+            Decoded::Text { content, .. } | Decoded::Notebook { content, .. } => Some(content),
+            Decoded::Binary(_) => (self.binary == BinaryPolicy::Compare).then(String::new),
+        }
+    }
+
+    /// Whether `bytes` has a NUL byte near its start. Text in a UTF-16
+    /// fallback encoding is full of them, so it is never binary.
+    fn is_binary(&self, bytes: &[u8]) -> bool {
+        let utf16 = self
+            .fallback
+            .is_some_and(|encoding| encoding == UTF_16LE || encoding == UTF_16BE);
+        !utf16 && bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0)
+    }
+
+    fn decode_as(&self, encoding: &'static Encoding, bytes: &[u8]) -> Option<Decoded> {
+        let content = match encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+            Some(text) => text,
+            None if self.lossy => encoding.decode_without_bom_handling(bytes).0,
+            None => return None,
+        };
+        Some(Decoded::Text {
+            content: content.into_owned(),
+            encoding: encoding.name(),
+        })
+    }
+}
+
+/// The canonical name of the encoding a WHATWG label such as `latin1` or
+/// `utf-16le` names, if any.
+pub(crate) fn encoding_for_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+#[cfg(test)]
+mod test_decode {
+    use super::*;
+
+    fn decoded(decoding: Decoding, bytes: &[u8]) -> Option<(String, &'static str)> {
+        match decoding.decode(Path::new("candidate"), bytes.to_vec())? {
+            Decoded::Text { content, encoding } => Some((content, encoding)),
+            other => panic!("decoded as {other:?}"),
+        }
+    }
+
+    #[test]
+    fn byte_order_marks_pick_the_encoding() {
+        let plain = Decoding::default();
+        assert_eq!(
+            decoded(plain, b"\xEF\xBB\xBFa\n"),
+            Some(("a\n".to_owned(), "UTF-8"))
+        );
+        assert_eq!(
+            decoded(plain, b"\xFF\xFEa\0\n\0"),
+            Some(("a\n".to_owned(), "UTF-16LE"))
+        );
+        assert_eq!(
+            decoded(plain, b"\xFE\xFF\0a\0\n"),
+            Some(("a\n".to_owned(), "UTF-16BE"))
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_needs_a_fallback_or_lossy_mode() {
+        let latin1 = b"caf\xE9\n";
+        assert_eq!(decoded(Decoding::default(), latin1), None);
+
+        let fallback = Decoding {
+            fallback: encoding_for_label("latin1"),
+            ..Decoding::default()
+        };
+        assert_eq!(
+            decoded(fallback, latin1),
+            Some(("caf\u{e9}\n".to_owned(), "windows-1252"))
+        );
+        // Valid UTF-8 is never reinterpreted by the fallback.
+        assert_eq!(
+            decoded(fallback, "caf\u{e9}\n".as_bytes()),
+            Some(("caf\u{e9}\n".to_owned(), "UTF-8"))
+        );
+
+        let lossy = Decoding {
+            lossy: true,
+            ..Decoding::default()
+        };
+        assert_eq!(
+            decoded(lossy, latin1),
+            Some(("caf\u{fffd}\n".to_owned(), "UTF-8"))
+        );
+    }
+
+    #[test]
+    fn nul_bytes_make_a_candidate_binary() {
+        let bytes = b"\x7fELF\x02\x01\x01\0\0\0";
+        let decode = |binary| {
+            Decoding {
+                binary,
+                ..Decoding::default()
+            }
+            .decode(Path::new("a.out"), bytes.to_vec())
+        };
+        assert_eq!(decode(BinaryPolicy::Skip), None);
+        assert_eq!(decode(BinaryPolicy::Report), None);
+        assert_eq!(
+            decode(BinaryPolicy::Compare),
+            Some(Decoded::Binary(bytes.to_vec()))
+        );
+        // A NUL past the sniffed prefix does not count.
+        let mut late = vec![b'a'; BINARY_SNIFF_LEN];
+        late.push(0);
+        assert!(decoded(Decoding::default(), &late).is_some());
+        // Nor does one in UTF-16 text, with a byte order mark or a fallback.
+        assert!(decoded(Decoding::default(), b"\xFF\xFEa\0").is_some());
+        let utf16 = Decoding {
+            fallback: encoding_for_label("utf-16le"),
+            ..Decoding::default()
+        };
+        assert_eq!(
+            decoded(utf16, b"\xE9\0"),
+            Some(("\u{e9}".to_owned(), "UTF-16LE"))
+        );
+    }
+
+    #[test]
+    fn binary_policies_parse_and_display_by_name() {
+        for policy in [
+            BinaryPolicy::Skip,
+            BinaryPolicy::Report,
+            BinaryPolicy::Compare,
+        ] {
+            assert_eq!(policy.to_string().parse::<BinaryPolicy>().unwrap(), policy);
+        }
+        assert!("sometimes".parse::<BinaryPolicy>().is_err());
+    }
+
+    #[test]
+    fn labels_are_resolved_to_canonical_names() {
+        assert_eq!(encoding_for_label("UTF-16LE").unwrap().name(), "UTF-16LE");
+        assert_eq!(
+            encoding_for_label(" cp1252 ").unwrap().name(),
+            "windows-1252"
+        );
+        assert!(encoding_for_label("klingon").is_none());
+    }
+}
@@ -0,0 +1,490 @@
+//! Diff rendering for the terminal and for pipes: the colored inline diff of
+//! the interactive picker, a unified diff that `patch` and `delta` accept, and
+//! a two-column side-by-side view. Every renderer returns a `String` styled
+//! through `console`, which drops the escape codes when colors are disabled.
+
+use console::{measure_text_width, style, truncate_str, Style, Term};
+use similar::{ChangeTag, DiffTag, InlineChange, TextDiff};
+use std::fmt::{self, Write};
+use std::ops::Range;
+
+/// Lines of unchanged context kept around each hunk unless `--context-lines`
+/// says otherwise.
+pub const DEFAULT_CONTEXT_LINES: usize = 3;
+
+/// Used for the side-by-side view when the terminal width is unknown.
+const FALLBACK_WIDTH: usize = 120;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum DiffFormat {
+    /// Line-numbered changes with word-level highlights, as in the picker
+    #[default]
+    Inline,
+    /// A unified diff with `---`/`+++` headers, for `patch` or `delta`
+    Unified,
+    /// The reference and the candidate in two columns, sized to the terminal
+    SideBySide,
+}
+
+/// How diffs are drawn, shared by `--diff`, the picker, `busca compare`, and
+/// `busca diff`.
+#[derive(clap::Args, Debug, Clone, Copy, PartialEq)]
+pub struct DiffOutputArgs {
+    /// Layout of printed diffs
+    #[arg(long, value_enum, default_value = "inline")]
+    pub diff_format: DiffFormat,
+
+    /// Lines of unchanged context around each hunk
+    #[arg(long, default_value_t = DEFAULT_CONTEXT_LINES)]
+    pub context_lines: usize,
+}
+
+impl Default for DiffOutputArgs {
+    fn default() -> Self {
+        Self {
+            diff_format: DiffFormat::Inline,
+            context_lines: DEFAULT_CONTEXT_LINES,
+        }
+    }
+}
+
+/// Which ranked results `--diff` prints.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DiffSelection {
+    Top,
+    All,
+    Count(usize),
+}
+
+impl DiffSelection {
+    /// How many of `available` ranked results to print.
+    pub fn take(self, available: usize) -> usize {
+        match self {
+            DiffSelection::Top => available.min(1),
+            DiffSelection::All => available,
+            DiffSelection::Count(n) => available.min(n),
+        }
+    }
+}
+
+pub fn parse_diff_selection(s: &str) -> Result<DiffSelection, String> {
+    match s {
+        "top" => Ok(DiffSelection::Top),
+        "all" => Ok(DiffSelection::All),
+        _ => match s.parse::<usize>() {
+            Ok(0) => Err("must be at least 1".to_owned()),
+            Ok(n) => Ok(DiffSelection::Count(n)),
+            Err(_) => Err(format!("expected 'top', 'all', or a count, got '{s}'")),
+        },
+    }
+}
+
+/// Renders the diff of the reference against one candidate. The labels name
+/// the two sides in the unified and side-by-side headers.
+pub fn render(
+    options: DiffOutputArgs,
+    reference_label: &str,
+    candidate_label: &str,
+    reference_string: &str,
+    candidate_content: &str,
+) -> String {
+    let diff = TextDiff::from_lines(reference_string, candidate_content);
+    match options.diff_format {
+        DiffFormat::Inline => inline(&diff, options.context_lines, usize::MAX),
+        DiffFormat::Unified => unified(
+            &diff,
+            options.context_lines,
+            reference_label,
+            candidate_label,
+        ),
+        DiffFormat::SideBySide => side_by_side(
+            &diff,
+            options.context_lines,
+            reference_label,
+            candidate_label,
+            terminal_width(),
+        ),
+    }
+}
+
+/// The runs of lines the reference and the candidate share, in order, as
+/// 0-based, end-exclusive spans of the reference and the candidate.
+pub fn shared_runs(
+    reference_string: &str,
+    candidate_content: &str,
+) -> Vec<(Range<usize>, Range<usize>)> {
+    TextDiff::from_lines(reference_string, candidate_content)
+        .ops()
+        .iter()
+        .filter(|op| op.tag() == DiffTag::Equal)
+        .map(|op| (op.old_range(), op.new_range()))
+        .collect()
+}
+
+/// The first differing hunk of the inline diff, cut to `max_lines` lines, for
+/// the picker's preview.
+pub fn first_hunk(
+    context_lines: usize,
+    reference_string: &str,
+    candidate_content: &str,
+    max_lines: usize,
+) -> String {
+    let diff = TextDiff::from_lines(reference_string, candidate_content);
+    let hunk = inline(&diff, context_lines, 1);
+    let line_count = hunk.lines().count();
+    if line_count <= max_lines {
+        return hunk;
+    }
+    let mut out: String = hunk.split_inclusive('\n').take(max_lines).collect();
+    let hidden = line_count - max_lines;
+    let _ = writeln!(
+        out,
+        "{}",
+        style(format!(
+            "… {hidden} more line{}",
+            if hidden == 1 { "" } else { "s" }
+        ))
+        .dim()
+    );
+    out
+}
+
+/// The inline diff, limited to the first `max_groups` hunks.
+fn inline(diff: &TextDiff<'_, '_, str>, context_lines: usize, max_groups: usize) -> String {
+    let mut grouped_operations = diff.grouped_ops(context_lines);
+    grouped_operations.truncate(max_groups);
+
+    if grouped_operations.is_empty() {
+        return "The sequences are identical.\n".to_owned();
+    }
+
+    let mut out = String::new();
+    for (idx, group) in grouped_operations.iter().enumerate() {
+        if idx > 0 {
+            let _ = writeln!(out, "{:-^1$}", "-", 80);
+        }
+        for op in group {
+            for change in diff.iter_inline_changes(op) {
+                let (sign, s) = match change.tag() {
+                    ChangeTag::Delete => ("-", Style::new().red()),
+                    ChangeTag::Insert => ("+", Style::new().green()),
+                    ChangeTag::Equal => (" ", Style::new().dim()),
+                };
+                let _ = write!(
+                    out,
+                    "{} {} {} |",
+                    style(Line(change.old_index())).dim(),
+                    style(Line(change.new_index())).dim(),
+                    s.apply_to(sign).bold(),
+                );
+                for (emphasized, value) in change.iter_strings_lossy() {
+                    if emphasized {
+                        let _ = write!(out, "{}", s.apply_to(value).underlined().on_black());
+                    } else {
+                        let _ = write!(out, "{}", s.apply_to(value));
+                    }
+                }
+                if change.missing_newline() {
+                    out.push('\n');
+                }
+            }
+        }
+    }
+    out
+}
+
+/// A unified diff with `---`/`+++` file headers and `@@` hunk headers. Empty
+/// when the sequences are identical, as from `diff -u`, so a concatenation of
+/// several is still a valid multi-file patch.
+fn unified(
+    diff: &TextDiff<'_, '_, str>,
+    context_lines: usize,
+    reference_label: &str,
+    candidate_label: &str,
+) -> String {
+    let mut unified_diff = diff.unified_diff();
+    unified_diff.context_radius(context_lines);
+
+    let mut out = String::new();
+    for (index, hunk) in unified_diff.iter_hunks().enumerate() {
+        if index == 0 {
+            let _ = writeln!(out, "{}", style(format!("--- {reference_label}")).bold());
+            let _ = writeln!(out, "{}", style(format!("+++ {candidate_label}")).bold());
+        }
+        let hunk = hunk.to_string();
+        let mut lines = hunk.lines();
+        if let Some(header) = lines.next() {
+            let _ = writeln!(out, "{}", style(header).cyan());
+        }
+        for line in lines {
+            let styled = match line.as_bytes().first() {
+                Some(b'-') => style(line).red(),
+                Some(b'+') => style(line).green(),
+                _ => style(line),
+            };
+            let _ = writeln!(out, "{styled}");
+        }
+    }
+    out
+}
+
+fn side_by_side(
+    diff: &TextDiff<'_, '_, str>,
+    context_lines: usize,
+    reference_label: &str,
+    candidate_label: &str,
+    width: usize,
+) -> String {
+    let groups = diff.grouped_ops(context_lines);
+    if groups.is_empty() {
+        return "The sequences are identical.\n".to_owned();
+    }
+
+    // Each side is a 5-column line number, a space, and the text; the sides
+    // are separated by " │ ".
+    let column = width.saturating_sub(3) / 2;
+    let text_width = column.saturating_sub(6).max(8);
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{} │ {}",
+        style(pad(&truncate_str(reference_label, column, "…"), column)).bold(),
+        style(truncate_str(candidate_label, column, "…")).bold(),
+    );
+    for (group_index, group) in groups.iter().enumerate() {
+        if group_index > 0 {
+            let _ = writeln!(out, "{}", style("┄".repeat(column * 2 + 3)).dim());
+        }
+        for op in group {
+            for row in pair_rows(diff.iter_inline_changes(op)) {
+                push_cell(&mut out, row.old.as_ref(), text_width);
+                out.push_str(" │ ");
+                push_cell(&mut out, row.new.as_ref(), text_width);
+                // Keep lines free of trailing padding.
+                let trimmed_len = out.trim_end_matches(' ').len();
+                out.truncate(trimmed_len);
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+fn push_cell(out: &mut String, side: Option<&Side>, text_width: usize) {
+    let Some(side) = side else {
+        out.push_str(&" ".repeat(text_width + 6));
+        return;
+    };
+    let s = match side.tag {
+        ChangeTag::Delete => Style::new().red(),
+        ChangeTag::Insert => Style::new().green(),
+        ChangeTag::Equal => Style::new().dim(),
+    };
+    let _ = write!(out, "{} ", style(format!("{:>5}", side.index + 1)).dim());
+
+    let mut remaining = text_width;
+    for (emphasized, value) in &side.segments {
+        if remaining == 0 {
+            break;
+        }
+        let value = value.replace('\t', "    ");
+        let value_width = measure_text_width(&value);
+        let text = if value_width > remaining {
+            truncate_str(&value, remaining, "…").into_owned()
+        } else {
+            value
+        };
+        remaining = remaining.saturating_sub(measure_text_width(&text));
+        if *emphasized && side.tag != ChangeTag::Equal {
+            let _ = write!(out, "{}", s.apply_to(text).underlined());
+        } else {
+            let _ = write!(out, "{}", s.apply_to(text));
+        }
+    }
+    out.push_str(&" ".repeat(remaining));
+}
+
+fn pad(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(measure_text_width(text));
+    format!("{text}{}", " ".repeat(padding))
+}
+
+fn terminal_width() -> usize {
+    Term::stdout()
+        .size_checked()
+        .map(|(_, columns)| usize::from(columns))
+        .unwrap_or(FALLBACK_WIDTH)
+}
+
+/// One row of a side-by-side diff. A side is `None` where the other side
+/// inserted or deleted a line.
+pub struct Row {
+    pub old: Option<Side>,
+    pub new: Option<Side>,
+}
+
+/// One line of one side: its 0-based line index, its change tag, and its text
+/// split into `(emphasized, text)` runs with the line ending removed.
+pub struct Side {
+    pub index: usize,
+    pub tag: ChangeTag,
+    pub segments: Vec<(bool, String)>,
+}
+
+impl Side {
+    fn new(index: usize, change: &InlineChange<'_, str>) -> Self {
+        let mut segments: Vec<(bool, String)> = change
+            .iter_strings_lossy()
+            .map(|(emphasized, value)| (emphasized, value.into_owned()))
+            .collect();
+        if let Some((_, last)) = segments.last_mut() {
+            let trimmed_len = last.trim_end_matches(['\n', '\r']).len();
+            last.truncate(trimmed_len);
+        }
+        Self {
+            index,
+            tag: change.tag(),
+            segments,
+        }
+    }
+}
+
+/// Lays one diff op's changes out as rows. Equal lines fill both sides; a run
+/// of deletions is paired line by line with the insertions that follow it, so
+/// a replaced line sits next to its replacement.
+pub fn pair_rows<'a>(changes: impl Iterator<Item = InlineChange<'a, str>>) -> Vec<Row> {
+    let mut rows: Vec<Row> = Vec::new();
+    let mut deletions: Vec<Side> = Vec::new();
+    let mut insertions: Vec<Side> = Vec::new();
+
+    let flush = |rows: &mut Vec<Row>, deletions: &mut Vec<Side>, insertions: &mut Vec<Side>| {
+        let mut olds = deletions.drain(..);
+        let mut news = insertions.drain(..);
+        loop {
+            let (old, new) = (olds.next(), news.next());
+            if old.is_none() && new.is_none() {
+                break;
+            }
+            rows.push(Row { old, new });
+        }
+    };
+
+    for change in changes {
+        let old_index = change.old_index().unwrap_or_default();
+        let new_index = change.new_index().unwrap_or_default();
+        match change.tag() {
+            ChangeTag::Equal => {
+                flush(&mut rows, &mut deletions, &mut insertions);
+                rows.push(Row {
+                    old: Some(Side::new(old_index, &change)),
+                    new: Some(Side::new(new_index, &change)),
+                });
+            }
+            ChangeTag::Delete => {
+                if !insertions.is_empty() {
+                    flush(&mut rows, &mut deletions, &mut insertions);
+                }
+                deletions.push(Side::new(old_index, &change));
+            }
+            ChangeTag::Insert => insertions.push(Side::new(new_index, &change)),
+        }
+    }
+    flush(&mut rows, &mut deletions, &mut insertions);
+    rows
+}
+
+struct Line(Option<usize>);
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            None => write!(f, "    "),
+            Some(idx) => write!(f, "{:<4}", idx + 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_diff {
+    use super::*;
+
+    #[test]
+    fn parses_selections() {
+        assert_eq!(parse_diff_selection("top"), Ok(DiffSelection::Top));
+        assert_eq!(parse_diff_selection("all"), Ok(DiffSelection::All));
+        assert_eq!(parse_diff_selection("3"), Ok(DiffSelection::Count(3)));
+        assert!(parse_diff_selection("0").is_err());
+        assert!(parse_diff_selection("first").is_err());
+        assert_eq!(DiffSelection::Count(3).take(2), 2);
+        assert_eq!(DiffSelection::Top.take(0), 0);
+    }
+
+    #[test]
+    fn replaced_lines_sit_side_by_side() {
+        let diff = TextDiff::from_lines("a\nb\nc\n", "a\nB\nc\nd\n");
+        let rows: Vec<(Option<usize>, Option<usize>)> = diff
+            .ops()
+            .iter()
+            .flat_map(|op| pair_rows(diff.iter_inline_changes(op)))
+            .map(|row| (row.old.map(|s| s.index), row.new.map(|s| s.index)))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (Some(0), Some(0)),
+                (Some(1), Some(1)),
+                (Some(2), Some(2)),
+                (None, Some(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn unified_has_file_and_hunk_headers() {
+        let options = DiffOutputArgs {
+            diff_format: DiffFormat::Unified,
+            context_lines: 1,
+        };
+        let out = render(
+            options,
+            "a.py",
+            "b.py",
+            "1\n2\n3\n4\n5\n",
+            "1\n2\nX\n4\n5\n",
+        );
+        assert_eq!(out, "--- a.py\n+++ b.py\n@@ -2,3 +2,3 @@\n 2\n-3\n+X\n 4\n");
+        assert_eq!(render(options, "a.py", "b.py", "1\n", "1\n"), "");
+    }
+
+    #[test]
+    fn context_lines_bound_the_hunks() {
+        let diff = TextDiff::from_lines("1\n2\n3\n4\n5\n", "1\n2\nX\n4\n5\n");
+        assert_eq!(inline(&diff, 0, usize::MAX).lines().count(), 2);
+        assert_eq!(inline(&diff, 2, usize::MAX).lines().count(), 6);
+    }
+
+    #[test]
+    fn first_hunk_is_cut_to_the_line_limit() {
+        let reference = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n";
+        let candidate = "X\n2\n3\n4\n5\n6\n7\n8\n9\nY\n";
+        let preview = first_hunk(1, reference, candidate, 10);
+        assert_eq!(preview.lines().count(), 3, "{preview}");
+        assert!(!preview.contains('Y'));
+
+        let cut = first_hunk(1, reference, candidate, 2);
+        assert_eq!(cut.lines().count(), 3);
+        assert!(cut.ends_with("… 1 more line\n"), "{cut}");
+    }
+
+    #[test]
+    fn side_by_side_truncates_to_the_width() {
+        let diff = TextDiff::from_lines("short\n", "a much longer replacement line\n");
+        let out = side_by_side(&diff, 3, "ref.py", "cand.py", 40);
+        for line in out.lines() {
+            assert!(measure_text_width(line) <= 40, "{line:?}");
+        }
+        assert!(out.contains("    1 short"));
+        assert!(out.contains('…'));
+    }
+}
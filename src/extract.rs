@@ -0,0 +1,632 @@
+//! Text extraction for document candidates. A candidate whose extension names a
+//! document format is converted to plain text before it is scored, so markup
+//! and container bytes do not swamp the line diff, and
+//! [`FileComparison::content`](crate::FileComparison::content) holds the text.
+//! Each format is behind a cargo feature: `html` (`.html`, `.htm`, `.xhtml`),
+//! `docx`, and `ipynb` are on by default, and `pdf` is opt-in since it pulls in
+//! a PDF parser. Extraction is off unless
+//! [`Args::with_text_extraction`](crate::Args::with_text_extraction) turns it
+//! on, and a reference file read with
+//! [`Args::with_reference_file`](crate::Args::with_reference_file) is
+//! extracted the same way.
+//!
+//! Notebooks are parsed into a `serde_json::Value` and walked by hand, as the
+//! amendment to ADR-0002 describes.
+
+use crate::decode::{Decoded, Decoding};
+use std::path::Path;
+
+/// Converts the raw bytes of one document format to the text that is scored.
+trait Extractor: Sync {
+    /// The lowercase extensions, without the dot, of the files it converts.
+    fn extensions(&self) -> &'static [&'static str];
+
+    /// The text of `bytes`, or `None` when the document cannot be read.
+    /// `decoding` applies to formats that are themselves text.
+    fn extract(&self, path: &Path, bytes: Vec<u8>, decoding: &Decoding) -> Option<Decoded>;
+}
+
+/// The extractors compiled in. Extensions must not overlap.
+const EXTRACTORS: &[&dyn Extractor] = &[
+    #[cfg(feature = "html")]
+    &Html,
+    #[cfg(feature = "docx")]
+    &Docx,
+    #[cfg(feature = "pdf")]
+    &Pdf,
+    #[cfg(feature = "ipynb")]
+    &Notebook,
+];
+
+/// The text of the document at `path`, `Ok(None)` when it cannot be read, or
+/// the bytes back when no extractor handles its extension, to be decoded as
+/// usual.
+pub(crate) fn extract(
+    path: &Path,
+    bytes: Vec<u8>,
+    decoding: &Decoding,
+) -> Result<Option<Decoded>, Vec<u8>> {
+    let Some(extension) = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+    else {
+        return Err(bytes);
+    };
+    match EXTRACTORS
+        .iter()
+        .find(|extractor| extractor.extensions().contains(&extension.as_str()))
+    {
+        Some(extractor) => Ok(extractor.extract(path, bytes, decoding)),
+        None => Err(bytes),
+    }
+}
+
+#[cfg(feature = "html")]
+struct Html;
+
+#[cfg(feature = "html")]
+impl Extractor for Html {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["html", "htm", "xhtml"]
+    }
+
+    fn extract(&self, path: &Path, bytes: Vec<u8>, decoding: &Decoding) -> Option<Decoded> {
+        match decoding.decode_text(path, bytes)? {
+            Decoded::Text { content, encoding } => Some(Decoded::Text {
+                content: markup::html_text(&content),
+                encoding,
+            }),
+            other => Some(other),
+        }
+    }
+}
+
+#[cfg(feature = "docx")]
+struct Docx;
+
+#[cfg(feature = "docx")]
+impl Extractor for Docx {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["docx"]
+    }
+
+    /// The paragraphs of the main document part, `word/document.xml`. `None`
+    /// when that part decompresses to more than an archive member may, as
+    /// for [`MAX_MEMBER_BYTES`](crate::archive::MAX_MEMBER_BYTES).
+    fn extract(&self, _path: &Path, bytes: Vec<u8>, _decoding: &Decoding) -> Option<Decoded> {
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).ok()?;
+        let xml = crate::archive::read_member(&mut archive.by_name("word/document.xml").ok()?)?;
+        let xml = String::from_utf8(xml).ok()?;
+        Some(Decoded::Text {
+            content: markup::docx_text(&xml),
+            encoding: encoding_rs::UTF_8.name(),
+        })
+    }
+}
+
+#[cfg(feature = "pdf")]
+struct Pdf;
+
+#[cfg(feature = "pdf")]
+impl Extractor for Pdf {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["pdf"]
+    }
+
+    fn extract(&self, path: &Path, bytes: Vec<u8>, _decoding: &Decoding) -> Option<Decoded> {
+        // The PDF parser panics on some malformed files; one bad document must
+        // not abort the search.
+        let text = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(&bytes));
+        match text {
+            Ok(Ok(text)) => Some(Decoded::Text {
+                content: markup::tidy_lines(text.lines().map(str::trim)),
+                encoding: encoding_rs::UTF_8.name(),
+            }),
+            Ok(Err(error)) => {
+                eprintln!("busca: skipping {}: {}", path.display(), error);
+                None
+            }
+            Err(_) => {
+                eprintln!("busca: skipping {}: cannot read PDF", path.display());
+                None
+            }
+        }
+    }
+}
+
+#[cfg(feature = "ipynb")]
+struct Notebook;
+
+#[cfg(feature = "ipynb")]
+impl Extractor for Notebook {
+    fn extensions(&self) -> &'static [&'static str] {
+        &["ipynb"]
+    }
+
+    /// The sources of the code cells, and of the markdown cells when
+    /// `decoding` asks for them, in notebook order. Outputs and metadata are
+    /// left out. `None` for a file that is not an nbformat 4 notebook.
+    fn extract(&self, _path: &Path, bytes: Vec<u8>, decoding: &Decoding) -> Option<Decoded> {
+        use crate::decode::NotebookCell;
+        use serde_json::Value;
+
+        let notebook: Value = serde_json::from_slice(&bytes).ok()?;
+        let mut content = String::new();
+        let mut cells = Vec::new();
+        for (index, cell) in notebook.get("cells")?.as_array()?.iter().enumerate() {
+            let compared = match cell.get("cell_type").and_then(Value::as_str) {
+                Some("code") => true,
+                Some("markdown") => decoding.notebook_markdown,
+                _ => false,
+            };
+            if !compared {
+                continue;
+            }
+            let start = content.len();
+            // nbformat stores a source as one string or as a list of lines.
+            match cell.get("source") {
+                Some(Value::String(source)) => content.push_str(source),
+                Some(Value::Array(lines)) => lines
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .for_each(|line| content.push_str(line)),
+                _ => {}
+            }
+            if content.len() == start {
+                continue;
+            }
+            if !content.ends_with('\n') {
+                content.push('\n');
+            }
+            cells.push(NotebookCell {
+                index,
+                source: start..content.len(),
+            });
+        }
+        Some(Decoded::Notebook { content, cells })
+    }
+}
+
+/// Markup scanning shared by the HTML and DOCX extractors. It is a tolerant
+/// tokenizer rather than a parser: it never fails, and unclosed or stray markup
+/// degrades into text.
+#[cfg(any(feature = "html", feature = "docx", feature = "pdf"))]
+mod markup {
+    /// A piece of a markup document.
+    #[cfg(any(feature = "html", feature = "docx"))]
+    #[derive(Debug, PartialEq)]
+    enum Token<'a> {
+        Text(&'a str),
+        /// A start, end, or empty-element tag, by lowercase name.
+        Tag {
+            name: String,
+            closing: bool,
+        },
+    }
+
+    /// Splits `markup` into text and tags. Comments, doctypes, and processing
+    /// instructions are dropped, and the contents of `script` and `style`
+    /// elements are skipped whole.
+    #[cfg(any(feature = "html", feature = "docx"))]
+    fn tokens(markup: &str) -> Vec<Token<'_>> {
+        let mut tokens = Vec::new();
+        let mut rest = markup;
+        while let Some(start) = rest.find('<') {
+            if start > 0 {
+                tokens.push(Token::Text(&rest[..start]));
+            }
+            rest = &rest[start..];
+            if let Some(comment) = rest.strip_prefix("<!--") {
+                rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+                continue;
+            }
+            let Some(end) = rest.find('>') else {
+                // A lone `<` is text.
+                tokens.push(Token::Text(rest));
+                return tokens;
+            };
+            let tag = &rest[1..end];
+            rest = &rest[end + 1..];
+            if tag.starts_with(['!', '?']) {
+                continue;
+            }
+            let closing = tag.starts_with('/');
+            let name: String = tag
+                .trim_start_matches('/')
+                .chars()
+                .take_while(|c| !c.is_whitespace() && *c != '/')
+                .collect::<String>()
+                .to_ascii_lowercase();
+            if !closing && (name == "script" || name == "style") && !tag.ends_with('/') {
+                let close = format!("</{name}");
+                rest = find_ascii_case_insensitive(rest, &close)
+                    .and_then(|index| rest[index..].find('>').map(|end| &rest[index + end + 1..]))
+                    .unwrap_or("");
+                continue;
+            }
+            tokens.push(Token::Tag { name, closing });
+        }
+        if !rest.is_empty() {
+            tokens.push(Token::Text(rest));
+        }
+        tokens
+    }
+
+    #[cfg(any(feature = "html", feature = "docx"))]
+    fn find_ascii_case_insensitive(haystack: &str, needle: &str) -> Option<usize> {
+        haystack
+            .as_bytes()
+            .windows(needle.len())
+            .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+    }
+
+    /// HTML elements that start a new line of text when rendered.
+    #[cfg(feature = "html")]
+    const BLOCK_ELEMENTS: &[&str] = &[
+        "address",
+        "article",
+        "aside",
+        "blockquote",
+        "br",
+        "dd",
+        "div",
+        "dl",
+        "dt",
+        "figcaption",
+        "figure",
+        "footer",
+        "form",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "header",
+        "hr",
+        "li",
+        "main",
+        "nav",
+        "ol",
+        "p",
+        "pre",
+        "section",
+        "table",
+        "td",
+        "th",
+        "title",
+        "tr",
+        "ul",
+    ];
+
+    /// The text of an HTML document as a browser lays it out: one line per
+    /// block element, whitespace collapsed outside `pre`, and entities decoded.
+    #[cfg(feature = "html")]
+    pub(super) fn html_text(html: &str) -> String {
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        let mut pre_depth = 0usize;
+        for token in tokens(html) {
+            match token {
+                Token::Tag { name, closing } => {
+                    if BLOCK_ELEMENTS.contains(&name.as_str()) {
+                        lines.push(std::mem::take(&mut line));
+                    }
+                    if name == "pre" {
+                        pre_depth = match closing {
+                            true => pre_depth.saturating_sub(1),
+                            false => pre_depth + 1,
+                        };
+                    }
+                }
+                Token::Text(text) if pre_depth > 0 => {
+                    let mut text_lines = text.split('\n');
+                    if let Some(first) = text_lines.next() {
+                        line.push_str(&decode_entities(first));
+                    }
+                    for text_line in text_lines {
+                        lines.push(std::mem::replace(&mut line, decode_entities(text_line)));
+                    }
+                }
+                Token::Text(text) => {
+                    let collapsed = text.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+                    let spaced = line.is_empty() || line.ends_with(' ');
+                    if text.starts_with(|c: char| c.is_ascii_whitespace()) && !spaced {
+                        line.push(' ');
+                    }
+                    line.push_str(&decode_entities(&collapsed));
+                    if text.ends_with(|c: char| c.is_ascii_whitespace()) && !collapsed.is_empty() {
+                        line.push(' ');
+                    }
+                }
+            }
+        }
+        lines.push(line);
+        tidy_lines(lines.iter().map(|line| line.trim_end()))
+    }
+
+    /// The text of a DOCX main document part: one line per paragraph, from
+    /// the text runs only, so field codes and other markup are left out.
+    #[cfg(feature = "docx")]
+    pub(super) fn docx_text(xml: &str) -> String {
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        let mut in_text_run = false;
+        // `w:tab` also defines tab stops inside `w:tabs`.
+        let mut in_tab_stops = false;
+        for token in tokens(xml) {
+            match token {
+                Token::Tag { name, closing } => match name.as_str() {
+                    "w:t" => in_text_run = !closing,
+                    "w:tabs" => in_tab_stops = !closing,
+                    "w:tab" if !closing && !in_tab_stops => line.push('\t'),
+                    "w:br" | "w:cr" if !closing => lines.push(std::mem::take(&mut line)),
+                    "w:p" if closing => lines.push(std::mem::take(&mut line)),
+                    _ => {}
+                },
+                Token::Text(text) if in_text_run => line.push_str(&decode_entities(text)),
+                Token::Text(_) => {}
+            }
+        }
+        lines.push(line);
+        tidy_lines(lines.iter().map(String::as_str))
+    }
+
+    /// Joins lines with a trailing newline, leaving out blank ones.
+    pub(super) fn tidy_lines<'a>(lines: impl Iterator<Item = &'a str>) -> String {
+        let mut text = String::new();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            text.push_str(line);
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Replaces character references: numeric ones, and the named ones that
+    /// are common in prose. Unknown or malformed references are kept as text.
+    #[cfg(any(feature = "html", feature = "docx"))]
+    fn decode_entities(text: &str) -> String {
+        let mut decoded = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('&') {
+            decoded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let reference = rest[1..]
+                .find(';')
+                .filter(|&end| end <= 32)
+                .and_then(|end| Some((entity(&rest[1..end + 1])?, end + 2)));
+            match reference {
+                Some((c, len)) => {
+                    decoded.push(c);
+                    rest = &rest[len..];
+                }
+                None => {
+                    decoded.push('&');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        decoded.push_str(rest);
+        decoded
+    }
+
+    #[cfg(any(feature = "html", feature = "docx"))]
+    fn entity(name: &str) -> Option<char> {
+        if let Some(number) = name.strip_prefix('#') {
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            return char::from_u32(code);
+        }
+        Some(match name {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            "nbsp" => '\u{a0}',
+            "copy" => '\u{a9}',
+            "reg" => '\u{ae}',
+            "trade" => '\u{2122}',
+            "deg" => '\u{b0}',
+            "middot" => '\u{b7}',
+            "bull" => '\u{2022}',
+            "hellip" => '\u{2026}',
+            "ndash" => '\u{2013}',
+            "mdash" => '\u{2014}',
+            "lsquo" => '\u{2018}',
+            "rsquo" => '\u{2019}',
+            "ldquo" => '\u{201c}',
+            "rdquo" => '\u{201d}',
+            "laquo" => '\u{ab}',
+            "raquo" => '\u{bb}',
+            "times" => '\u{d7}',
+            "euro" => '\u{20ac}',
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_extract {
+    use super::*;
+
+    #[cfg(any(feature = "html", feature = "docx", feature = "ipynb"))]
+    fn text(path: &str, bytes: &[u8]) -> Option<String> {
+        match extract(Path::new(path), bytes.to_vec(), &Decoding::default()) {
+            Ok(Some(Decoded::Text { content, .. })) => Some(content),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn other_extensions_are_left_to_the_decoder() {
+        let bytes = b"<p>kept</p>".to_vec();
+        assert_eq!(
+            extract(Path::new("page.txt"), bytes.clone(), &Decoding::default()),
+            Err(bytes)
+        );
+    }
+
+    #[cfg(feature = "html")]
+    #[test]
+    fn html_is_laid_out_as_text() {
+        let html = r#"<!DOCTYPE html>
+<html><head><title>Guide</title><style>p { color: red }</style><script>if (a < b) {}</script></head>
+<body><!-- nav --><h1>Install &amp; run</h1>
+<p>Run   the
+  <b>tool</b>, then&nbsp;wait&#8230; &#x2014; &bogus; AT&T</p>
+<pre>fn main() {
+    run();
+}</pre><ul><li>one<li>two</ul></body></html>"#;
+        assert_eq!(
+            text("docs/Guide.HTML", html.as_bytes()).unwrap(),
+            "Guide\nInstall & run\nRun the tool, then\u{a0}wait\u{2026} \u{2014} &bogus; AT&T\n\
+             fn main() {\n    run();\n}\none\ntwo\n"
+        );
+    }
+
+    #[cfg(feature = "docx")]
+    #[test]
+    fn docx_paragraphs_come_from_the_text_runs() {
+        use std::io::Write;
+
+        let xml = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>
+<w:p><w:r><w:t>First </w:t></w:r><w:r><w:rPr><w:b/></w:rPr><w:t xml:space="preserve">para &amp; more</w:t></w:r></w:p>
+<w:p><w:pPr><w:tabs><w:tab w:val="left" w:pos="720"/></w:tabs></w:pPr><w:r><w:instrText>PAGE</w:instrText><w:tab/><w:t>Second</w:t><w:br/><w:t>line</w:t></w:r></w:p>
+<w:p/></w:body></w:document>"#;
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .start_file(
+                "word/document.xml",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        writer.write_all(xml.as_bytes()).unwrap();
+        let docx = writer.finish().unwrap().into_inner();
+
+        assert_eq!(
+            text("report.docx", &docx).unwrap(),
+            "First para & more\n\tSecond\nline\n"
+        );
+        assert_eq!(text("broken.docx", b"not a zip"), None);
+    }
+
+    #[cfg(feature = "docx")]
+    #[test]
+    fn oversized_docx_parts_are_skipped() {
+        use crate::archive::MAX_MEMBER_BYTES;
+        use std::io::Write;
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        writer
+            .start_file(
+                "word/document.xml",
+                zip::write::SimpleFileOptions::default(),
+            )
+            .unwrap();
+        let chunk = vec![b' '; 1024 * 1024];
+        for _ in 0..MAX_MEMBER_BYTES / chunk.len() as u64 {
+            writer.write_all(&chunk).unwrap();
+        }
+        writer.write_all(b" ").unwrap();
+        let docx = writer.finish().unwrap().into_inner();
+
+        assert!(docx.len() < 1024 * 1024);
+        assert_eq!(text("bomb.docx", &docx), None);
+    }
+
+    #[cfg(feature = "ipynb")]
+    #[test]
+    fn notebooks_keep_only_cell_sources() {
+        use crate::decode::NotebookCell;
+
+        let notebook = r##"{
+ "cells": [
+  {"cell_type": "markdown", "metadata": {}, "source": ["# Load\n", "Read the data"]},
+  {"cell_type": "code", "execution_count": 1, "metadata": {},
+   "outputs": [{"output_type": "stream", "name": "stdout", "text": ["3\n"]}],
+   "source": ["import json\n", "print(len(data))"]},
+  {"cell_type": "raw", "metadata": {}, "source": "raw"},
+  {"cell_type": "code", "metadata": {}, "outputs": [], "source": ""},
+  {"cell_type": "code", "metadata": {}, "outputs": [], "source": "x = {\"a\": 1}\n"}
+ ],
+ "metadata": {"kernelspec": {"name": "python3"}},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}"##;
+        let extract_with = |notebook_markdown| {
+            let decoding = Decoding {
+                notebook_markdown,
+                ..Decoding::default()
+            };
+            extract(Path::new("analysis.ipynb"), notebook.into(), &decoding)
+        };
+        let code = "import json\nprint(len(data))\nx = {\"a\": 1}\n";
+        assert_eq!(
+            extract_with(false),
+            Ok(Some(Decoded::Notebook {
+                content: code.to_owned(),
+                cells: vec![
+                    NotebookCell {
+                        index: 1,
+                        source: 0..29,
+                    },
+                    NotebookCell {
+                        index: 4,
+                        source: 29..code.len(),
+                    },
+                ],
+            }))
+        );
+        let Ok(Some(Decoded::Notebook { content, cells })) = extract_with(true) else {
+            panic!("not a notebook");
+        };
+        assert_eq!(content, format!("# Load\nRead the data\n{code}"));
+        assert_eq!(cells[0].index, 0);
+        assert_eq!(cells.len(), 3);
+        assert_eq!(text("broken.ipynb", b"{\"cells\": 3}"), None);
+    }
+
+    #[cfg(feature = "pdf")]
+    #[test]
+    fn pdf_text_is_extracted() {
+        let stream = "BT /F1 12 Tf 72 720 Td (Hello PDF) Tj ET";
+        let objects = [
+            "<< /Type /Catalog /Pages 2 0 R >>".to_owned(),
+            "<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_owned(),
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 612 792] \
+             /Resources << /Font << /F1 5 0 R >> >> /Contents 4 0 R >>"
+                .to_owned(),
+            format!(
+                "<< /Length {} >>\nstream\n{stream}\nendstream",
+                stream.len()
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_owned(),
+        ];
+        let mut pdf = String::from("%PDF-1.4\n");
+        let mut offsets = Vec::new();
+        for (index, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.push_str(&format!("{} 0 obj\n{object}\nendobj\n", index + 1));
+        }
+        let xref = pdf.len();
+        pdf.push_str(&format!(
+            "xref\n0 {}\n0000000000 65535 f \n",
+            objects.len() + 1
+        ));
+        for offset in offsets {
+            pdf.push_str(&format!("{offset:010} 00000 n \n"));
+        }
+        pdf.push_str(&format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        ));
+
+        assert_eq!(text("paper.pdf", pdf.as_bytes()).unwrap(), "Hello PDF\n");
+        assert_eq!(text("broken.pdf", b"%PDF-1.4 truncated"), None);
+    }
+}
@@ -0,0 +1,182 @@
+//! Git-aware candidate selection: `--tracked`, `--since <REV>`, and `--staged`
+//! restrict a search to the files the local repository lists, for pre-commit
+//! duplicate checks. The lists come from the local `git` binary, which reads
+//! only the `.git` directory and never contacts a remote. The globs and
+//! `--max-file-lines` still apply on top.
+
+use busca::Args;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(clap::Args, Debug, Default)]
+#[group(multiple = false)]
+pub struct GitArgs {
+    /// Only compare files tracked by git
+    #[arg(long)]
+    pub tracked: bool,
+
+    /// Only compare files changed since this git revision, whether committed,
+    /// staged, or not. Untracked files are left out
+    #[arg(long, value_name = "REV")]
+    pub since: Option<String>,
+
+    /// Only compare files with staged changes. Their working-tree content is
+    /// compared, not the staged content
+    #[arg(long)]
+    pub staged: bool,
+}
+
+impl GitArgs {
+    /// Restricts `args` to the files git lists, when a git flag is set.
+    pub fn restrict(&self, args: Args) -> Result<Args, String> {
+        match self.git_arguments()? {
+            Some(git_arguments) => {
+                let paths = listed_paths(&args.search_path, &git_arguments)?;
+                Ok(args.with_candidate_paths(paths))
+            }
+            None => Ok(args),
+        }
+    }
+
+    /// The `git` arguments that list the selected files, NUL-separated and
+    /// relative to the working directory.
+    fn git_arguments(&self) -> Result<Option<Vec<&str>>, String> {
+        // `d` leaves out deleted files, which cannot be candidates.
+        let changed = ["diff", "-z", "--name-only", "--relative", "--diff-filter=d"];
+        if self.tracked {
+            return Ok(Some(vec!["ls-files", "-z"]));
+        }
+        if self.staged {
+            return Ok(Some([&changed[..], &["--cached"]].concat()));
+        }
+        match &self.since {
+            // A revision starting with `-` would be read as an option.
+            Some(rev) if rev.starts_with('-') => Err(format!("invalid revision '{rev}'")),
+            Some(rev) => Ok(Some([&changed[..], &[rev.as_str(), "--"]].concat())),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Runs `git` in the search path (or, for a file, its directory) and returns
+/// the listed paths spelled as the walk will produce them.
+fn listed_paths(search_path: &Path, git_arguments: &[&str]) -> Result<Vec<PathBuf>, String> {
+    let base = match search_path.is_dir() {
+        true => search_path,
+        false => search_path.parent().unwrap_or(Path::new("")),
+    };
+    let directory = match base.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => base,
+    };
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(git_arguments)
+        // Listing must not take the index lock from a concurrent git command.
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .output()
+        .map_err(|e| format!("cannot run git: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {}: {}",
+            git_arguments[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(output
+        .stdout
+        .split(|&byte| byte == 0)
+        .filter(|path| !path.is_empty())
+        .map(|path| base.join(path_from_bytes(path)))
+        .collect())
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+/// Git writes paths as UTF-8 outside Unix.
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(test)]
+mod test_git {
+    use super::*;
+    use std::fs;
+
+    fn git(repo: &Path, arguments: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args([
+                "-c",
+                "user.name=busca",
+                "-c",
+                "user.email=busca@example.com",
+            ])
+            .args(arguments)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {arguments:?}");
+    }
+
+    #[test]
+    fn each_flag_lists_its_files() {
+        let repo = std::env::temp_dir().join(format!("busca_git_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&repo);
+        fs::create_dir_all(repo.join("src")).unwrap();
+        git(&repo, &["init", "-q"]);
+        fs::write(repo.join("src/committed.py"), "a = 1\n").unwrap();
+        fs::write(repo.join("src/changed.py"), "b = 1\n").unwrap();
+        git(&repo, &["add", "."]);
+        git(&repo, &["commit", "-q", "-m", "first"]);
+        fs::write(repo.join("src/changed.py"), "b = 2\n").unwrap();
+        fs::write(repo.join("src/staged.py"), "c = 1\n").unwrap();
+        git(&repo, &["add", "src/staged.py"]);
+        fs::write(repo.join("src/untracked.py"), "d = 1\n").unwrap();
+
+        let search_path = repo.join("src");
+        let listed = |git_args: GitArgs| {
+            let mut paths =
+                listed_paths(&search_path, &git_args.git_arguments().unwrap().unwrap()).unwrap();
+            paths.sort();
+            paths
+        };
+        let paths = |names: &[&str]| -> Vec<PathBuf> {
+            names.iter().map(|name| search_path.join(name)).collect()
+        };
+        let tracked = listed(GitArgs {
+            tracked: true,
+            ..GitArgs::default()
+        });
+        let staged = listed(GitArgs {
+            staged: true,
+            ..GitArgs::default()
+        });
+        let since = listed(GitArgs {
+            since: Some("HEAD".to_owned()),
+            ..GitArgs::default()
+        });
+        let _ = fs::remove_dir_all(&repo);
+
+        assert_eq!(tracked, paths(&["changed.py", "committed.py", "staged.py"]));
+        assert_eq!(staged, paths(&["staged.py"]));
+        assert_eq!(since, paths(&["changed.py", "staged.py"]));
+    }
+
+    #[test]
+    fn option_like_revisions_are_rejected() {
+        let git_args = GitArgs {
+            since: Some("--output=x".to_owned()),
+            ..GitArgs::default()
+        };
+        assert!(git_args.git_arguments().is_err());
+        assert!(GitArgs::default().git_arguments().unwrap().is_none());
+    }
+}
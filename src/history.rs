@@ -0,0 +1,406 @@
+//! `busca history`: finds the commit and path whose content most resembles the
+//! reference, for files that were deleted or rewritten. Every blob a commit in
+//! the range introduced is a candidate, once: blobs are deduplicated by object
+//! id and reported at the newest commit that introduced them. Blobs are read
+//! from the local repository with `git`, which needs no network, and ranked by
+//! the library's `rank_contents` with the same top-N pruning as a search.
+
+use crate::{
+    get_piped_input, graceful_panic, parse_count, parse_similarity_ratio, terminal, OutputFormat,
+    DEFAULT_COUNT, DEFAULT_MAX_FILE_LINES,
+};
+use busca::{rank_contents, Args, FileComparison};
+use console::Term;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Hex digits of the commit id shown in human output.
+const SHORT_COMMIT_LEN: usize = 12;
+
+/// Blobs larger than this are skipped without being loaded, as oversized
+/// archive members are by a search.
+const MAX_BLOB_BYTES: usize = 64 * 1024 * 1024;
+
+#[derive(clap::Args, Debug)]
+pub struct HistoryArgs {
+    /// Commits to search, as `git log` takes them: a revision such as `main`,
+    /// or a range such as `v1.0..HEAD`
+    #[arg(default_value = "HEAD")]
+    revision_range: String,
+
+    /// Local or absolute path to the reference comparison file. Overrides any
+    /// piped input
+    #[arg(short, long)]
+    ref_file_path: Option<PathBuf>,
+
+    /// The repository to search. Defaults to CWD
+    #[arg(long)]
+    repo: Option<PathBuf>,
+
+    /// Only search the newest N commits of the range
+    #[arg(long, value_parser = parse_count)]
+    max_commits: Option<usize>,
+
+    /// Number of results to display. Defaults to 10
+    #[arg(short, long, value_parser = parse_count)]
+    count: Option<usize>,
+
+    /// Drop versions whose similarity ratio is below this value (in [0.0, 1.0])
+    #[arg(long, value_parser = parse_similarity_ratio)]
+    min_similarity_ratio: Option<f32>,
+
+    /// Globs that qualify a path in the repository for comparison
+    #[arg(short, long)]
+    include_glob: Option<Vec<String>>,
+
+    /// Globs that disqualify a path in the repository from comparison
+    #[arg(short = 'x', long)]
+    exclude_glob: Option<Vec<String>>,
+
+    /// The maximum number of lines a version may have. Defaults to 10000
+    #[arg(short, long)]
+    max_file_lines: Option<usize>,
+
+    /// Output format: human or json
+    #[arg(long, value_enum, default_value = "human")]
+    format: OutputFormat,
+}
+
+/// One blob a commit introduced at a path.
+#[derive(Debug, Clone, PartialEq)]
+struct Version {
+    commit: String,
+    path: String,
+    blob: String,
+}
+
+/// One row of `busca history --format json`.
+#[derive(Serialize)]
+struct JsonVersion {
+    commit: String,
+    path: String,
+    similarity_ratio: f32,
+}
+
+/// Handles `busca history`. Exits `0` when a version matched, `1` when none
+/// did, and `2` on error, like a search.
+pub fn run(history_args: HistoryArgs) {
+    let format = history_args.format;
+    if !matches!(format, OutputFormat::Human | OutputFormat::Json) {
+        graceful_panic("busca history supports --format human and json.");
+    }
+    if history_args.revision_range.starts_with('-') {
+        graceful_panic(&format!(
+            "invalid revision range '{}'",
+            history_args.revision_range
+        ));
+    }
+    let reference_string = match &history_args.ref_file_path {
+        Some(path) => fs::read_to_string(path)
+            .unwrap_or_else(|e| graceful_panic(&format!("cannot read '{}': {e}", path.display()))),
+        None => get_piped_input().unwrap_or_else(|e| graceful_panic(&e)),
+    };
+    let repo = history_args.repo.unwrap_or_else(|| PathBuf::from("."));
+    let args = Args::new(
+        reference_string,
+        repo.clone(),
+        Some(
+            history_args
+                .max_file_lines
+                .unwrap_or(DEFAULT_MAX_FILE_LINES),
+        ),
+        Some(history_args.count.unwrap_or(DEFAULT_COUNT)),
+        history_args.min_similarity_ratio,
+        history_args.include_glob.unwrap_or_default(),
+        history_args.exclude_glob.unwrap_or_default(),
+    )
+    .unwrap_or_else(|e| graceful_panic(&e.to_string()));
+
+    let versions = list_versions(
+        &repo,
+        &history_args.revision_range,
+        history_args.max_commits,
+    )
+    .unwrap_or_else(|e| graceful_panic(&e));
+    let versions: Vec<Version> = versions
+        .into_iter()
+        .filter(|version| args.matches_path(Path::new(&version.path)))
+        .collect();
+    let contents =
+        read_blobs(&repo, &versions, MAX_BLOB_BYTES).unwrap_or_else(|e| graceful_panic(&e));
+
+    // Each candidate is named `<commit>:<path>`, as `git show` names a blob.
+    let candidates: Vec<(PathBuf, String)> = versions
+        .iter()
+        .zip(contents)
+        .filter_map(|(version, content)| {
+            let name = format!("{}:{}", version.commit, version.path);
+            Some((PathBuf::from(name), content?))
+        })
+        .collect();
+    let ranked: Vec<(String, String, f32)> = rank_contents(&args, candidates)
+        .into_iter()
+        .map(|fc| {
+            let name = fc.path.to_string_lossy();
+            let (commit, path) = name
+                .split_once(':')
+                .expect("candidates are named <commit>:<path>");
+            (commit.to_owned(), path.to_owned(), fc.similarity_ratio)
+        })
+        .collect();
+
+    if ranked.is_empty() {
+        eprintln!("No versions found that match the criteria.");
+        std::process::exit(1);
+    }
+    if format == OutputFormat::Json {
+        let rows: Vec<JsonVersion> = ranked
+            .iter()
+            .map(|(commit, path, similarity_ratio)| JsonVersion {
+                commit: commit.clone(),
+                path: path.clone(),
+                similarity_ratio: *similarity_ratio,
+            })
+            .collect();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&rows).expect("JSON serialization of rows cannot fail")
+        );
+        return;
+    }
+    let rows: Vec<FileComparison> = ranked
+        .iter()
+        .map(|(commit, path, similarity_ratio)| {
+            FileComparison::new(
+                PathBuf::from(format!(
+                    "{}:{path}",
+                    &commit[..SHORT_COMMIT_LEN.min(commit.len())]
+                )),
+                *similarity_ratio,
+                String::new(),
+            )
+        })
+        .collect();
+    println!(
+        "{}",
+        terminal::format_grid(&rows, terminal::width(&Term::stdout()))
+    );
+}
+
+/// The blobs introduced by the commits of `revision_range`, newest commit
+/// first, each blob once. Only regular files count: symlinks and submodules
+/// are skipped. Merge commits introduce nothing here, as in `git log --raw`.
+fn list_versions(
+    repo: &Path,
+    revision_range: &str,
+    max_commits: Option<usize>,
+) -> Result<Vec<Version>, String> {
+    let mut command = git(repo);
+    command.args([
+        "log",
+        "--raw",
+        "--no-abbrev",
+        "--no-renames",
+        "-z",
+        "--format=%H",
+        "--diff-filter=d",
+    ]);
+    if let Some(max_commits) = max_commits {
+        command.arg(format!("--max-count={max_commits}"));
+    }
+    let output = command
+        .args([revision_range, "--"])
+        .output()
+        .map_err(|e| format!("cannot run git: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git log: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(parse_log(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Parses `git log --raw -z --format=%H`: each commit id is followed by its
+/// raw entries, `:<old mode> <new mode> <old blob> <new blob> <status>` and
+/// then the path, all NUL-separated.
+fn parse_log(log: &str) -> Vec<Version> {
+    let mut seen = HashSet::new();
+    let mut versions = Vec::new();
+    let mut commit = "";
+    let mut fields = log.split('\0');
+    while let Some(field) = fields.next() {
+        let field = field.trim_start_matches('\n');
+        let Some(raw) = field.strip_prefix(':') else {
+            if !field.is_empty() {
+                commit = field;
+            }
+            continue;
+        };
+        let Some(path) = fields.next() else {
+            break;
+        };
+        let raw: Vec<&str> = raw.split(' ').collect();
+        let (Some(mode), Some(blob)) = (raw.get(1), raw.get(3)) else {
+            continue;
+        };
+        // 100644 and 100755 are regular files.
+        if mode.starts_with("100") && seen.insert(*blob) {
+            versions.push(Version {
+                commit: commit.to_owned(),
+                path: path.to_owned(),
+                blob: (*blob).to_owned(),
+            });
+        }
+    }
+    versions
+}
+
+/// Reads each version's blob with one `git cat-file --batch`. A blob that is
+/// not UTF-8 is `None`, as an unreadable file is skipped by a search, and so is
+/// one over `max_bytes`, whose bytes are discarded as they stream past.
+fn read_blobs(
+    repo: &Path,
+    versions: &[Version],
+    max_bytes: usize,
+) -> Result<Vec<Option<String>>, String> {
+    let mut child = git(repo)
+        .args(["cat-file", "--batch"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("cannot run git: {e}"))?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let blobs: Vec<String> = versions
+        .iter()
+        .map(|version| version.blob.clone())
+        .collect();
+    // Written from another thread so that git never blocks on a full stdout
+    // pipe while this thread is still writing.
+    let writer = thread::spawn(move || {
+        for blob in blobs {
+            if writeln!(stdin, "{blob}").is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut reader = BufReader::new(child.stdout.take().expect("stdout is piped"));
+    let mut contents = Vec::with_capacity(versions.len());
+    let read_error = |e: std::io::Error| format!("git cat-file: {e}");
+    for _ in versions {
+        let mut header = String::new();
+        reader.read_line(&mut header).map_err(read_error)?;
+        // `<blob> blob <size>`, or `<blob> missing`.
+        let size = header
+            .split_whitespace()
+            .nth(2)
+            .and_then(|size| size.parse::<usize>().ok());
+        let Some(size) = size else {
+            contents.push(None);
+            continue;
+        };
+        // The content is followed by a newline.
+        if size > max_bytes {
+            io::copy(&mut (&mut reader).take(size as u64 + 1), &mut io::sink())
+                .map_err(read_error)?;
+            contents.push(None);
+            continue;
+        }
+        let mut content = vec![0; size + 1];
+        reader.read_exact(&mut content).map_err(read_error)?;
+        content.pop();
+        contents.push(String::from_utf8(content).ok());
+    }
+    let _ = writer.join();
+    let _ = child.wait();
+    Ok(contents)
+}
+
+fn git(repo: &Path) -> Command {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(repo);
+    command
+}
+
+#[cfg(test)]
+mod test_history {
+    use super::*;
+
+    #[test]
+    fn log_entries_are_deduplicated_by_blob() {
+        let (a, b) = ("a".repeat(40), "b".repeat(40));
+        let zero = "0".repeat(40);
+        let log = format!(
+            "c2\0\n:100644 100644 {a} {b} M\0src/x.py\0:000000 100644 {zero} {a} A\0src/copy.py\0\
+             :000000 120000 {zero} {b} A\0link\0c1\0\n:000000 100644 {zero} {a} A\0src/x.py\0"
+        );
+        let version = |commit: &str, path: &str, blob: &str| Version {
+            commit: commit.to_owned(),
+            path: path.to_owned(),
+            blob: blob.to_owned(),
+        };
+        assert_eq!(
+            parse_log(&log),
+            [
+                version("c2", "src/x.py", &b),
+                version("c2", "src/copy.py", &a),
+            ]
+        );
+    }
+
+    #[test]
+    fn blobs_are_found_in_the_commit_that_introduced_them() {
+        let repo = std::env::temp_dir().join(format!("busca_history_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&repo);
+        fs::create_dir_all(&repo).unwrap();
+        let run = |arguments: &[&str]| {
+            let status = git(&repo)
+                .args([
+                    "-c",
+                    "user.name=busca",
+                    "-c",
+                    "user.email=busca@example.com",
+                ])
+                .args(arguments)
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success(), "git {arguments:?}");
+        };
+        run(&["init", "-q"]);
+        fs::write(repo.join("gone.py"), "def f():\n    return 1\n").unwrap();
+        run(&["add", "."]);
+        run(&["commit", "-q", "-m", "add"]);
+        fs::remove_file(repo.join("gone.py")).unwrap();
+        fs::write(repo.join("other.py"), "x = 1\n").unwrap();
+        run(&["add", "-A"]);
+        run(&["commit", "-q", "-m", "remove"]);
+
+        let versions = list_versions(&repo, "HEAD", None).unwrap();
+        let newest = list_versions(&repo, "HEAD", Some(1)).unwrap();
+        let contents = read_blobs(&repo, &versions, MAX_BLOB_BYTES).unwrap();
+        // gone.py is over the cap and read first, so other.py is only read
+        // correctly if its bytes were skipped in step.
+        let oldest_first: Vec<Version> = versions.iter().rev().cloned().collect();
+        let capped = read_blobs(&repo, &oldest_first, 8).unwrap();
+        let _ = fs::remove_dir_all(&repo);
+
+        let paths: Vec<&str> = versions.iter().map(|v| v.path.as_str()).collect();
+        assert_eq!(paths, ["other.py", "gone.py"]);
+        assert_eq!(newest.len(), 1);
+        assert_eq!(
+            contents,
+            [
+                Some("x = 1\n".to_owned()),
+                Some("def f():\n    return 1\n".to_owned())
+            ]
+        );
+        assert_eq!(capped, [None, Some("x = 1\n".to_owned())]);
+    }
+}
@@ -0,0 +1,190 @@
+//! `--format html`: a single self-contained HTML report of the ranked
+//! comparisons, each with an expandable side-by-side diff against the
+//! reference. The page inlines its stylesheet and uses `<details>` for the
+//! expandable rows, so it needs no scripts, external assets, or network access
+//! and can be archived as a CI artifact.
+
+use crate::diff::{pair_rows, Side};
+use busca::FileComparison;
+use similar::{ChangeTag, TextDiff};
+use std::fmt::Write;
+
+const STYLE: &str = "\
+body{font-family:-apple-system,BlinkMacSystemFont,'Segoe UI',sans-serif;margin:2em;color:#1f2328}
+h1{font-size:1.4em}
+.query{color:#59636e;font-size:.9em}
+details{border:1px solid #d1d9e0;border-radius:6px;margin:.5em 0}
+summary{cursor:pointer;padding:.4em .8em;display:flex;gap:1em;align-items:baseline}
+.rank{color:#59636e;min-width:2em;text-align:right}
+.path{flex:1;font-family:ui-monospace,Menlo,monospace}
+.bar{font-family:ui-monospace,Menlo,monospace;color:#1a7f37;min-width:10ch}
+.ratio{min-width:4.5em;text-align:right;font-variant-numeric:tabular-nums}
+table.diff{border-collapse:collapse;width:100%;font-family:ui-monospace,Menlo,monospace;font-size:.85em;table-layout:fixed}
+table.diff th{background:#f6f8fa;text-align:left;padding:.3em .6em;font-weight:normal}
+table.diff td{padding:0 .6em;white-space:pre-wrap;word-break:break-all;vertical-align:top}
+table.diff td.ln{width:3.5em;color:#59636e;text-align:right;user-select:none}
+td.del{background:#ffebe9}td.ins{background:#e6ffec}
+td.del .em{background:#ffcecb}td.ins .em{background:#aceebb}
+tr.sep td{background:#f6f8fa;height:.6em}
+p.identical{padding:.4em .8em;color:#59636e}
+";
+
+/// Renders the full report. `reference_label` names the reference in the diff
+/// headers: its path, or a placeholder for piped input. `context_lines` is the
+/// unchanged context kept around each hunk.
+pub fn render_report(
+    reference_label: &str,
+    reference_string: &str,
+    search_path: &str,
+    file_comparisons: &[FileComparison],
+    context_lines: usize,
+) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    out.push_str("<title>busca report</title>\n<style>\n");
+    out.push_str(STYLE);
+    out.push_str("</style>\n</head>\n<body>\n<h1>busca report</h1>\n");
+    let _ = writeln!(
+        out,
+        "<p class=\"query\">Reference <code>{}</code> searched in <code>{}</code>: {} result{}.</p>",
+        escape(reference_label),
+        escape(search_path),
+        file_comparisons.len(),
+        if file_comparisons.len() == 1 { "" } else { "s" },
+    );
+
+    for (index, fc) in file_comparisons.iter().enumerate() {
+        let path = fc.path.display().to_string();
+        out.push_str("<details>\n<summary>");
+        let _ = write!(
+            out,
+            "<span class=\"rank\">{}</span><span class=\"path\">{}</span>\
+             <span class=\"bar\">{}</span><span class=\"ratio\">{:.1}%</span>",
+            index + 1,
+            escape(&path),
+            "+".repeat((fc.similarity_ratio * 10.0).round() as usize),
+            fc.similarity_ratio * 100.0,
+        );
+        out.push_str("</summary>\n");
+        out.push_str(&side_by_side_diff(
+            reference_label,
+            reference_string,
+            &path,
+            &fc.content,
+            context_lines,
+        ));
+        out.push_str("</details>\n");
+    }
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// The diff of the reference (left) against one candidate (right), as an HTML
+/// table with the same hunks and inline word emphasis as the terminal diff.
+fn side_by_side_diff(
+    reference_label: &str,
+    reference_string: &str,
+    candidate_label: &str,
+    candidate_content: &str,
+    context_lines: usize,
+) -> String {
+    let diff = TextDiff::from_lines(reference_string, candidate_content);
+    let groups = diff.grouped_ops(context_lines);
+    if groups.is_empty() {
+        return "<p class=\"identical\">The sequences are identical.</p>\n".to_owned();
+    }
+
+    let mut out = String::from("<table class=\"diff\">\n");
+    let _ = writeln!(
+        out,
+        "<colgroup><col style=\"width:3.5em\"><col><col style=\"width:3.5em\"><col></colgroup>\n\
+         <tr><th colspan=\"2\">{}</th><th colspan=\"2\">{}</th></tr>",
+        escape(reference_label),
+        escape(candidate_label),
+    );
+
+    for (group_index, group) in groups.iter().enumerate() {
+        if group_index > 0 {
+            out.push_str("<tr class=\"sep\"><td colspan=\"4\"></td></tr>\n");
+        }
+        for op in group {
+            for row in pair_rows(diff.iter_inline_changes(op)) {
+                out.push_str("<tr>");
+                push_side(&mut out, row.old.as_ref());
+                push_side(&mut out, row.new.as_ref());
+                out.push_str("</tr>\n");
+            }
+        }
+    }
+
+    out.push_str("</table>\n");
+    out
+}
+
+fn push_side(out: &mut String, side: Option<&Side>) {
+    let Some(side) = side else {
+        out.push_str("<td class=\"ln\"></td><td></td>");
+        return;
+    };
+    let class = match side.tag {
+        ChangeTag::Delete => " class=\"del\"",
+        ChangeTag::Insert => " class=\"ins\"",
+        ChangeTag::Equal => "",
+    };
+    let _ = write!(out, "<td class=\"ln\">{}</td><td{class}>", side.index + 1);
+    for (emphasized, value) in &side.segments {
+        let text = escape(value);
+        if *emphasized && side.tag != ChangeTag::Equal {
+            let _ = write!(out, "<span class=\"em\">{text}</span>");
+        } else {
+            out.push_str(&text);
+        }
+    }
+    out.push_str("</td>");
+}
+
+/// Escapes text for HTML element content and double-quoted attribute values.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test_html {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(
+            escape("<a href=\"x\">&'</a>"),
+            "&lt;a href=&quot;x&quot;&gt;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn report_lists_each_result_with_its_diff() {
+        let comparisons = vec![
+            FileComparison::new(PathBuf::from("same.py"), 1.0, "x = 1\n".to_owned()),
+            FileComparison::new(PathBuf::from("<other>.py"), 0.5, "x = 2\n".to_owned()),
+        ];
+        let report = render_report("ref.py", "x = 1\n", "src", &comparisons, 3);
+        assert!(report.starts_with("<!DOCTYPE html>"));
+        assert_eq!(report.matches("<details>").count(), 2);
+        assert!(report.contains("The sequences are identical."));
+        assert!(report.contains("&lt;other&gt;.py"));
+        assert!(report.contains("<span class=\"bar\">+++++</span>"));
+        assert!(report.contains("<td class=\"del\">x = <span class=\"em\">1</span></td>"));
+    }
+}
@@ -5,14 +5,31 @@ use pyo3::prelude::*;
 use rayon::iter::{IndexedParallelIterator, ParallelIterator};
 use rayon::prelude::IntoParallelIterator;
 use similar::{DiffableStr, TextDiff};
-use std::collections::{BinaryHeap, HashMap};
+use std::borrow::Borrow;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fs::{self};
+use std::hash::Hash;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 use term_grid::{Alignment, Cell, Direction, Filling, Grid, GridOptions};
 use walkdir::{DirEntry, WalkDir};
 
 use std::fmt;
 
+mod archive;
+mod cache;
+mod chunks;
+mod config;
+mod decode;
+mod extract;
+
+pub use cache::{rank_contents, run_search_cached, ContentCache};
+pub use config::{Config, PROJECT_CONFIG_FILE_NAME};
+pub use decode::BinaryPolicy;
+
+use chunks::Fingerprint;
+use decode::{Decoded, NotebookCell};
+
 #[non_exhaustive]
 #[derive(Debug)]
 pub enum Error {
@@ -24,6 +41,22 @@ pub enum Error {
     InvalidSimilarityRatio {
         value: f32,
     },
+    ConfigRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    ConfigParse {
+        path: PathBuf,
+        message: String,
+    },
+    UnknownProfile(String),
+    UnknownEncoding(String),
+    UnknownBinaryPolicy(String),
+    ReferenceRead {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    ReferenceDecode(PathBuf),
 }
 
 impl fmt::Display for Error {
@@ -42,6 +75,34 @@ impl fmt::Display for Error {
                     write!(f, "min_similarity_ratio must be in [0.0, 1.0], got {value}")
                 }
             }
+            Error::ConfigRead { path, source } => {
+                write!(f, "cannot read config '{}': {source}", path.display())
+            }
+            Error::ConfigParse { path, message } => {
+                write!(f, "invalid config '{}': {message}", path.display())
+            }
+            Error::UnknownProfile(name) => {
+                write!(f, "config profile not found: {name}")
+            }
+            Error::UnknownEncoding(label) => {
+                write!(f, "unknown encoding: {label}")
+            }
+            Error::UnknownBinaryPolicy(name) => {
+                write!(
+                    f,
+                    "unknown binary policy: {name} (expected skip, report, or compare)"
+                )
+            }
+            Error::ReferenceRead { path, source } => {
+                write!(f, "cannot read reference '{}': {source}", path.display())
+            }
+            Error::ReferenceDecode(path) => {
+                write!(
+                    f,
+                    "cannot decode reference '{}': it is binary, not valid in its encoding, or an unreadable document",
+                    path.display()
+                )
+            }
         }
     }
 }
@@ -52,6 +113,13 @@ impl std::error::Error for Error {
             Error::InvalidGlob { source, .. } => Some(source),
             Error::SearchPathNotFound(_) => None,
             Error::InvalidSimilarityRatio { .. } => None,
+            Error::ConfigRead { source, .. } => Some(source),
+            Error::ConfigParse { .. } => None,
+            Error::UnknownProfile(_) => None,
+            Error::UnknownEncoding(_) => None,
+            Error::UnknownBinaryPolicy(_) => None,
+            Error::ReferenceRead { source, .. } => Some(source),
+            Error::ReferenceDecode(_) => None,
         }
     }
 }
@@ -61,21 +129,83 @@ impl std::error::Error for Error {
 // the FromPyObject derive opt-in for Clone pyclasses, so we skip it.
 #[pyclass(get_all, skip_from_py_object)]
 #[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub struct FileComparison {
     pub path: PathBuf,
     pub similarity_ratio: f32,
     pub content: String,
+    /// The name of the encoding `content` was decoded from, such as `UTF-8`,
+    /// `UTF-16LE`, or `windows-1252`. `binary` for a binary candidate compared
+    /// byte by byte under [`BinaryPolicy::Compare`], whose `content` is empty.
+    pub encoding: &'static str,
+    /// For a Jupyter notebook, the index in its `cells` array of the compared
+    /// cell most similar to the reference. `None` for other candidates.
+    pub cell: Option<usize>,
 }
-#[pymethods]
 impl FileComparison {
-    #[new]
-    fn new(path: PathBuf, similarity_ratio: f32, content: String) -> Self {
+    /// A comparison of UTF-8 `content` that is not a notebook cell. The
+    /// `encoding` and `cell` fields can be set afterwards.
+    pub fn new(path: PathBuf, similarity_ratio: f32, content: String) -> Self {
         Self {
             path,
             similarity_ratio,
             content,
+            encoding: UTF_8,
+            cell: None,
         }
     }
+}
+#[pymethods]
+impl FileComparison {
+    #[new]
+    #[pyo3(signature = (path, similarity_ratio, content, encoding=None, cell=None))]
+    fn py_new(
+        path: PathBuf,
+        similarity_ratio: f32,
+        content: String,
+        encoding: Option<String>,
+        cell: Option<usize>,
+    ) -> PyResult<Self> {
+        let encoding = match encoding {
+            Some(label) => decode::encoding_for_label(&label)
+                .ok_or_else(|| PyValueError::new_err(Error::UnknownEncoding(label).to_string()))?
+                .name(),
+            None => UTF_8,
+        };
+        Ok(Self {
+            path,
+            similarity_ratio,
+            content,
+            encoding,
+            cell,
+        })
+    }
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+/// The encoding name of candidates that were valid UTF-8 without a byte order
+/// mark, and of in-memory contents.
+const UTF_8: &str = "UTF-8";
+
+/// A file that survives the walk, glob, and line-count filters of a search,
+/// listed without being scored. Returned by [`list_candidates`].
+#[pyclass(get_all, skip_from_py_object)]
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Candidate {
+    /// The file's path, or an archive member's virtual path.
+    pub path: PathBuf,
+    /// The `str::lines()` count of the decoded, or extracted, text that
+    /// `max_file_lines` applies to. `0` for a binary file.
+    pub line_count: usize,
+    /// The size of the file on disk, or of an archive member uncompressed,
+    /// before any decoding or extraction.
+    pub size_bytes: u64,
+}
+#[pymethods]
+impl Candidate {
     fn __repr__(&self) -> String {
         format!("{:?}", self)
     }
@@ -88,23 +218,21 @@ impl FileComparison {
 ///
 /// ```
 /// let file_comparisons = vec![
-///     busca::FileComparison {
-///         path: std::path::PathBuf::from(
-///             "sample_dir_hello_world/nested_dir/sample_python_file_3.py",
-///         ),
-///         similarity_ratio: 0.9846,
-///         content: "print(\"Hello World\")\n".to_string(),
-///     },
-///     busca::FileComparison {
-///         path: std::path::PathBuf::from("sample_dir_hello_world/file_1.py"),
-///         similarity_ratio: 0.3481,
-///         content: "print(\"Hello\")\n".to_string(),
-///     },
-///     busca::FileComparison {
-///         path: std::path::PathBuf::from("sample_dir_mix/file_5.py"),
-///         similarity_ratio: 0.0521,
-///         content: String::new(),
-///     },
+///     busca::FileComparison::new(
+///         std::path::PathBuf::from("sample_dir_hello_world/nested_dir/sample_python_file_3.py"),
+///         0.9846,
+///         "print(\"Hello World\")\n".to_string(),
+///     ),
+///     busca::FileComparison::new(
+///         std::path::PathBuf::from("sample_dir_hello_world/file_1.py"),
+///         0.3481,
+///         "print(\"Hello\")\n".to_string(),
+///     ),
+///     busca::FileComparison::new(
+///         std::path::PathBuf::from("sample_dir_mix/file_5.py"),
+///         0.0521,
+///         String::new(),
+///     ),
 /// ];
 ///
 /// let expected_output = "\
@@ -161,7 +289,7 @@ mod busca_py {
     use super::*;
 
     #[pymodule_export]
-    use super::FileComparison;
+    use super::{Candidate, FileComparison};
 
     #[pyfunction]
     #[pyo3(signature = (
@@ -171,8 +299,17 @@ mod busca_py {
         count=None,
         min_similarity_ratio=None,
         include_glob=None,
-        exclude_glob=None
+        exclude_glob=None,
+        config_path=None,
+        profile=None,
+        search_archives=false,
+        encoding=None,
+        lossy=false,
+        binary=None,
+        extract_text=false,
+        notebook_markdown=false
     ))]
+    #[allow(clippy::too_many_arguments)]
     fn search(
         reference_string: String,
         search_path: PathBuf,
@@ -181,35 +318,133 @@ mod busca_py {
         min_similarity_ratio: Option<f32>,
         include_glob: Option<Bound<'_, PyAny>>,
         exclude_glob: Option<Bound<'_, PyAny>>,
+        config_path: Option<PathBuf>,
+        profile: Option<String>,
+        search_archives: bool,
+        encoding: Option<String>,
+        lossy: bool,
+        binary: Option<String>,
+        extract_text: bool,
+        notebook_markdown: bool,
     ) -> PyResult<Vec<FileComparison>> {
-        let include_glob = extract_glob_arg(include_glob)?;
-        let exclude_glob = extract_glob_arg(exclude_glob)?;
+        let config = load_config(config_path, profile)?;
+        let include_glob = extract_glob_arg(include_glob)?.or(config.include_glob);
+        let exclude_glob = extract_glob_arg(exclude_glob)?.or(config.exclude_glob);
 
         let args = Args::new(
             reference_string,
             search_path,
-            max_file_lines,
-            count,
-            min_similarity_ratio,
-            include_glob,
-            exclude_glob,
+            max_file_lines.or(config.max_file_lines),
+            count.or(config.count),
+            min_similarity_ratio.or(config.min_similarity_ratio),
+            include_glob.unwrap_or_default(),
+            exclude_glob.unwrap_or_default(),
         )
-        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        .and_then(|args| with_decoding(args, encoding, lossy, binary))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?
+        .with_archives(search_archives)
+        .with_text_extraction(extract_text)
+        .with_notebook_markdown(notebook_markdown);
 
         run_search(&args).map_err(|e| PyValueError::new_err(e.to_string()))
     }
 
-    fn extract_glob_arg(obj: Option<Bound<'_, PyAny>>) -> PyResult<Vec<String>> {
+    #[pyfunction]
+    #[pyo3(signature = (
+        search_path,
+        max_file_lines=None,
+        include_glob=None,
+        exclude_glob=None,
+        config_path=None,
+        profile=None,
+        search_archives=false,
+        encoding=None,
+        lossy=false,
+        binary=None,
+        extract_text=false,
+        notebook_markdown=false
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn list_candidates(
+        search_path: PathBuf,
+        max_file_lines: Option<usize>,
+        include_glob: Option<Bound<'_, PyAny>>,
+        exclude_glob: Option<Bound<'_, PyAny>>,
+        config_path: Option<PathBuf>,
+        profile: Option<String>,
+        search_archives: bool,
+        encoding: Option<String>,
+        lossy: bool,
+        binary: Option<String>,
+        extract_text: bool,
+        notebook_markdown: bool,
+    ) -> PyResult<Vec<Candidate>> {
+        let config = load_config(config_path, profile)?;
+        let include_glob = extract_glob_arg(include_glob)?.or(config.include_glob);
+        let exclude_glob = extract_glob_arg(exclude_glob)?.or(config.exclude_glob);
+
+        // Listing never scores, so the reference and the result limits are unused.
+        let args = Args::new(
+            String::new(),
+            search_path,
+            max_file_lines.or(config.max_file_lines),
+            None,
+            None,
+            include_glob.unwrap_or_default(),
+            exclude_glob.unwrap_or_default(),
+        )
+        .and_then(|args| with_decoding(args, encoding, lossy, binary))
+        .map_err(|e| PyValueError::new_err(e.to_string()))?
+        .with_archives(search_archives)
+        .with_text_extraction(extract_text)
+        .with_notebook_markdown(notebook_markdown);
+
+        super::list_candidates(&args).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Loads the config file passed explicitly from Python. Python never
+    /// discovers `.busca.toml` on its own, so a library call is not affected by
+    /// whatever directory the interpreter happens to run in.
+    fn load_config(config_path: Option<PathBuf>, profile: Option<String>) -> PyResult<Config> {
+        match config_path {
+            Some(path) => Config::from_path(&path, profile.as_deref())
+                .map_err(|e| PyValueError::new_err(e.to_string())),
+            None if profile.is_some() => Err(PyValueError::new_err(
+                "profile requires config_path to be set",
+            )),
+            None => Ok(Config::default()),
+        }
+    }
+
+    fn with_decoding(
+        args: Args,
+        encoding: Option<String>,
+        lossy: bool,
+        binary: Option<String>,
+    ) -> Result<Args, Error> {
+        let args = match encoding {
+            Some(label) => args.with_fallback_encoding(&label)?,
+            None => args,
+        };
+        let binary = match binary {
+            Some(name) => name.parse()?,
+            None => BinaryPolicy::default(),
+        };
+        Ok(args.with_lossy_decoding(lossy).with_binary_policy(binary))
+    }
+
+    /// `None` when the kwarg is omitted or `None`, so a config file can fill it.
+    fn extract_glob_arg(obj: Option<Bound<'_, PyAny>>) -> PyResult<Option<Vec<String>>> {
         let Some(obj) = obj else {
-            return Ok(Vec::new());
+            return Ok(None);
         };
         if obj.is_none() {
-            return Ok(Vec::new());
+            return Ok(None);
         }
         if let Ok(s) = obj.extract::<String>() {
-            return Ok(vec![s]);
+            return Ok(Some(vec![s]));
         }
-        obj.extract::<Vec<String>>().map_err(|e| {
+        obj.extract::<Vec<String>>().map(Some).map_err(|e| {
             PyValueError::new_err(format!(
                 "glob argument must be a string, a list of strings, or None: {e}"
             ))
@@ -218,13 +453,17 @@ mod busca_py {
 }
 
 #[non_exhaustive]
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Args {
     pub reference_string: String,
     pub search_path: PathBuf,
     pub max_file_lines: Option<usize>,
     pub(crate) include_glob: Option<Vec<Pattern>>,
     pub(crate) exclude_glob: Option<Vec<Pattern>>,
+    pub(crate) candidate_paths: Option<HashSet<PathBuf>>,
+    pub(crate) search_archives: bool,
+    pub(crate) decoding: decode::Decoding,
+    pub(crate) reference_bytes: Option<Vec<u8>>,
     pub count: Option<usize>,
     pub min_similarity_ratio: Option<f32>,
 }
@@ -265,12 +504,214 @@ impl Args {
             max_file_lines,
             include_glob,
             exclude_glob,
+            candidate_paths: None,
+            search_archives: false,
+            decoding: decode::Decoding::default(),
+            reference_bytes: None,
             count,
             min_similarity_ratio,
         })
     }
 }
 
+impl Args {
+    /// Points the search at `search_path`, keeping every other option, as when
+    /// rescoring just the files under one changed directory.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::SearchPathNotFound`] if `search_path` is neither an existing
+    /// file nor a directory.
+    pub fn with_search_path(mut self, search_path: PathBuf) -> Result<Self, Error> {
+        if !search_path.is_file() && !search_path.is_dir() {
+            return Err(Error::SearchPathNotFound(search_path));
+        }
+        self.search_path = search_path;
+        Ok(self)
+    }
+
+    /// The include globs as given to [`Args::new`]. Empty means no include
+    /// filter.
+    pub fn include_glob(&self) -> Vec<&str> {
+        glob_strs(&self.include_glob)
+    }
+
+    /// The exclude globs as given to [`Args::new`].
+    pub fn exclude_glob(&self) -> Vec<&str> {
+        glob_strs(&self.exclude_glob)
+    }
+
+    /// Restricts the search to `paths`, on top of the globs. Paths must be
+    /// spelled as the walk produces them: the search path joined with the
+    /// path below it, such as `src/lib.rs` for the search path `src`.
+    pub fn with_candidate_paths(mut self, paths: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.candidate_paths = Some(paths.into_iter().collect());
+        self
+    }
+
+    /// The paths set by [`Args::with_candidate_paths`], if any.
+    pub fn candidate_paths(&self) -> Option<&HashSet<PathBuf>> {
+        self.candidate_paths.as_ref()
+    }
+
+    /// Whether a search would consider `path`: it passes the globs and, when
+    /// they are set, is one of the candidate paths.
+    pub fn matches_path(&self, path: &Path) -> bool {
+        matches_filters(path, self)
+    }
+
+    /// Searches inside archives when `search_archives` is set: each UTF-8 file
+    /// in a `.zip`, `.tar`, `.tar.gz`, or `.tgz`, and the file compressed in a
+    /// standalone `.gz`, becomes a candidate named by a virtual path such as
+    /// `release.tar.gz!/src/main.py`. The globs apply to these virtual paths;
+    /// an archive excluded by a glob or left out of the candidate paths is not
+    /// opened. Off by default.
+    pub fn with_archives(mut self, search_archives: bool) -> Self {
+        self.search_archives = search_archives;
+        self
+    }
+
+    /// Whether [`Args::with_archives`] turned on archive traversal.
+    pub fn search_archives(&self) -> bool {
+        self.search_archives
+    }
+
+    /// Decodes candidates that have no byte order mark and are not valid UTF-8
+    /// with the encoding named by `label`, a WHATWG label such as `latin1`,
+    /// `windows-1252`, or `utf-16le`. Without a fallback such candidates are
+    /// skipped, unless lossy decoding is on.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::UnknownEncoding`] if `label` names no encoding.
+    pub fn with_fallback_encoding(mut self, label: &str) -> Result<Self, Error> {
+        let encoding = decode::encoding_for_label(label)
+            .ok_or_else(|| Error::UnknownEncoding(label.to_owned()))?;
+        self.decoding.fallback = Some(encoding);
+        Ok(self)
+    }
+
+    /// The canonical name of the encoding set by
+    /// [`Args::with_fallback_encoding`], if any.
+    pub fn fallback_encoding(&self) -> Option<&'static str> {
+        self.decoding.fallback.map(|encoding| encoding.name())
+    }
+
+    /// With `lossy`, malformed sequences in a candidate decode to U+FFFD
+    /// instead of skipping the candidate. Off by default.
+    pub fn with_lossy_decoding(mut self, lossy: bool) -> Self {
+        self.decoding.lossy = lossy;
+        self
+    }
+
+    /// Whether [`Args::with_lossy_decoding`] turned on lossy decoding.
+    pub fn lossy_decoding(&self) -> bool {
+        self.decoding.lossy
+    }
+
+    /// Sets what happens to binary candidates: files without a byte order mark
+    /// that have a NUL byte in their first 8000 bytes. They are skipped by
+    /// default. Under [`BinaryPolicy::Compare`] they are scored with
+    /// [`get_chunk_similarity_ratio`] against [`Args::reference_bytes`], are
+    /// not subject to `max_file_lines`, and are reported with an empty
+    /// `content` and the encoding `binary`.
+    pub fn with_binary_policy(mut self, policy: BinaryPolicy) -> Self {
+        self.decoding.binary = policy;
+        self
+    }
+
+    /// The policy set by [`Args::with_binary_policy`].
+    pub fn binary_policy(&self) -> BinaryPolicy {
+        self.decoding.binary
+    }
+
+    /// Converts documents to plain text before scoring them, chosen by
+    /// extension among the formats enabled by cargo features: HTML (`html`,
+    /// tags stripped and entities decoded), DOCX (`docx`), PDF (`pdf`), and
+    /// Jupyter notebooks (`ipynb`, see [`Args::with_notebook_markdown`]).
+    /// `FileComparison::content` then holds the text. Off by default, since a
+    /// `reference_string` given as is would be compared with the extracted
+    /// text; read a reference document with [`Args::with_reference_file`]
+    /// after turning it on, so it is extracted the same way.
+    pub fn with_text_extraction(mut self, extract_text: bool) -> Self {
+        self.decoding.extract_text = extract_text;
+        self
+    }
+
+    /// Whether [`Args::with_text_extraction`] turned on text extraction.
+    pub fn text_extraction(&self) -> bool {
+        self.decoding.extract_text
+    }
+
+    /// Compares the markdown cells of Jupyter notebooks along with their code
+    /// cells. Notebooks (`.ipynb`, with the `ipynb` cargo feature) are compared
+    /// by the source of their code cells only by default, leaving out outputs
+    /// and metadata, and `FileComparison::cell` names the best-matching cell.
+    pub fn with_notebook_markdown(mut self, notebook_markdown: bool) -> Self {
+        self.decoding.notebook_markdown = notebook_markdown;
+        self
+    }
+
+    /// Whether [`Args::with_notebook_markdown`] turned on markdown cells.
+    pub fn notebook_markdown(&self) -> bool {
+        self.decoding.notebook_markdown
+    }
+
+    /// Replaces `reference_string` with the file at `path`, decoded and
+    /// extracted as the candidates are: by its byte order mark, as UTF-8, or
+    /// with the fallback encoding and lossy decoding, and converted to text
+    /// under [`Args::with_text_extraction`]. The decoding options must be set
+    /// first.
+    /// Under [`BinaryPolicy::Compare`] the file's raw bytes also become
+    /// [`Args::reference_bytes`], and text that does not decode is decoded
+    /// lossily.
+    ///
+    /// # Errors
+    ///
+    /// - [`Error::ReferenceRead`] if the file cannot be read.
+    /// - [`Error::ReferenceDecode`] if it cannot be decoded or extracted under
+    ///   these options, or is binary and binary files are not compared.
+    pub fn with_reference_file(mut self, path: &Path) -> Result<Self, Error> {
+        let bytes = fs::read(path).map_err(|source| Error::ReferenceRead {
+            path: path.to_owned(),
+            source,
+        })?;
+        let reference_bytes =
+            (self.decoding.binary == BinaryPolicy::Compare).then(|| bytes.clone());
+        self.reference_string = self
+            .decoding
+            .decode_reference(path, bytes)
+            .ok_or_else(|| Error::ReferenceDecode(path.to_owned()))?;
+        self.reference_bytes = reference_bytes;
+        Ok(self)
+    }
+
+    /// Sets the bytes binary candidates are compared with, for a reference
+    /// that is itself binary. Text candidates are still compared with
+    /// `reference_string`.
+    pub fn with_reference_bytes(mut self, bytes: Vec<u8>) -> Self {
+        self.reference_bytes = Some(bytes);
+        self
+    }
+
+    /// The bytes set by [`Args::with_reference_bytes`], or else the UTF-8
+    /// bytes of `reference_string`.
+    pub fn reference_bytes(&self) -> &[u8] {
+        match &self.reference_bytes {
+            Some(bytes) => bytes,
+            None => self.reference_string.as_bytes(),
+        }
+    }
+}
+
+fn glob_strs(globs: &Option<Vec<Pattern>>) -> Vec<&str> {
+    globs
+        .iter()
+        .flatten()
+        .map(|pattern| pattern.as_str())
+        .collect()
+}
+
 fn parse_glob_vec(globs: Vec<String>) -> Result<Option<Vec<Pattern>>, Error> {
     if globs.is_empty() {
         return Ok(None);
@@ -324,20 +765,14 @@ where
         .collect::<Vec<_>>();
     let total = dir_entries.len() as u64;
     let done = AtomicU64::new(0);
+    let reference = ReferenceIndex::new(args);
 
     match args.count {
         None => {
             let mut file_comparisons: Vec<FileComparison> = dir_entries
                 .into_par_iter()
-                .filter_map(|dir_entry_result| {
-                    let out = match dir_entry_result {
-                        Ok(dir_entry) => compare_file(dir_entry, args, &args.reference_string),
-                        Err(_) => None,
-                    };
-                    let out = out.filter(|fc| match args.min_similarity_ratio {
-                        Some(min) => fc.similarity_ratio >= min,
-                        None => true,
-                    });
+                .flat_map_iter(|dir_entry_result| {
+                    let out = compare_entry(dir_entry_result, args, &reference);
                     let d = done.fetch_add(1, Ordering::Relaxed) + 1;
                     on_progress(d, total);
                     out
@@ -353,7 +788,6 @@ where
             Ok(file_comparisons)
         }
         Some(count) => {
-            let reference_index = ReferenceIndex::new(&args.reference_string);
             let floor = args.min_similarity_ratio.unwrap_or(0.0);
             let collected = dir_entries
                 .into_par_iter()
@@ -361,11 +795,15 @@ where
                 .fold(
                     || TopN::new(count, floor),
                     |mut heap, (walk_index, dir_entry_result)| {
-                        if let Ok(dir_entry) = dir_entry_result {
+                        let candidates = match dir_entry_result {
+                            Ok(dir_entry) => read_candidates(dir_entry, args),
+                            Err(_) => Vec::new(),
+                        };
+                        for (member_index, candidate) in candidates.into_iter().enumerate() {
                             if let Some(comparison) =
-                                score_candidate_bounded(dir_entry, args, &reference_index, &heap)
+                                score_candidate_bounded(candidate, args, &reference, &heap)
                             {
-                                heap.push(walk_index, comparison);
+                                heap.push((walk_index, member_index), comparison);
                             }
                         }
                         let d = done.fetch_add(1, Ordering::Relaxed) + 1;
@@ -380,30 +818,203 @@ where
     }
 }
 
+/// Walks `args.search_path` like [`run_search_with_progress`], but hands each
+/// comparison to `on_comparison` as soon as it is scored, in no particular
+/// order, instead of returning them ranked. `min_similarity_ratio` applies;
+/// `count` does not, since the best `count` are known only at the end.
+///
+/// # Errors
+///
+/// Returns [`Error`] if the search cannot complete, as
+/// [`run_search_with_progress`] does.
+pub fn run_search_streaming<P, C>(
+    args: &Args,
+    on_progress: P,
+    on_comparison: C,
+) -> Result<(), Error>
+where
+    P: Fn(u64, u64) + Send + Sync,
+    C: Fn(FileComparison) + Send + Sync,
+{
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    let dir_entries = WalkDir::new(&args.search_path)
+        .into_iter()
+        .collect::<Vec<_>>();
+    let total = dir_entries.len() as u64;
+    let done = AtomicU64::new(0);
+    let reference = ReferenceIndex::new(args);
+
+    dir_entries.into_par_iter().for_each(|dir_entry_result| {
+        compare_entry(dir_entry_result, args, &reference)
+            .into_iter()
+            .for_each(&on_comparison);
+        let d = done.fetch_add(1, Ordering::Relaxed) + 1;
+        on_progress(d, total);
+    });
+    Ok(())
+}
+
+/// Walks `args.search_path` and returns every candidate that a search with the
+/// same [`Args`] would score, in walk order, without scoring any of them. The
+/// include and exclude globs and `max_file_lines` apply exactly as they do in
+/// [`run_search`]; `reference_string`, `count`, and `min_similarity_ratio` are
+/// ignored.
+///
+/// # Errors
+///
+/// Returns [`Error`] if the walk cannot complete. As in [`run_search`],
+/// directory entries that cannot be read are skipped rather than propagated.
+pub fn list_candidates(args: &Args) -> Result<Vec<Candidate>, Error> {
+    let dir_entries = WalkDir::new(&args.search_path)
+        .into_iter()
+        .collect::<Vec<_>>();
+
+    let candidates = dir_entries
+        .into_par_iter()
+        .flat_map_iter(|dir_entry_result| match dir_entry_result {
+            Ok(dir_entry) => read_candidates(dir_entry, args),
+            Err(_) => Vec::new(),
+        })
+        .filter_map(|candidate| {
+            // Same str::lines() count and zero-line rule as compare_file, so the
+            // listing is exactly the set a search would score.
+            let line_count = candidate.line_count();
+            if line_count.is_some_and(|line_count| exceeds_line_limit(line_count, args)) {
+                return None;
+            }
+
+            Some(Candidate {
+                path: candidate.path,
+                line_count: line_count.unwrap_or(0),
+                size_bytes: candidate.size_bytes,
+            })
+        })
+        .collect();
+
+    Ok(candidates)
+}
+
+/// Reads the candidate at `path` as a search would, decoding it with the
+/// options of `args`, and scores it against `args.reference_string`. `path` is
+/// a file or, with [`Args::with_archives`], an archive member's virtual path
+/// from a search or [`list_candidates`]. The globs and `max_file_lines` are not
+/// applied. `None` when the candidate cannot be read or decoded.
+pub fn compare_candidate(args: &Args, path: &Path) -> Option<FileComparison> {
+    let candidate = match path.is_file() {
+        true => read_file_candidate(path.to_path_buf(), args.decoding),
+        false if args.search_archives => archive::read_member_at(path, args),
+        false => None,
+    }?;
+    let reference = ReferenceIndex::new(args);
+    let similarity_ratio = reference.score(&candidate.decoded);
+    Some(candidate.into_comparison(similarity_ratio, &reference))
+}
+
+#[cfg(test)]
+mod test_list_candidates {
+    use super::*;
+
+    fn args(max_file_lines: Option<usize>, include_glob: Vec<String>) -> Args {
+        Args::new(
+            String::new(),
+            PathBuf::from("sample_dir_hello_world"),
+            max_file_lines,
+            None,
+            None,
+            include_glob,
+            vec![],
+        )
+        .unwrap()
+    }
+
+    fn sorted_paths(candidates: &[Candidate]) -> Vec<String> {
+        let mut paths: Vec<String> = candidates
+            .iter()
+            .map(|c| c.path.display().to_string())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    #[test]
+    fn lists_the_files_a_search_would_score() {
+        let args = Args::new(
+            fs::read_to_string("sample_dir_hello_world/nested_dir/ref_B.py").unwrap(),
+            PathBuf::from("sample_dir_hello_world"),
+            Some(5000),
+            None,
+            None,
+            vec!["*.py".into()],
+            vec![],
+        )
+        .unwrap();
+        let listed = list_candidates(&args).unwrap();
+        let searched: Vec<FileComparison> = run_search(&args).unwrap();
+        let mut searched_paths: Vec<String> = searched
+            .iter()
+            .map(|c| c.path.display().to_string())
+            .collect();
+        searched_paths.sort();
+        assert_eq!(sorted_paths(&listed), searched_paths);
+    }
+
+    #[test]
+    fn reports_line_count_and_size() {
+        let listed = list_candidates(&args(None, vec!["*file_1.py".into()])).unwrap();
+        assert_eq!(
+            listed,
+            vec![Candidate {
+                path: PathBuf::from("sample_dir_hello_world/file_1.py"),
+                line_count: 9,
+                size_bytes: 140,
+            }]
+        );
+    }
+
+    #[test]
+    fn max_file_lines_filters_long_files() {
+        let listed = list_candidates(&args(Some(8), vec!["*.py".into()])).unwrap();
+        assert_eq!(
+            sorted_paths(&listed),
+            vec![
+                "sample_dir_hello_world/file_3.py",
+                "sample_dir_hello_world/nested_dir/sample_python_file_3.py",
+            ]
+        );
+    }
+}
+
 /// Scores one candidate for the bounded search. Skips the full `TextDiff::ratio`
 /// whenever a cheap upper bound proves the file cannot enter the current top-N.
 fn score_candidate_bounded(
-    dir_entry: DirEntry,
+    candidate: ReadCandidate,
     args: &Args,
     reference: &ReferenceIndex,
     heap: &TopN,
 ) -> Option<FileComparison> {
-    let (candidate_path, candidate_content) = read_candidate(dir_entry, args)?;
-
+    let content = match &candidate.decoded {
+        Decoded::Text { content, .. } | Decoded::Notebook { content, .. } => content,
+        Decoded::Binary(bytes) => {
+            // Shared chunks cannot cover more bytes than the shorter input.
+            if !heap.should_compute(real_quick_ratio(reference.bytes.len(), bytes.len())) {
+                return None;
+            }
+            let similarity_ratio = reference.score(&candidate.decoded);
+            return Some(candidate.into_comparison(similarity_ratio, reference));
+        }
+    };
     // max_file_lines uses str::lines().count(), identical to the unbounded path,
     // so behavior is preserved even for files with lone carriage returns (where
     // similar's tokenize_lines would count differently). This is why the double
     // line scan is not merged away here: the two counts use deliberately
     // different tokenizers.
-    if let Some(max_file_lines) = args.max_file_lines {
-        let num_candidate_lines = candidate_content.lines().count();
-        if (num_candidate_lines > max_file_lines) || (num_candidate_lines == 0) {
-            return None;
-        }
+    if args.max_file_lines.is_some() && exceeds_line_limit(content.lines().count(), args) {
+        return None;
     }
 
     // The multiset and token count for the upper bounds use similar's tokenizer.
-    let (cand_counts, cand_len) = line_counts(&candidate_content);
+    let (cand_counts, cand_len) = line_counts(content);
 
     if !heap.should_compute(real_quick_ratio(reference.len, cand_len)) {
         return None;
@@ -414,13 +1025,9 @@ fn score_candidate_bounded(
         return None;
     }
 
-    let similarity_ratio = get_similarity_ratio(&args.reference_string, &candidate_content);
+    let similarity_ratio = get_similarity_ratio(reference.text, content);
 
-    Some(FileComparison {
-        path: candidate_path,
-        similarity_ratio,
-        content: candidate_content,
-    })
+    Some(candidate.into_comparison(similarity_ratio, reference))
 }
 #[cfg(test)]
 mod test_run_search {
@@ -434,6 +1041,10 @@ mod test_run_search {
             max_file_lines: Some(5000),
             include_glob: Some(vec![Pattern::new("*.py").unwrap()]),
             exclude_glob: Some(vec![Pattern::new("*.yml").unwrap()]),
+            candidate_paths: None,
+            search_archives: false,
+            decoding: decode::Decoding::default(),
+            reference_bytes: None,
             count: Some(2),
             min_similarity_ratio: None,
         }
@@ -448,11 +1059,15 @@ mod test_run_search {
                 path: PathBuf::from("sample_dir_hello_world/nested_dir/ref_B.py"),
                 similarity_ratio: 1.0,
                 content: fs::read_to_string("sample_dir_hello_world/nested_dir/ref_B.py").unwrap(),
+                encoding: "UTF-8",
+                cell: None,
             },
             FileComparison {
                 path: PathBuf::from("sample_dir_hello_world/file_1.py"),
                 similarity_ratio: 2.0 / 9.0,
                 content: fs::read_to_string("sample_dir_hello_world/file_1.py").unwrap(),
+                encoding: "UTF-8",
+                cell: None,
             },
         ];
         assert_eq!(run_search(&valid_args).unwrap(), expected);
@@ -468,10 +1083,26 @@ mod test_run_search {
             similarity_ratio: 0.0,
             content: fs::read_to_string("sample_dir_hello_world/nested_dir/sample_json.json")
                 .unwrap(),
+            encoding: "UTF-8",
+            cell: None,
         }];
         assert_eq!(run_search(&valid_args).unwrap(), expected);
     }
 
+    #[test]
+    fn candidate_paths() {
+        let valid_args = get_valid_args().with_candidate_paths([
+            PathBuf::from("sample_dir_hello_world/file_1.py"),
+            PathBuf::from("sample_dir_hello_world/missing.py"),
+            PathBuf::from("sample_dir_hello_world/nested_dir/sample_json.json"),
+        ]);
+
+        // The JSON file is a candidate path but fails the include glob.
+        let results = run_search(&valid_args).unwrap();
+        let paths: Vec<&Path> = results.iter().map(|fc| fc.path.as_path()).collect();
+        assert_eq!(paths, [Path::new("sample_dir_hello_world/file_1.py")]);
+    }
+
     #[test]
     fn exclude_glob() {
         let mut valid_args = get_valid_args();
@@ -482,11 +1113,15 @@ mod test_run_search {
                 path: PathBuf::from("sample_dir_hello_world/nested_dir/ref_B.py"),
                 similarity_ratio: 1.0,
                 content: fs::read_to_string("sample_dir_hello_world/nested_dir/ref_B.py").unwrap(),
+                encoding: "UTF-8",
+                cell: None,
             },
             FileComparison {
                 path: PathBuf::from("sample_dir_hello_world/file_1.py"),
                 similarity_ratio: 2.0 / 9.0,
                 content: fs::read_to_string("sample_dir_hello_world/file_1.py").unwrap(),
+                encoding: "UTF-8",
+                cell: None,
             },
         ];
         assert_eq!(run_search(&valid_args).unwrap(), expected);
@@ -500,6 +1135,10 @@ mod test_run_search {
             max_file_lines: Some(5000),
             include_glob: None,
             exclude_glob: None,
+            candidate_paths: None,
+            search_archives: false,
+            decoding: decode::Decoding::default(),
+            reference_bytes: None,
             count,
             min_similarity_ratio: None,
         }
@@ -616,6 +1255,10 @@ mod test_run_search {
             max_file_lines: Some(5000),
             include_glob: None,
             exclude_glob: None,
+            candidate_paths: None,
+            search_archives: false,
+            decoding: decode::Decoding::default(),
+            reference_bytes: None,
             count,
             min_similarity_ratio: None,
         };
@@ -634,73 +1277,475 @@ mod test_run_search {
             assert_eq!(bounded, expected, "tie mismatch at count {n}");
         }
     }
+
+    #[test]
+    fn archives_are_searched_only_when_enabled() {
+        let dir = TempDir::new("archives");
+        fs::write(dir.path.join("plain.txt"), "a\nb\nx\ny\n").unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, content) in [
+            ("src/tie_1.txt", "a\nb\nx\ny\n"),
+            ("src/exact.txt", "a\nb\nc\nd\n"),
+            ("src/tie_2.txt", "a\nb\nx\ny\n"),
+            ("skip.md", "a\n"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        let archive = dir.path.join("release.tar");
+        fs::write(&archive, builder.into_inner().unwrap()).unwrap();
+
+        let args = |count, search_archives| {
+            Args::new(
+                "a\nb\nc\nd\n".to_string(),
+                dir.path.clone(),
+                None,
+                count,
+                None,
+                vec!["*.txt".to_string()],
+                vec![],
+            )
+            .unwrap()
+            .with_archives(search_archives)
+        };
+        let member = |name: &str| PathBuf::from(format!("{}!/{name}", archive.display()));
+
+        let without = run_search(&args(None, false)).unwrap();
+        assert_eq!(without.len(), 1);
+
+        let reference = run_search(&args(None, true)).unwrap();
+        let paths: Vec<PathBuf> = reference.iter().map(|fc| fc.path.clone()).collect();
+        assert_eq!(paths[0], member("src/exact.txt"));
+        assert_eq!(paths.len(), 4);
+        assert!(!paths.contains(&member("skip.md")));
+        for n in [1usize, 2, 3, 4] {
+            let mut expected = reference.clone();
+            expected.truncate(n);
+            assert_eq!(run_search(&args(Some(n), true)).unwrap(), expected);
+            let cache = ContentCache::new();
+            assert_eq!(
+                run_search_cached(&args(Some(n), true), &cache).unwrap(),
+                expected
+            );
+        }
+        assert_eq!(list_candidates(&args(None, true)).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn non_utf8_candidates_need_a_fallback_or_lossy_decoding() {
+        let dir = TempDir::new("encodings");
+        fs::write(dir.path.join("latin1.txt"), b"caf\xE9\nbar\n").unwrap();
+        fs::write(dir.path.join("utf16.txt"), b"\xFF\xFEc\0a\0f\0\xE9\0\n\0").unwrap();
+
+        let args = Args::new(
+            "caf\u{e9}\nbar\n".to_string(),
+            dir.path.clone(),
+            None,
+            None,
+            None,
+            vec![],
+            vec![],
+        )
+        .unwrap();
+        let encodings = |found: Vec<FileComparison>| -> Vec<(String, &'static str)> {
+            let mut encodings: Vec<_> = found
+                .into_iter()
+                .map(|fc| {
+                    (
+                        fc.path.file_name().unwrap().to_string_lossy().into_owned(),
+                        fc.encoding,
+                    )
+                })
+                .collect();
+            encodings.sort();
+            encodings
+        };
+
+        let cache = ContentCache::snapshot();
+        let plain = run_search(&args).unwrap();
+        assert_eq!(plain.len(), 1);
+        assert_eq!(plain[0].content, "caf\u{e9}\n");
+        assert_eq!(run_search_cached(&args, &cache).unwrap(), plain);
+
+        let args = args.with_fallback_encoding("latin1").unwrap();
+        assert_eq!(args.fallback_encoding(), Some("windows-1252"));
+        let found = run_search(&args).unwrap();
+        assert_eq!(found[0].similarity_ratio, 1.0);
+        assert_eq!(
+            encodings(found.clone()),
+            [
+                ("latin1.txt".to_owned(), "windows-1252"),
+                ("utf16.txt".to_owned(), "UTF-16LE")
+            ]
+        );
+        // The cache decodes again when the options change.
+        assert_eq!(run_search_cached(&args, &cache).unwrap(), found);
+
+        assert!(matches!(
+            args.with_fallback_encoding("klingon"),
+            Err(Error::UnknownEncoding(label)) if label == "klingon"
+        ));
+    }
+
+    #[test]
+    fn candidate_sizes_are_measured_before_decoding() {
+        let dir = TempDir::new("sizes");
+        let utf16 = b"\xFF\xFEc\0a\0f\0\xE9\0\n\0";
+        fs::write(dir.path.join("utf16.txt"), utf16).unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(utf16.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "member.txt", &utf16[..])
+            .unwrap();
+        fs::write(dir.path.join("bundle.tar"), builder.into_inner().unwrap()).unwrap();
+
+        let args = Args::new(
+            String::new(),
+            dir.path.clone(),
+            None,
+            None,
+            None,
+            vec!["*.txt".to_string()],
+            vec![],
+        )
+        .unwrap()
+        .with_archives(true);
+        let listed = list_candidates(&args).unwrap();
+        assert_eq!(listed.len(), 2);
+        for candidate in listed {
+            // "café\n" is six bytes once decoded, but twelve in UTF-16.
+            assert_eq!(candidate.line_count, 1, "{}", candidate.path.display());
+            assert_eq!(candidate.size_bytes, 12, "{}", candidate.path.display());
+        }
+    }
+
+    #[cfg(feature = "ipynb")]
+    #[test]
+    fn notebooks_report_their_best_matching_cell() {
+        let dir = TempDir::new("notebooks");
+        let cell = |cell_type: &str, source: &str| {
+            format!(r#"{{"cell_type": "{cell_type}", "metadata": {{}}, "source": {source:?}}}"#)
+        };
+        let notebook =
+            |cells: &[String]| format!(r#"{{"cells": [{}], "nbformat": 4}}"#, cells.join(","));
+        fs::write(
+            dir.path.join("analysis.ipynb"),
+            notebook(&[
+                cell("markdown", "# Plot\n"),
+                cell("code", "import pandas as pd\n"),
+                cell("code", "df = pd.read_csv(path)\ndf.plot()\n"),
+            ]),
+        )
+        .unwrap();
+        fs::write(
+            dir.path.join("other.ipynb"),
+            notebook(&[cell("code", "print(1)\n")]),
+        )
+        .unwrap();
+        fs::write(dir.path.join("script.py"), "df = pd.read_csv(path)\n").unwrap();
+
+        let args = |count| {
+            Args::new(
+                "df = pd.read_csv(path)\ndf.plot()\n".to_string(),
+                dir.path.clone(),
+                None,
+                count,
+                None,
+                vec![],
+                vec![],
+            )
+            .unwrap()
+            .with_text_extraction(true)
+        };
+        let found = run_search(&args(None)).unwrap();
+        let cells: Vec<(String, Option<usize>)> = found
+            .iter()
+            .map(|fc| {
+                (
+                    fc.path.file_name().unwrap().to_string_lossy().into_owned(),
+                    fc.cell,
+                )
+            })
+            .collect();
+        assert_eq!(
+            cells,
+            [
+                ("analysis.ipynb".to_owned(), Some(2)),
+                ("script.py".to_owned(), None),
+                ("other.ipynb".to_owned(), Some(0)),
+            ]
+        );
+        assert_eq!(
+            found[0].content,
+            "import pandas as pd\ndf = pd.read_csv(path)\ndf.plot()\n"
+        );
+        for n in [1usize, 2, 3] {
+            let mut expected = found.clone();
+            expected.truncate(n);
+            assert_eq!(run_search(&args(Some(n))).unwrap(), expected);
+            assert_eq!(
+                run_search_cached(&args(Some(n)), &ContentCache::new()).unwrap(),
+                expected
+            );
+        }
+        let with_markdown = run_search(&args(None).with_notebook_markdown(true)).unwrap();
+        assert!(with_markdown[0].content.starts_with("# Plot\n"));
+        assert_eq!(with_markdown[0].cell, Some(2));
+    }
+
+    #[test]
+    fn binary_candidates_are_skipped_or_compared_byte_by_byte() {
+        let noise = |len: usize, mut state: u32| -> Vec<u8> {
+            let mut bytes: Vec<u8> = (0..len)
+                .map(|_| {
+                    state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                    (state >> 24) as u8
+                })
+                .collect();
+            bytes[0] = 0;
+            bytes
+        };
+        let reference = noise(32 * 1024, 1);
+        let mut patched = reference.clone();
+        patched.splice(20_000..20_000, *b"patch");
+
+        let dir = TempDir::new("binary");
+        fs::write(dir.path.join("same.bin"), &reference).unwrap();
+        fs::write(dir.path.join("patched.bin"), &patched).unwrap();
+        fs::write(dir.path.join("other.bin"), noise(32 * 1024, 2)).unwrap();
+        fs::write(dir.path.join("text.txt"), "a\nb\n").unwrap();
+
+        let args = |count, binary| {
+            Args::new(
+                "a\nb\n".to_string(),
+                dir.path.clone(),
+                Some(1),
+                count,
+                None,
+                vec![],
+                vec![],
+            )
+            .unwrap()
+            .with_binary_policy(binary)
+            .with_reference_bytes(reference.clone())
+        };
+        let names = |found: &[FileComparison]| -> Vec<String> {
+            found
+                .iter()
+                .map(|fc| fc.path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+
+        // The line limit of 1 drops text.txt but not the binary files.
+        assert!(run_search(&args(None, BinaryPolicy::Skip))
+            .unwrap()
+            .is_empty());
+        let compared = run_search(&args(None, BinaryPolicy::Compare)).unwrap();
+        assert_eq!(names(&compared), ["same.bin", "patched.bin", "other.bin"]);
+        assert_eq!(compared[0].similarity_ratio, 1.0);
+        assert!(compared[1].similarity_ratio > 0.9);
+        assert_eq!(compared[2].similarity_ratio, 0.0);
+        assert_eq!(compared[0].encoding, "binary");
+        assert_eq!(compared[0].content, "");
+        for n in [1usize, 2, 3] {
+            let mut expected = compared.clone();
+            expected.truncate(n);
+            let args = args(Some(n), BinaryPolicy::Compare);
+            assert_eq!(run_search(&args).unwrap(), expected);
+            assert_eq!(
+                run_search_cached(&args, &ContentCache::new()).unwrap(),
+                expected
+            );
+        }
+        let listed = list_candidates(&args(None, BinaryPolicy::Compare)).unwrap();
+        assert_eq!(listed.len(), 3);
+        assert!(listed.iter().all(|candidate| candidate.line_count == 0));
+        assert_eq!(
+            get_chunk_similarity_ratio(&reference, &patched),
+            compared[1].similarity_ratio
+        );
+    }
+}
+
+/// Applies the file-type and glob filters and reads the candidates' contents:
+/// the file itself, or with [`Args::search_archives`] the members of an
+/// archive. Empty when the entry is filtered out or cannot be decoded.
+fn read_candidates(dir_entry: DirEntry, args: &Args) -> Vec<ReadCandidate> {
+    if !is_file_entry(&dir_entry) {
+        return Vec::new();
+    }
+    read_path_candidates(dir_entry.into_path(), args)
+}
+
+/// [`read_candidates`] for a path already known to be a file.
+pub(crate) fn read_path_candidates(path: PathBuf, args: &Args) -> Vec<ReadCandidate> {
+    if args.search_archives && archive::is_archive(&path) {
+        if !matches_candidate_paths(&path, args) || matches_exclude_glob(&path, args) {
+            return Vec::new();
+        }
+        return archive::read_members(&path, args);
+    }
+    if !matches_filters(&path, args) {
+        return Vec::new();
+    }
+    read_file_candidate(path, args.decoding)
+        .into_iter()
+        .collect()
+}
+
+/// A candidate read for scoring: its path, its decoded content, and the size
+/// of the bytes it was decoded from.
+#[derive(Debug)]
+pub(crate) struct ReadCandidate {
+    pub(crate) path: PathBuf,
+    pub(crate) decoded: Decoded,
+    pub(crate) size_bytes: u64,
 }
 
-/// Applies the file-type and glob filters and reads the candidate's content.
-/// Returns `None` when the entry is filtered out or cannot be read as UTF-8.
-fn read_candidate(dir_entry: DirEntry, args: &Args) -> Option<(PathBuf, String)> {
+impl ReadCandidate {
+    /// The `str::lines()` count `max_file_lines` applies to. `None` for a
+    /// binary candidate, which the limit does not apply to.
+    fn line_count(&self) -> Option<usize> {
+        self.decoded.text().map(|content| content.lines().count())
+    }
+
+    fn into_comparison(self, similarity_ratio: f32, reference: &ReferenceIndex) -> FileComparison {
+        let (content, encoding, cell) = match self.decoded {
+            Decoded::Text { content, encoding } => (content, encoding, None),
+            Decoded::Notebook { content, cells } => {
+                let cell = best_cell(reference.text, &content, &cells);
+                (content, UTF_8, cell)
+            }
+            Decoded::Binary(_) => (String::new(), decode::BINARY, None),
+        };
+        FileComparison {
+            path: self.path,
+            similarity_ratio,
+            content,
+            encoding,
+            cell,
+        }
+    }
+}
+
+/// Whether a walked entry is a file, or a symlink to one.
+fn is_file_entry(dir_entry: &DirEntry) -> bool {
     // file_type() comes from the directory read at no extra syscall, where
     // is_file() would re-stat every candidate. A symlink reports as neither file
     // nor directory, so follow it with is_file() to match the old behavior.
     let file_type = dir_entry.file_type();
-    let is_file = file_type.is_file() || (file_type.is_symlink() && dir_entry.path().is_file());
-    if !is_file {
-        return None;
-    }
+    file_type.is_file() || (file_type.is_symlink() && dir_entry.path().is_file())
+}
 
-    let candidate_path = dir_entry.into_path();
+/// Whether `path` is one of the candidate paths (when they are set), passes
+/// the include globs (when any are set), and matches no exclude glob.
+fn matches_filters(path: &Path, args: &Args) -> bool {
+    matches_candidate_paths(path, args) && matches_globs(path, args)
+}
+
+fn matches_candidate_paths(path: &Path, args: &Args) -> bool {
+    match &args.candidate_paths {
+        Some(candidate_paths) => candidate_paths.contains(path),
+        None => true,
+    }
+}
 
+/// Whether `path` passes the include globs (when any are set) and matches no
+/// exclude glob.
+fn matches_globs(path: &Path, args: &Args) -> bool {
     if let Some(include_glob) = &args.include_glob {
-        let matches_any_include = include_glob
-            .iter()
-            .any(|glob| glob.matches_path(candidate_path.as_path()));
-        if !matches_any_include {
-            return None;
+        if !include_glob.iter().any(|glob| glob.matches_path(path)) {
+            return false;
         }
     }
+    !matches_exclude_glob(path, args)
+}
 
-    if let Some(exclude_glob) = &args.exclude_glob {
-        let matches_any_exclude = exclude_glob
-            .iter()
-            .any(|glob| glob.matches_path(candidate_path.as_path()));
-        if matches_any_exclude {
-            return None;
-        }
+fn matches_exclude_glob(path: &Path, args: &Args) -> bool {
+    match &args.exclude_glob {
+        Some(exclude_glob) => exclude_glob.iter().any(|glob| glob.matches_path(path)),
+        None => false,
     }
+}
 
-    let candidate_content = read_file(&candidate_path)?;
-    Some((candidate_path, candidate_content))
+/// Whether `max_file_lines` excludes a file with this many lines, counted with
+/// `str::lines()`. Empty files are excluded whenever a limit is set.
+fn exceeds_line_limit(line_count: usize, args: &Args) -> bool {
+    match args.max_file_lines {
+        Some(max_file_lines) => (line_count > max_file_lines) || (line_count == 0),
+        None => false,
+    }
 }
 
 pub(crate) fn compare_file(
     dir_entry: DirEntry,
     args: &Args,
-    reference_string: &str,
-) -> Option<FileComparison> {
-    let (candidate_path, candidate_content) = read_candidate(dir_entry, args)?;
+    reference: &ReferenceIndex,
+) -> Vec<FileComparison> {
+    read_candidates(dir_entry, args)
+        .into_iter()
+        .filter(|candidate| {
+            args.max_file_lines.is_none()
+                || !candidate
+                    .line_count()
+                    .is_some_and(|line_count| exceeds_line_limit(line_count, args))
+        })
+        .map(|candidate| {
+            let similarity_ratio = reference.score(&candidate.decoded);
+            candidate.into_comparison(similarity_ratio, reference)
+        })
+        .collect()
+}
 
-    if let Some(max_file_lines) = args.max_file_lines {
-        let num_candidate_lines = candidate_content.lines().count();
-        if (num_candidate_lines > max_file_lines) || (num_candidate_lines == 0) {
-            return None;
-        }
-    }
+/// The comparisons of one walked entry that reach `args.min_similarity_ratio`.
+fn compare_entry(
+    dir_entry_result: walkdir::Result<DirEntry>,
+    args: &Args,
+    reference: &ReferenceIndex,
+) -> Vec<FileComparison> {
+    let mut file_comparisons = match dir_entry_result {
+        Ok(dir_entry) => compare_file(dir_entry, args, reference),
+        Err(_) => Vec::new(),
+    };
+    file_comparisons.retain(|fc| match args.min_similarity_ratio {
+        Some(min) => fc.similarity_ratio >= min,
+        None => true,
+    });
+    file_comparisons
+}
 
-    let similarity_ratio = get_similarity_ratio(reference_string, &candidate_content);
+/// Reads and decodes a file. `None` when it cannot be decoded under `decoding`,
+/// is binary and not compared, or cannot be read.
+fn read_file(candidate_path: &Path, decoding: decode::Decoding) -> Option<Decoded> {
+    decoding.decode(candidate_path, read_bytes(candidate_path)?)
+}
 
-    Some(FileComparison {
-        path: candidate_path,
-        similarity_ratio,
-        content: candidate_content,
+/// Reads and decodes a file as [`read_file`] does, keeping its size on disk.
+fn read_file_candidate(path: PathBuf, decoding: decode::Decoding) -> Option<ReadCandidate> {
+    let bytes = read_bytes(&path)?;
+    let size_bytes = bytes.len() as u64;
+    let decoded = decoding.decode(&path, bytes)?;
+    Some(ReadCandidate {
+        path,
+        decoded,
+        size_bytes,
     })
 }
 
-fn read_file(candidate_path: &Path) -> Option<String> {
-    match fs::read_to_string(candidate_path) {
-        Ok(content) => Some(content),
-        Err(error) if error.kind() == std::io::ErrorKind::InvalidData => None,
+/// The bytes of a file, or `None` after a warning when it cannot be read.
+fn read_bytes(path: &Path) -> Option<Vec<u8>> {
+    match fs::read(path) {
+        Ok(bytes) => Some(bytes),
         Err(error) => {
-            eprintln!("busca: skipping {}: {}", candidate_path.display(), error);
+            eprintln!("busca: skipping {}: {}", path.display(), error);
             None
         }
     }
@@ -732,6 +1777,37 @@ pub fn get_similarity_ratio(reference_string: &str, candidate_content: &str) ->
     diff.ratio()
 }
 
+/// The index of the notebook cell whose source is most similar to the
+/// reference, the first one on ties. `None` for a notebook with no compared
+/// cells.
+fn best_cell(reference: &str, content: &str, cells: &[NotebookCell]) -> Option<usize> {
+    let mut best: Option<(usize, f32)> = None;
+    for cell in cells {
+        let ratio = get_similarity_ratio(reference, &content[cell.source.clone()]);
+        if best.is_none_or(|(_, best_ratio)| ratio > best_ratio) {
+            best = Some((cell.index, ratio));
+        }
+    }
+    best.map(|(index, _)| index)
+}
+
+/// Returns the byte-level similarity of two inputs, used for binary candidates
+/// under [`BinaryPolicy::Compare`]. Each input is cut into content-defined
+/// chunks, and the ratio is twice the bytes in chunks they share over their
+/// total length, so an insertion or deletion only affects the chunks around
+/// it.
+///
+/// # Examples
+///
+/// ```
+/// let original: Vec<u8> = (0..20_000u32).map(|i| (i * 7919 % 251) as u8).collect();
+/// assert_eq!(busca::get_chunk_similarity_ratio(&original, &original), 1.0);
+/// assert_eq!(busca::get_chunk_similarity_ratio(&original, &[]), 0.0);
+/// ```
+pub fn get_chunk_similarity_ratio(reference: &[u8], candidate: &[u8]) -> f32 {
+    Fingerprint::new(reference).ratio(&Fingerprint::new(candidate))
+}
+
 /// Builds a line-count multiset using `similar`'s own line tokenizer, so the
 /// counts share the exact tokenization (and denominator) `TextDiff::from_lines`
 /// uses. Returns each distinct line's count and the total token count.
@@ -746,16 +1822,44 @@ fn line_counts(s: &str) -> (HashMap<&str, u32>, usize) {
 }
 
 /// The reference file's line multiset and token count, built once and shared
-/// across every candidate comparison.
+/// across every candidate comparison, and its chunk fingerprint, built on the
+/// first binary candidate.
 struct ReferenceIndex<'a> {
+    text: &'a str,
     counts: HashMap<&'a str, u32>,
     len: usize,
+    bytes: &'a [u8],
+    fingerprint: OnceLock<Fingerprint>,
 }
 
 impl<'a> ReferenceIndex<'a> {
-    fn new(reference: &'a str) -> Self {
-        let (counts, len) = line_counts(reference);
-        Self { counts, len }
+    fn new(args: &'a Args) -> Self {
+        Self::from_parts(&args.reference_string, args.reference_bytes())
+    }
+
+    fn from_parts(text: &'a str, bytes: &'a [u8]) -> Self {
+        let (counts, len) = line_counts(text);
+        Self {
+            text,
+            counts,
+            len,
+            bytes,
+            fingerprint: OnceLock::new(),
+        }
+    }
+
+    fn fingerprint(&self) -> &Fingerprint {
+        self.fingerprint
+            .get_or_init(|| Fingerprint::new(self.bytes))
+    }
+
+    /// The similarity of a candidate to the reference: `TextDiff::ratio` for
+    /// text, chunk similarity for binary.
+    fn score(&self, decoded: &Decoded) -> f32 {
+        match decoded {
+            Decoded::Binary(bytes) => self.fingerprint().ratio(&Fingerprint::new(bytes)),
+            decoded => get_similarity_ratio(self.text, decoded.text().unwrap_or_default()),
+        }
     }
 }
 
@@ -772,42 +1876,59 @@ fn real_quick_ratio(ref_len: usize, cand_len: usize) -> f32 {
 /// Line-multiset upper bound on `similar`'s ratio: matches cannot exceed the
 /// multiset intersection of the two line sequences. Tighter than
 /// `real_quick_ratio` and always less than or equal to it.
-fn quick_ratio_bound(
-    ref_counts: &HashMap<&str, u32>,
+/// Generic over the key types so that a [`ContentCache`] entry's owned tokens
+/// can be compared with the reference's borrowed ones.
+fn quick_ratio_bound<R, C>(
+    ref_counts: &HashMap<R, u32>,
     ref_len: usize,
-    cand_counts: &HashMap<&str, u32>,
+    cand_counts: &HashMap<C, u32>,
     cand_len: usize,
-) -> f32 {
+) -> f32
+where
+    R: Borrow<str> + Hash + Eq,
+    C: Borrow<str> + Hash + Eq,
+{
     let total = ref_len + cand_len;
     if total == 0 {
         return 1.0;
     }
     // Iterate the smaller map; the intersection sum is symmetric.
-    let (small, large) = if cand_counts.len() <= ref_counts.len() {
-        (cand_counts, ref_counts)
+    let matches = if cand_counts.len() <= ref_counts.len() {
+        multiset_intersection(cand_counts, ref_counts)
     } else {
-        (ref_counts, cand_counts)
+        multiset_intersection(ref_counts, cand_counts)
     };
+    2.0 * matches as f32 / total as f32
+}
+
+fn multiset_intersection<S, L>(small: &HashMap<S, u32>, large: &HashMap<L, u32>) -> u32
+where
+    S: Borrow<str> + Hash + Eq,
+    L: Borrow<str> + Hash + Eq,
+{
     let mut matches = 0u32;
     for (token, &count) in small {
-        if let Some(&other) = large.get(token) {
+        if let Some(&other) = large.get(token.borrow()) {
             matches += count.min(other);
         }
     }
-    2.0 * matches as f32 / total as f32
+    matches
 }
 
+/// A candidate's position in the walk: its entry's index, then its index
+/// among the members of an archive (`0` for a file).
+type WalkIndex = (usize, usize);
+
 /// One result inside a `TopN` heap. Ordered so the "greatest" entry is the one
 /// to evict first: lowest ratio, and for ties the highest walk index.
 struct HeapEntry {
-    walk_index: usize,
+    walk_index: WalkIndex,
     comparison: FileComparison,
 }
 
 impl PartialEq for HeapEntry {
     fn eq(&self, other: &Self) -> bool {
-        self.walk_index == other.walk_index
-            && self.comparison.similarity_ratio == other.comparison.similarity_ratio
+        self.cmp(other) == std::cmp::Ordering::Equal
     }
 }
 impl Eq for HeapEntry {}
@@ -867,7 +1988,7 @@ impl TopN {
         }
     }
 
-    fn push(&mut self, walk_index: usize, comparison: FileComparison) {
+    fn push(&mut self, walk_index: WalkIndex, comparison: FileComparison) {
         self.push_entry(HeapEntry {
             walk_index,
             comparison,
@@ -918,11 +2039,23 @@ mod test_compare_file {
             max_file_lines: Some(5000),
             include_glob: Some(vec![Pattern::new("*.py").unwrap()]),
             exclude_glob: Some(vec![Pattern::new("*.yml").unwrap()]),
+            candidate_paths: None,
+            search_archives: false,
+            decoding: decode::Decoding::default(),
+            reference_bytes: None,
             count: Some(8),
             min_similarity_ratio: None,
         }
     }
 
+    fn compare(dir_entry: DirEntry, args: &Args, reference: &str) -> Vec<FileComparison> {
+        compare_file(
+            dir_entry,
+            args,
+            &ReferenceIndex::from_parts(reference, reference.as_bytes()),
+        )
+    }
+
     #[test]
     fn skip_directory() {
         let valid_args = get_valid_args();
@@ -937,9 +2070,9 @@ mod test_compare_file {
             .unwrap()
             .unwrap();
 
-        let file_comparison = compare_file(dir_entry_result, &valid_args, &reference_string);
+        let file_comparison = compare(dir_entry_result, &valid_args, &reference_string);
 
-        assert_eq!(file_comparison, None);
+        assert_eq!(file_comparison, vec![]);
     }
 
     #[test]
@@ -956,15 +2089,17 @@ mod test_compare_file {
             .unwrap()
             .unwrap();
 
-        let file_comparison = compare_file(dir_entry_result, &valid_args, &reference_string);
+        let file_comparison = compare(dir_entry_result, &valid_args, &reference_string);
 
         assert_eq!(
             file_comparison,
-            Some(FileComparison {
+            vec![FileComparison {
                 path: PathBuf::from(file_path_str),
                 similarity_ratio: 1.0,
                 content: reference_string,
-            })
+                encoding: "UTF-8",
+                cell: None,
+            }]
         );
     }
 
@@ -984,15 +2119,17 @@ mod test_compare_file {
             .unwrap()
             .unwrap();
 
-        let file_comparison = compare_file(dir_entry_result, &valid_args, &reference_string);
+        let file_comparison = compare(dir_entry_result, &valid_args, &reference_string);
 
         assert_eq!(
             file_comparison,
-            Some(FileComparison {
+            vec![FileComparison {
                 path: PathBuf::from(candidate_path_str),
                 similarity_ratio: 3.0 / 7.0,
                 content: fs::read_to_string(candidate_path_str).unwrap(),
-            })
+                encoding: "UTF-8",
+                cell: None,
+            }]
         );
     }
 
@@ -1009,15 +2146,17 @@ mod test_compare_file {
             .unwrap()
             .unwrap();
 
-        let file_comparison = compare_file(dir_entry_result, &valid_args, "");
+        let file_comparison = compare(dir_entry_result, &valid_args, "");
 
         assert_eq!(
             file_comparison,
-            Some(FileComparison {
+            vec![FileComparison {
                 path: PathBuf::from(candidate_path_str),
                 similarity_ratio: 0.0,
                 content: fs::read_to_string(candidate_path_str).unwrap(),
-            })
+                encoding: "UTF-8",
+                cell: None,
+            }]
         );
     }
 
@@ -1034,9 +2173,9 @@ mod test_compare_file {
             .unwrap()
             .unwrap();
 
-        let file_comparison = compare_file(dir_entry_result, &valid_args, "");
+        let file_comparison = compare(dir_entry_result, &valid_args, "");
 
-        assert_eq!(file_comparison, None);
+        assert_eq!(file_comparison, vec![]);
     }
 }
 
@@ -1110,6 +2249,39 @@ mod test_run_search_with_progress {
         assert!(!result.is_empty());
         assert!(counter.load(Ordering::SeqCst) > 0);
     }
+
+    #[test]
+    fn streaming_yields_the_comparisons_of_a_search() {
+        let args = Args::new(
+            fs::read_to_string("sample_dir_hello_world/nested_dir/ref_B.py").unwrap(),
+            PathBuf::from("sample_dir_hello_world"),
+            Some(5000),
+            None,
+            Some(0.1),
+            vec!["*.py".into()],
+            vec![],
+        )
+        .unwrap();
+
+        let streamed = std::sync::Mutex::new(Vec::new());
+        let counter = AtomicU64::new(0);
+        run_search_streaming(
+            &args,
+            |_done, _total| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            },
+            |fc| streamed.lock().unwrap().push(fc),
+        )
+        .unwrap();
+
+        let mut streamed = streamed.into_inner().unwrap();
+        streamed.sort_by(|a, b| a.path.cmp(&b.path));
+        let mut searched = run_search(&args).unwrap();
+        searched.sort_by(|a, b| a.path.cmp(&b.path));
+        assert!(!searched.is_empty());
+        assert_eq!(streamed, searched);
+        assert!(counter.load(Ordering::SeqCst) > 0);
+    }
 }
 
 #[cfg(test)]
@@ -1132,6 +2304,8 @@ mod test_args_new {
         assert_eq!(args.count, Some(5));
         assert!(args.include_glob.is_some());
         assert!(args.exclude_glob.is_some());
+        assert_eq!(args.include_glob(), vec!["*.py"]);
+        assert_eq!(args.exclude_glob(), vec!["*.yml"]);
     }
 
     #[test]
@@ -1245,6 +2419,40 @@ mod test_args_new {
         );
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn with_search_path_keeps_the_other_options() {
+        let args = Args::new(
+            "ref".into(),
+            PathBuf::from("sample_dir_hello_world"),
+            Some(1000),
+            None,
+            None,
+            vec!["*.py".into()],
+            vec![],
+        )
+        .unwrap()
+        .with_lossy_decoding(true);
+        let scoped = args
+            .clone()
+            .with_search_path(PathBuf::from("sample_dir_hello_world/nested_dir"))
+            .unwrap();
+        assert_eq!(
+            scoped.search_path,
+            PathBuf::from("sample_dir_hello_world/nested_dir")
+        );
+        assert_eq!(
+            Args {
+                search_path: args.search_path.clone(),
+                ..scoped
+            },
+            args
+        );
+        assert!(matches!(
+            args.with_search_path(PathBuf::from("/definitely/not/a/real/path/xyz123")),
+            Err(Error::SearchPathNotFound(_))
+        ));
+    }
 }
 
 #[cfg(test)]
@@ -1254,14 +2462,14 @@ mod test_read_file {
     #[test]
     fn returns_none_on_invalid_data() {
         let path = PathBuf::from("sample_dir_hello_world/nested_dir/sample_json.json");
-        let result = read_file(&path);
+        let result = read_file(&path, decode::Decoding::default());
         assert!(result.is_some(), "json file should read as UTF-8");
     }
 
     #[test]
     fn returns_none_on_directory_read_without_panicking() {
         let path = PathBuf::from("sample_dir_hello_world");
-        let result = read_file(&path);
+        let result = read_file(&path, decode::Decoding::default());
         assert_eq!(result, None);
     }
 }
@@ -1370,15 +2578,17 @@ mod test_topn {
             path: PathBuf::from(path),
             similarity_ratio: ratio,
             content: String::new(),
+            encoding: "UTF-8",
+            cell: None,
         }
     }
 
     #[test]
     fn keeps_highest_ratios_sorted_descending() {
         let mut top = TopN::new(2, 0.0);
-        top.push(0, fc("a", 0.1));
-        top.push(1, fc("b", 0.9));
-        top.push(2, fc("c", 0.5));
+        top.push((0, 0), fc("a", 0.1));
+        top.push((1, 0), fc("b", 0.9));
+        top.push((2, 0), fc("c", 0.5));
         let out = top.into_sorted_vec();
         assert_eq!(
             out.iter()
@@ -1392,9 +2602,9 @@ mod test_topn {
     fn ties_break_by_walk_index_ascending() {
         let mut top = TopN::new(2, 0.0);
         // Equal ratios; lower walk index must win and sort first.
-        top.push(5, fc("late", 0.5));
-        top.push(1, fc("early", 0.5));
-        top.push(9, fc("latest", 0.5));
+        top.push((5, 0), fc("late", 0.5));
+        top.push((1, 0), fc("early", 0.5));
+        top.push((9, 0), fc("latest", 0.5));
         let out = top.into_sorted_vec();
         assert_eq!(
             out.iter()
@@ -1408,8 +2618,8 @@ mod test_topn {
     fn should_compute_is_false_only_when_full_and_below_threshold() {
         let mut top = TopN::new(2, 0.0);
         assert!(top.should_compute(0.0), "not full: always compute");
-        top.push(0, fc("a", 0.4));
-        top.push(1, fc("b", 0.6));
+        top.push((0, 0), fc("a", 0.4));
+        top.push((1, 0), fc("b", 0.6));
         // Full; threshold is the lowest kept ratio, 0.4.
         assert!(!top.should_compute(0.3), "below threshold: prune");
         assert!(
@@ -1423,16 +2633,16 @@ mod test_topn {
     fn capacity_zero_never_computes_and_is_empty() {
         let mut top = TopN::new(0, 0.0);
         assert!(!top.should_compute(1.0));
-        top.push(0, fc("a", 1.0));
+        top.push((0, 0), fc("a", 1.0));
         assert!(top.into_sorted_vec().is_empty());
     }
 
     #[test]
     fn floor_rejects_entries_below_threshold() {
         let mut top = TopN::new(5, 0.5);
-        top.push(0, fc("low", 0.4));
-        top.push(1, fc("at", 0.5));
-        top.push(2, fc("high", 0.9));
+        top.push((0, 0), fc("low", 0.4));
+        top.push((1, 0), fc("at", 0.5));
+        top.push((2, 0), fc("high", 0.9));
         let out = top.into_sorted_vec();
         assert_eq!(
             out.iter()
@@ -1453,11 +2663,11 @@ mod test_topn {
     #[test]
     fn merge_keeps_global_top_n() {
         let mut a = TopN::new(2, 0.0);
-        a.push(0, fc("a", 0.9));
-        a.push(1, fc("b", 0.2));
+        a.push((0, 0), fc("a", 0.9));
+        a.push((1, 0), fc("b", 0.2));
         let mut b = TopN::new(2, 0.0);
-        b.push(2, fc("c", 0.7));
-        b.push(3, fc("d", 0.1));
+        b.push((2, 0), fc("c", 0.7));
+        b.push((3, 0), fc("d", 0.1));
         let out = a.merge(b).into_sorted_vec();
         assert_eq!(
             out.iter()
@@ -0,0 +1,646 @@
+//! `busca lsp`: a Language Server Protocol server on stdio, so editors can
+//! offer "Find files similar to this selection". The server advertises one
+//! command, `busca.findSimilar`, and a code action that invokes it for the
+//! current document or selection. The reference is the editor's buffer as
+//! synchronized by `didOpen` and `didChange`, not the file on disk, and the
+//! search root is the workspace root from `initialize`. Candidates are cached
+//! between commands (see ADR-0007).
+
+use crate::sarif::{matched_lines, path_to_uri};
+use crate::{
+    parse_count, parse_similarity_ratio, same_file, DEFAULT_COUNT, DEFAULT_MAX_FILE_LINES,
+};
+use busca::{run_search_cached, Args, Config, ContentCache};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+/// The command that ranks the workspace against a document or selection.
+const FIND_SIMILAR: &str = "busca.findSimilar";
+
+/// JSON-RPC error codes used by the server.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Messages larger than this are rejected before their body is read.
+const MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(clap::Args, Debug)]
+pub struct LspArgs {
+    /// Number of locations returned per command. Defaults to the workspace
+    /// config's count, then 10
+    #[arg(short, long, value_parser = parse_count)]
+    count: Option<usize>,
+
+    /// Drop files whose similarity ratio is below this value (in [0.0, 1.0])
+    #[arg(long, value_parser = parse_similarity_ratio)]
+    min_similarity_ratio: Option<f32>,
+}
+
+/// Handles `busca lsp`. Serves stdin and stdout until the client sends
+/// `exit`, then exits `0` after a `shutdown` request and `1` otherwise, as
+/// the protocol asks.
+pub fn run(lsp_args: LspArgs) {
+    let mut server = Server::new(lsp_args);
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut output = io::stdout().lock();
+    loop {
+        let response = match read_message(&mut input) {
+            Ok(Some(message)) => match serde_json::from_slice::<Message>(&message) {
+                Ok(message) if message.method.as_deref() == Some("exit") => break,
+                Ok(message) => server.handle(message),
+                Err(e) => Some(error_response(Value::Null, PARSE_ERROR, e.to_string())),
+            },
+            Ok(None) => break,
+            Err(e) => {
+                eprintln!("busca: cannot read an LSP message: {e}");
+                break;
+            }
+        };
+        if let Some(response) = response {
+            if write_message(&mut output, &response).is_err() {
+                break;
+            }
+        }
+    }
+    std::process::exit(if server.shut_down { 0 } else { 1 });
+}
+
+/// Reads one message body framed by a `Content-Length` header. `None` at the
+/// end of the input, and an `InvalidData` error for a body over
+/// `MAX_MESSAGE_BYTES`.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok();
+            }
+        }
+    }
+    let Some(content_length) = content_length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "missing Content-Length header",
+        ));
+    };
+    if content_length > MAX_MESSAGE_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message body exceeds {MAX_MESSAGE_BYTES} bytes"),
+        ));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    writer.flush()
+}
+
+/// A request, notification, or response from the client. Responses, which
+/// have no method, are ignored: the server sends no requests.
+#[derive(Deserialize)]
+struct Message {
+    id: Option<Value>,
+    method: Option<String>,
+    #[serde(default)]
+    params: Value,
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+/// A position in a document: a 0-based line and a character offset in UTF-16
+/// code units, as LSP counts them.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+struct Position {
+    line: usize,
+    character: usize,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+struct Range {
+    start: Position,
+    end: Position,
+}
+
+impl Range {
+    /// The whole-line range for a 0-based, end-exclusive line span.
+    fn lines(lines: std::ops::Range<usize>) -> Self {
+        Self {
+            start: Position {
+                line: lines.start,
+                character: 0,
+            },
+            end: Position {
+                line: lines.end,
+                character: 0,
+            },
+        }
+    }
+
+    /// The same range with `start` before `end`. Clients are not trusted to
+    /// send them in order.
+    fn ordered(self) -> Self {
+        let key = |position: Position| (position.line, position.character);
+        if key(self.end) < key(self.start) {
+            Self {
+                start: self.end,
+                end: self.start,
+            }
+        } else {
+            self
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InitializeParams {
+    root_uri: Option<String>,
+    root_path: Option<String>,
+    workspace_folders: Option<Vec<WorkspaceFolder>>,
+}
+
+#[derive(Deserialize)]
+struct WorkspaceFolder {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidOpenParams {
+    text_document: TextDocumentItem,
+}
+
+#[derive(Deserialize)]
+struct TextDocumentItem {
+    uri: String,
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DidChangeParams {
+    text_document: TextDocumentIdentifier,
+    content_changes: Vec<ContentChange>,
+}
+
+#[derive(Deserialize)]
+struct ContentChange {
+    range: Option<Range>,
+    text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TextDocumentParams {
+    text_document: TextDocumentIdentifier,
+}
+
+#[derive(Deserialize)]
+struct TextDocumentIdentifier {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CodeActionParams {
+    text_document: TextDocumentIdentifier,
+    range: Range,
+}
+
+#[derive(Deserialize)]
+struct ExecuteCommandParams {
+    command: String,
+    #[serde(default)]
+    arguments: Vec<Value>,
+}
+
+/// The argument of `busca.findSimilar`: the document, and the selection to
+/// use as the reference instead of the whole document.
+#[derive(Deserialize, Serialize)]
+struct FindSimilarArgs {
+    uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    range: Option<Range>,
+}
+
+/// One ranked result of `busca.findSimilar`: an LSP `Location` for the lines
+/// of the file that match the reference, its ratio, and the matching lines of
+/// the reference document.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SimilarLocation {
+    uri: String,
+    range: Range,
+    similarity_ratio: f32,
+    reference_range: Range,
+}
+
+struct Server {
+    root: Option<PathBuf>,
+    documents: HashMap<String, String>,
+    cache: ContentCache,
+    count: Option<usize>,
+    min_similarity_ratio: Option<f32>,
+    shut_down: bool,
+}
+
+impl Server {
+    fn new(lsp_args: LspArgs) -> Self {
+        Self {
+            root: None,
+            documents: HashMap::new(),
+            cache: ContentCache::new(),
+            count: lsp_args.count,
+            min_similarity_ratio: lsp_args.min_similarity_ratio,
+            shut_down: false,
+        }
+    }
+
+    /// Handles a message and returns the response to send, if any.
+    fn handle(&mut self, message: Message) -> Option<Value> {
+        let method = message.method?;
+        let Some(id) = message.id else {
+            self.notify(&method, message.params);
+            return None;
+        };
+        Some(match self.request(&method, message.params) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, error)) => error_response(id, code, error),
+        })
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => {
+                let params: InitializeParams = parse(params)?;
+                self.root = params
+                    .workspace_folders
+                    .and_then(|folders| folders.into_iter().next())
+                    .and_then(|folder| uri_to_path(&folder.uri))
+                    .or_else(|| params.root_uri.as_deref().and_then(uri_to_path))
+                    .or_else(|| params.root_path.map(PathBuf::from));
+                Ok(json!({
+                    "capabilities": {
+                        // Full document sync: each change carries the whole
+                        // buffer, which is what the reference needs anyway.
+                        "textDocumentSync": 1,
+                        "codeActionProvider": true,
+                        "executeCommandProvider": { "commands": [FIND_SIMILAR] },
+                    },
+                    "serverInfo": { "name": "busca", "version": env!("CARGO_PKG_VERSION") },
+                }))
+            }
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/codeAction" => {
+                let params: CodeActionParams = parse(params)?;
+                let selection = (params.range.start != params.range.end).then_some(params.range);
+                let title = match selection {
+                    Some(_) => "Find files similar to this selection",
+                    None => "Find files similar to this file",
+                };
+                let arguments = FindSimilarArgs {
+                    uri: params.text_document.uri,
+                    range: selection,
+                };
+                Ok(json!([{
+                    "title": title,
+                    "command": { "title": title, "command": FIND_SIMILAR, "arguments": [arguments] },
+                }]))
+            }
+            "workspace/executeCommand" => {
+                let params: ExecuteCommandParams = parse(params)?;
+                if params.command != FIND_SIMILAR {
+                    return Err((
+                        INVALID_PARAMS,
+                        format!("unknown command '{}'", params.command),
+                    ));
+                }
+                let argument = params.arguments.into_iter().next().unwrap_or(Value::Null);
+                let locations = self.find_similar(parse(argument)?)?;
+                Ok(serde_json::to_value(locations).expect("locations serialize to JSON"))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("unsupported method '{method}'"))),
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        match method {
+            "textDocument/didOpen" => {
+                if let Ok(params) = parse::<DidOpenParams>(params) {
+                    let document = params.text_document;
+                    self.documents.insert(document.uri, document.text);
+                }
+            }
+            "textDocument/didChange" => {
+                if let Ok(params) = parse::<DidChangeParams>(params) {
+                    let document = self.documents.entry(params.text_document.uri).or_default();
+                    for change in params.content_changes {
+                        match change.range {
+                            Some(range) => {
+                                document.replace_range(span(document, range), &change.text)
+                            }
+                            None => *document = change.text,
+                        }
+                    }
+                }
+            }
+            "textDocument/didClose" => {
+                if let Ok(params) = parse::<TextDocumentParams>(params) {
+                    self.documents.remove(&params.text_document.uri);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Ranks the workspace against the buffer of `args.uri`, or against the
+    /// selected text of it. The document's own file is left out.
+    fn find_similar(&self, args: FindSimilarArgs) -> Result<Vec<SimilarLocation>, (i64, String)> {
+        let Some(root) = &self.root else {
+            return Err((
+                INVALID_PARAMS,
+                "the workspace has no root folder".to_owned(),
+            ));
+        };
+        let Some(document) = self.documents.get(&args.uri) else {
+            return Err((
+                INVALID_PARAMS,
+                format!("'{}' is not open in the editor", args.uri),
+            ));
+        };
+        let (reference_string, first_line) = match args.range.map(Range::ordered) {
+            Some(range) => (document[span(document, range)].to_owned(), range.start.line),
+            None => (document.clone(), 0),
+        };
+
+        let failed = |e: busca::Error| (INVALID_PARAMS, e.to_string());
+        let config = Config::discover(root, None).map_err(failed)?;
+        let count = self.count.or(config.count).unwrap_or(DEFAULT_COUNT);
+        let search = Args::new(
+            reference_string,
+            root.clone(),
+            Some(config.max_file_lines.unwrap_or(DEFAULT_MAX_FILE_LINES)),
+            // One more than needed, in case the document itself ranks.
+            Some(count + 1),
+            self.min_similarity_ratio.or(config.min_similarity_ratio),
+            config.include_glob.unwrap_or_default(),
+            config.exclude_glob.unwrap_or_default(),
+        )
+        .map_err(failed)?;
+        let document_path = uri_to_path(&args.uri);
+        let file_comparisons = run_search_cached(&search, &self.cache).map_err(failed)?;
+
+        Ok(file_comparisons
+            .iter()
+            .filter(|fc| {
+                document_path
+                    .as_deref()
+                    .is_none_or(|document_path| !same_file(&fc.path, document_path))
+            })
+            .take(count)
+            .map(|fc| {
+                let (reference_lines, candidate_lines) =
+                    matched_lines(&search.reference_string, &fc.content).unwrap_or((0..0, 0..0));
+                SimilarLocation {
+                    uri: path_to_uri(&fc.path),
+                    range: Range::lines(candidate_lines),
+                    similarity_ratio: fc.similarity_ratio,
+                    reference_range: Range::lines(
+                        reference_lines.start + first_line..reference_lines.end + first_line,
+                    ),
+                }
+            })
+            .collect())
+    }
+}
+
+fn parse<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, (i64, String)> {
+    serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, e.to_string()))
+}
+
+/// The byte offset of `position` in `text`, clamped to the end of its line
+/// and of the text.
+fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return text.len(),
+        }
+    }
+    let line = &text[line_start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let mut units = 0;
+    for (index, c) in line.char_indices() {
+        if units >= position.character {
+            return line_start + index;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+/// The byte range of `range` in `text`, with its positions put in order.
+fn span(text: &str, range: Range) -> std::ops::Range<usize> {
+    let range = range.ordered();
+    offset(text, range.start)..offset(text, range.end)
+}
+
+/// The local path of a `file://` URI, percent-decoded. `None` for other
+/// schemes.
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    let mut bytes = Vec::with_capacity(rest.len());
+    let mut input = rest.bytes();
+    while let Some(byte) = input.next() {
+        if byte == b'%' {
+            let hex = [input.next()?, input.next()?];
+            bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    let path = String::from_utf8(bytes).ok()?;
+    // `file:///C:/src` names `C:/src` on Windows.
+    if cfg!(windows) && path.as_bytes().get(2) == Some(&b':') {
+        return Some(PathBuf::from(&path[1..]));
+    }
+    Some(PathBuf::from(path))
+}
+
+#[cfg(test)]
+mod test_lsp {
+    use super::*;
+    use std::path::Path;
+
+    fn server() -> Server {
+        Server::new(LspArgs {
+            count: Some(2),
+            min_similarity_ratio: None,
+        })
+    }
+
+    fn message(id: Option<u64>, method: &str, params: Value) -> Message {
+        Message {
+            id: id.map(Value::from),
+            method: Some(method.to_owned()),
+            params,
+        }
+    }
+
+    #[test]
+    fn messages_are_framed_by_content_length() {
+        let mut framed = Vec::new();
+        write_message(&mut framed, &json!({ "id": 1 })).unwrap();
+        assert_eq!(framed, b"Content-Length: 8\r\n\r\n{\"id\":1}");
+        let mut reader = framed.as_slice();
+        assert_eq!(read_message(&mut reader).unwrap().unwrap(), b"{\"id\":1}");
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+        assert!(read_message(&mut "X: 1\r\n\r\n".as_bytes()).is_err());
+        let oversized = format!("Content-Length: {}\r\n\r\n", MAX_MESSAGE_BYTES + 1);
+        let error = read_message(&mut oversized.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn positions_count_utf16_units() {
+        let text = "ab\n\u{1F600}cd\nlast";
+        let position = |line, character| Position { line, character };
+        assert_eq!(offset(text, position(0, 1)), 1);
+        assert_eq!(offset(text, position(1, 2)), 7);
+        assert_eq!(offset(text, position(1, 99)), 9);
+        assert_eq!(offset(text, position(9, 0)), text.len());
+    }
+
+    #[test]
+    fn reversed_ranges_are_put_in_order() {
+        let mut server = server();
+        let uri = "file:///tmp/a.py";
+        server.handle(message(
+            None,
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": uri, "languageId": "python", "version": 1, "text": "abc\ndef\n" } }),
+        ));
+        server.handle(message(
+            None,
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{
+                    "range": { "start": { "line": 1, "character": 1 }, "end": { "line": 0, "character": 1 } },
+                    "text": "X",
+                }],
+            }),
+        ));
+        assert_eq!(server.documents[uri], "aXef\n");
+
+        // A reversed selection is searched like the ordered one.
+        let selection =
+            json!({ "start": { "line": 1, "character": 0 }, "end": { "line": 0, "character": 0 } });
+        server.handle(message(
+            Some(1),
+            "initialize",
+            json!({ "rootPath": "sample_dir_hello_world" }),
+        ));
+        let response = server.handle(message(
+            Some(2),
+            "workspace/executeCommand",
+            json!({ "command": FIND_SIMILAR, "arguments": [{ "uri": uri, "range": selection }] }),
+        ));
+        assert!(response.unwrap()["result"].is_array());
+    }
+
+    #[test]
+    fn file_uris_are_decoded() {
+        assert_eq!(
+            uri_to_path("file:///home/me/my%20file.py"),
+            Some(PathBuf::from("/home/me/my file.py"))
+        );
+        assert_eq!(uri_to_path("untitled:Untitled-1"), None);
+    }
+
+    #[test]
+    fn find_similar_ranks_the_workspace_against_the_buffer() {
+        let root = Path::new("sample_dir_hello_world").canonicalize().unwrap();
+        let uri = path_to_uri(&root.join("file_1.py"));
+        let mut server = server();
+        server.handle(message(
+            Some(1),
+            "initialize",
+            json!({ "rootUri": path_to_uri(&root) }),
+        ));
+        // The buffer differs from the file on disk; the file itself is left
+        // out of its own results.
+        let buffer = "import os\nprint(\"Hello World 1\")\nprint(\"Hello World 2\")\n";
+        server.handle(message(
+            None,
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": uri, "languageId": "python", "version": 1, "text": buffer } }),
+        ));
+
+        let selection =
+            json!({ "start": { "line": 1, "character": 0 }, "end": { "line": 3, "character": 0 } });
+        let actions = server
+            .handle(message(
+                Some(2),
+                "textDocument/codeAction",
+                json!({ "textDocument": { "uri": uri }, "range": selection, "context": { "diagnostics": [] } }),
+            ))
+            .unwrap();
+        let command = &actions["result"][0]["command"];
+        assert_eq!(command["command"], FIND_SIMILAR);
+
+        let response = server
+            .handle(message(
+                Some(3),
+                "workspace/executeCommand",
+                json!({ "command": FIND_SIMILAR, "arguments": command["arguments"] }),
+            ))
+            .unwrap();
+        let locations = response["result"].as_array().unwrap();
+        assert_eq!(locations.len(), 2);
+        assert!(locations.iter().all(|location| location["uri"] != uri));
+        // The selection starts at line 1 of the buffer.
+        assert_eq!(locations[0]["referenceRange"]["start"]["line"], 1);
+        assert!(locations[0]["similarityRatio"].as_f64().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn unknown_methods_and_unopened_documents_are_errors() {
+        let mut server = server();
+        let response = server.handle(message(Some(1), "textDocument/hover", json!({})));
+        assert_eq!(response.unwrap()["error"]["code"], METHOD_NOT_FOUND);
+        server.handle(message(Some(2), "initialize", json!({ "rootPath": "." })));
+        let response = server.handle(message(
+            Some(3),
+            "workspace/executeCommand",
+            json!({ "command": FIND_SIMILAR, "arguments": [{ "uri": "file:///nowhere.py" }] }),
+        ));
+        assert_eq!(response.unwrap()["error"]["code"], INVALID_PARAMS);
+        assert_eq!(server.handle(message(None, "initialized", json!({}))), None);
+    }
+}
@@ -1,19 +1,66 @@
-use busca::format_file_comparisons;
-use busca::{run_search_with_progress, Args, FileComparison};
+use busca::{
+    compare_candidate, list_candidates, run_search_with_progress, Args, BinaryPolicy, Candidate,
+    Config, FileComparison,
+};
 use clap::Parser;
-use console::{style, Style};
+use console::{style, Term};
+use diff::{parse_diff_selection, DiffFormat, DiffOutputArgs, DiffSelection};
 use indicatif::ProgressStyle;
-use inquire::{InquireError, Select};
-use similar::{ChangeTag, TextDiff};
 use std::env;
-use std::fmt;
-use std::fs;
-use std::path::PathBuf;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use term_grid::{Alignment, Cell, Direction, Filling, Grid, GridOptions};
+use terminal::{ColorChoice, PathDisplay};
+
+// Everything below is part of the CLI binary, not the library (see ADR-0004).
+mod batch;
+mod check;
+mod diff;
+mod git;
+mod history;
+mod html;
+mod lsp;
+mod markdown;
+mod pager;
+mod picker;
+mod sarif;
+mod serve;
+mod terminal;
+mod tui;
+mod watch;
+
+/// Used when neither the CLI nor a config file sets `max_file_lines`.
+const DEFAULT_MAX_FILE_LINES: usize = 10_000;
+
+/// Used when neither the CLI nor a config file sets `count`.
+const DEFAULT_COUNT: usize = 10;
+
+/// The encoding the library reports for a binary file compared byte by byte.
+const BINARY: &str = "binary";
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
 enum OutputFormat {
+    /// Ranked grid, with the interactive picker on a TTY
     Human,
+    /// A single pretty-printed JSON array
     Json,
+    /// A versioned JSON object with the query, search stats, and results
+    JsonEnvelope,
+    /// One compact JSON object per line
+    Ndjson,
+    /// Comma-separated values with a header row (RFC 4180 quoting)
+    Csv,
+    /// Tab-separated values with a header row (backslash escapes)
+    Tsv,
+    /// One path per line, or NUL-separated with --print0
+    Paths,
+    /// A self-contained HTML report with side-by-side diffs
+    Html,
+    /// A Markdown table for pull-request comments; add --with-diffs for diffs
+    Markdown,
+    /// A SARIF 2.1.0 log with one result per comparison, for CI code scanning
+    Sarif,
 }
 
 /// Print an error message to stderr and exit with status code 2.
@@ -47,37 +94,191 @@ fn parse_count(s: &str) -> Result<usize, String> {
 
 fn main() {
     let input_args = InputArgs::parse();
+    input_args.color.apply();
+    pager::set_enabled(!input_args.no_pager);
+    let path_display = input_args.path_display;
+
+    match input_args.command {
+        // The flag-only invocation predates subcommands and stays an alias for
+        // `busca search`, with the same exit codes.
+        None => run_search(input_args.search, path_display),
+        Some(Command::Search(search_args)) => run_search(search_args, path_display),
+        Some(Command::Compare(compare_args)) => run_compare(compare_args, path_display),
+        Some(Command::Diff(diff_args)) => run_diff(diff_args, path_display),
+        Some(Command::Check(check_args)) => check::run(check_args, path_display),
+        Some(Command::Tui(tui_args)) => tui::run(tui_args, path_display),
+        Some(Command::Serve(serve_args)) => serve::run(serve_args),
+        Some(Command::Lsp(lsp_args)) => lsp::run(lsp_args),
+        Some(Command::Batch) => batch::run(),
+        Some(Command::History(history_args)) => history::run(history_args),
+    }
+}
+
+/// Handles `busca search` and the flag-only invocation: rank the candidates
+/// and print them in the requested format, or launch the picker.
+fn run_search(input_args: SearchArgs, path_display: Option<PathDisplay>) {
+    if input_args.list_candidates {
+        run_list_candidates(input_args);
+        return;
+    }
 
     let output_format = input_args.format;
     let with_content = input_args.with_content;
+    let with_diffs = input_args.with_diffs;
+    let diff_selection = input_args.diff;
+    let diff_output = input_args.diff_output;
     let no_interactive = input_args.no_interactive;
+    let select = input_args.select;
+    let multi = input_args.multi;
+    let watch = input_args.watch;
+    let print0 = input_args.print0;
+    let template = input_args.template.clone();
+    let ref_file_path = input_args.query.ref_file_path.clone();
 
     let args = match input_args.into_args() {
         Ok(args) => args,
         Err(err_str) => graceful_panic(&err_str),
     };
 
-    let file_comparisons = match cli_run_search(&args) {
+    if watch {
+        watch::run(
+            args,
+            ref_file_path,
+            output_format,
+            with_content,
+            path_display,
+        );
+        return;
+    }
+
+    let (mut file_comparisons, search_stats) = match cli_run_search(&args) {
         Ok(search_results) => search_results,
         Err(err_str) => graceful_panic(&err_str),
     };
+    terminal::display_paths(&mut file_comparisons, path_display);
 
     if file_comparisons.is_empty() {
         eprintln!("No files found that match the criteria.");
         std::process::exit(1);
     }
 
+    if select {
+        if !interactive_input_mode() {
+            graceful_panic("--select needs an interactive terminal on stdin.");
+        }
+        let selected = picker::select(&file_comparisons, multi);
+        if selected.is_empty() {
+            std::process::exit(1);
+        }
+        write_records(
+            selected.iter().map(|fc| fc.path.display().to_string()),
+            print0,
+        );
+        return;
+    }
+
+    if let Some(template) = template {
+        let records = file_comparisons
+            .iter()
+            .enumerate()
+            .map(|(index, fc)| template.render(index + 1, fc));
+        write_records(records, print0);
+        return;
+    }
+
+    let reference_label = match &ref_file_path {
+        Some(path) => path.display().to_string(),
+        None => "(piped input)".to_owned(),
+    };
+
+    if let Some(diff_selection) = diff_selection {
+        let selected = diff_selection.take(file_comparisons.len());
+        let mut out = String::new();
+        for (index, fc) in file_comparisons[..selected].iter().enumerate() {
+            if diff_output.diff_format != DiffFormat::Unified {
+                if index > 0 {
+                    out.push('\n');
+                }
+                let header = format!("{}  {:.1}%", fc.path.display(), fc.similarity_ratio * 100.0);
+                out.push_str(&format!("{}\n", style(header).bold()));
+            }
+            out.push_str(&result_diff(
+                diff_output,
+                &reference_label,
+                &args.reference_string,
+                fc,
+            ));
+        }
+        pager::output(&out);
+        return;
+    }
+
     match output_format {
         OutputFormat::Json => {
             println!("{}", comparisons_to_json(&file_comparisons, with_content));
         }
+        OutputFormat::JsonEnvelope => {
+            let envelope = json_envelope(
+                &args,
+                ref_file_path.as_deref(),
+                &search_stats,
+                &file_comparisons,
+                with_content,
+            );
+            println!("{}", envelope);
+        }
+        OutputFormat::Ndjson => {
+            print!("{}", comparisons_to_ndjson(&file_comparisons, with_content));
+        }
+        OutputFormat::Html => {
+            print!(
+                "{}",
+                html::render_report(
+                    &reference_label,
+                    &args.reference_string,
+                    &args.search_path.display().to_string(),
+                    &file_comparisons,
+                    diff_output.context_lines,
+                )
+            );
+        }
+        OutputFormat::Markdown => {
+            print!(
+                "{}",
+                markdown::render(
+                    &reference_label,
+                    &args.reference_string,
+                    &args.search_path.display().to_string(),
+                    &file_comparisons,
+                    with_diffs.then_some(diff_output.context_lines),
+                )
+            );
+        }
+        OutputFormat::Sarif => {
+            let log = sarif::render(
+                ref_file_path.as_deref(),
+                &args.reference_string,
+                &file_comparisons,
+            );
+            println!("{}", log);
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let (header, rows) = comparison_rows(&file_comparisons, with_content);
+            print!("{}", to_delimited(&header, &rows, output_format));
+        }
+        OutputFormat::Paths => {
+            let paths = file_comparisons
+                .iter()
+                .map(|fc| fc.path.display().to_string());
+            write_records(paths, print0);
+        }
         OutputFormat::Human => {
-            let file_comparisons_output = format_file_comparisons(&file_comparisons);
             let is_tty = interactive_input_mode();
             let interactive = is_tty && !no_interactive;
 
             if !interactive {
-                println!("{}", file_comparisons_output);
+                let width = terminal::width(&Term::stdout());
+                println!("{}", terminal::format_grid(&file_comparisons, width));
                 // Explain the missing picker only on automatic fallback, and on
                 // stderr so stdout stays clean for parsing.
                 if !is_tty && !no_interactive {
@@ -86,21 +287,12 @@ fn main() {
                 return;
             }
 
-            let grid_options: Vec<&str> = file_comparisons_output.split('\n').collect();
-            let ans = match Select::new("Select a file to compare:", grid_options)
-                .with_page_size(10)
-                .raw_prompt()
-            {
-                Ok(answer) => answer,
-                Err(InquireError::OperationCanceled) => std::process::exit(0),
-                Err(err) => graceful_panic(&err.to_string()),
-            };
-
-            let selected_file_comparison = &file_comparisons[ans.index];
-            // Reuse the content captured during the search rather than reading the
-            // file again. That keeps the diff consistent with the ranked ratio and
-            // avoids a second read that could fail if the file changed meanwhile.
-            output_detailed_diff(&args.reference_string, &selected_file_comparison.content);
+            picker::run(
+                &file_comparisons,
+                &reference_label,
+                &args.reference_string,
+                diff_output,
+            );
         }
     }
 }
@@ -108,10 +300,162 @@ fn main() {
 /// Simple utility to search for files with content that most closely match the lines of a reference string.
 #[derive(Parser, Debug)]
 #[command(author="Noah Baculi", version, about, long_about = None, override_usage="\
-    busca --ref-file-path <REF_FILE_PATH> [OPTIONS]\n       \
-    <SomeCommand> | busca [OPTIONS]"
+    busca [search] --ref-file-path <REF_FILE_PATH> [OPTIONS]\n       \
+    <SomeCommand> | busca [search] [OPTIONS]\n       \
+    busca <COMMAND> [OPTIONS]",
+    args_conflicts_with_subcommands = true
 )]
 struct InputArgs {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    search: SearchArgs,
+
+    /// When to color output
+    #[arg(long, value_enum, default_value = "auto", global = true)]
+    color: ColorChoice,
+
+    /// Print result paths relative to the current directory or absolute,
+    /// instead of as the search walked them
+    #[arg(long, value_enum, global = true)]
+    path_display: Option<PathDisplay>,
+
+    /// Print diffs directly instead of through $BUSCA_PAGER, $PAGER, or
+    /// `less -R` when stdout is a terminal
+    #[arg(long, global = true)]
+    no_pager: bool,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Rank the files most similar to the reference. The default when no
+    /// subcommand is given
+    Search(SearchArgs),
+    /// Print the similarity ratio and diff of two files
+    Compare(CompareArgs),
+    /// Print the diff of the reference against its closest match, without
+    /// prompting
+    Diff(DiffArgs),
+    /// Exit 1 if any file is more similar to the reference than --fail-above,
+    /// for duplicate-code gates in CI. Pairs recorded in the baseline file are
+    /// accepted until either file changes
+    Check(check::CheckArgs),
+    /// Browse the results full-screen as they are scored, refining the path
+    /// filter, count, and similarity floor without rescanning
+    Tui(tui::TuiArgs),
+    /// Answer search, compare, and explain requests over a local HTTP/JSON
+    /// API, keeping file contents cached between requests
+    Serve(serve::ServeArgs),
+    /// Run a Language Server Protocol server on stdio that finds the files
+    /// most similar to the current document or selection
+    Lsp(lsp::LspArgs),
+    /// Run one search per JSON line of stdin and print one JSON line of
+    /// results per query, reusing the walk and file contents between queries
+    Batch,
+    /// Find the commits and paths whose past content most resembles the
+    /// reference, for files that were deleted or rewritten
+    History(history::HistoryArgs),
+}
+
+/// Options of `busca search`, which are also the top-level options.
+#[derive(clap::Args, Debug)]
+struct SearchArgs {
+    #[command(flatten)]
+    query: QueryArgs,
+
+    /// Number of results to display. Defaults to 10
+    #[arg(short, long, value_parser = parse_count)]
+    count: Option<usize>,
+
+    /// Drop comparisons whose similarity ratio is below this value (in [0.0, 1.0]).
+    /// Applied during the search, before the --count limit.
+    #[arg(long, value_parser = parse_similarity_ratio)]
+    min_similarity_ratio: Option<f32>,
+
+    /// Output format for the ranked results
+    #[arg(long, value_enum, default_value = "human")]
+    format: OutputFormat,
+
+    /// Include each file's content in JSON, NDJSON, CSV, and TSV output.
+    /// Ignored for the human and paths formats
+    #[arg(long)]
+    with_content: bool,
+
+    /// Include each result's diff against the reference in Markdown output,
+    /// collapsed under <details>
+    #[arg(long)]
+    with_diffs: bool,
+
+    /// Print the diffs of the top result, all results, or the top N results
+    /// instead of the ranked list, without prompting
+    #[arg(
+        long,
+        value_name = "top|all|N",
+        value_parser = parse_diff_selection,
+        conflicts_with_all = ["format", "template", "list_candidates"]
+    )]
+    diff: Option<DiffSelection>,
+
+    #[command(flatten)]
+    diff_output: DiffOutputArgs,
+
+    /// Print one line per result from a template instead of --format.
+    /// Placeholders: {rank}, {path}, {similarity_ratio} (alias {ratio}),
+    /// {content}, {encoding}, and {cell} (empty except for notebooks); the
+    /// ratio accepts a precision such as {ratio:.3}. `\t`, `\n`,
+    /// `\0`, and `\\` are unescaped, and `{{`/`}}` print literal braces
+    #[arg(long, value_parser = parse_template, conflicts_with = "list_candidates")]
+    template: Option<Template>,
+
+    /// Print the ranked list instead of launching the interactive picker
+    #[arg(long)]
+    no_interactive: bool,
+
+    /// Print the path picked in the interactive picker to stdout instead of
+    /// showing its diff, as in `vim "$(busca -r ref.py --select)"`. The picker
+    /// is drawn on stderr. Exits 1 if the pick is cancelled
+    #[arg(
+        long,
+        conflicts_with_all = ["format", "diff", "template", "list_candidates", "no_interactive"]
+    )]
+    select: bool,
+
+    /// Keep running and update the ranking as files under the search path or
+    /// the reference change, rescoring only the changed files. Supports
+    /// --format human (redrawn) and ndjson (one `ranking` event per update)
+    #[arg(
+        long,
+        conflicts_with_all = ["diff", "template", "list_candidates", "select"]
+    )]
+    watch: bool,
+
+    /// With --select, pick several results (space toggles, enter confirms) and
+    /// print one path per line
+    #[arg(long, requires = "select")]
+    multi: bool,
+
+    /// List the files that would be compared, without scoring them. No
+    /// reference is needed
+    #[arg(long)]
+    list_candidates: bool,
+
+    /// Include each candidate's line count and on-disk size in bytes in the
+    /// --list-candidates output
+    #[arg(long, requires = "list_candidates")]
+    with_stats: bool,
+
+    /// Terminate each record with NUL instead of a newline, for `xargs -0`.
+    /// Applies to --format paths, --template, --select, and --list-candidates
+    /// (where it overrides --format)
+    #[arg(long)]
+    print0: bool,
+}
+
+/// The reference, where to search, which files qualify, and which config files
+/// to read: the options shared by every mode that walks a tree.
+#[derive(clap::Args, Debug)]
+struct QueryArgs {
     /// Local or absolute path to the reference comparison file. Overrides any
     /// piped input
     #[arg(short, long)]
@@ -122,9 +466,9 @@ struct InputArgs {
     search_path: Option<PathBuf>,
 
     /// The maximum number of lines a candidate file may have. Candidates with
-    /// more lines (or zero lines) are skipped entirely.
-    #[arg(short, long, default_value_t = 10_000)]
-    max_file_lines: usize,
+    /// more lines (or zero lines) are skipped entirely. Defaults to 10000
+    #[arg(short, long)]
+    max_file_lines: Option<usize>,
 
     /// Globs that qualify a file for comparison
     #[arg(short, long)]
@@ -134,70 +478,242 @@ struct InputArgs {
     #[arg(short = 'x', long)]
     exclude_glob: Option<Vec<String>>,
 
-    /// Number of results to display
-    #[arg(short, long, default_value_t = 10, value_parser = parse_count)]
-    count: usize,
+    /// Read default options from this config file instead of discovering
+    /// `.busca.toml` and the user-level config. Flags still take precedence
+    #[arg(long)]
+    config: Option<PathBuf>,
 
-    /// Drop comparisons whose similarity ratio is below this value (in [0.0, 1.0]).
-    /// Applied during the search, before the --count limit.
-    #[arg(long, value_parser = parse_similarity_ratio)]
-    min_similarity_ratio: Option<f32>,
+    /// Apply the named `[profile.<name>]` table from the config file(s)
+    #[arg(long)]
+    profile: Option<String>,
 
-    /// Output format for the ranked results
-    #[arg(long, value_enum, default_value = "human")]
-    format: OutputFormat,
+    /// Ignore every config file and use only flags and built-in defaults
+    #[arg(long, conflicts_with_all = ["config", "profile"])]
+    no_config: bool,
 
-    /// Include each file's content in JSON output. Ignored for the human format
+    #[command(flatten)]
+    read: ReadArgs,
+
+    #[command(flatten)]
+    git: git::GitArgs,
+}
+
+/// How candidates are read: archive traversal and text decoding.
+#[derive(clap::Args, Debug, Default)]
+struct ReadArgs {
+    /// Also compare the files inside .zip, .tar, .tar.gz, and .tgz archives and
+    /// standalone .gz files, named like `release.tar.gz!/src/main.py`. Globs
+    /// apply to these inner paths
     #[arg(long)]
-    with_content: bool,
+    search_archives: bool,
 
-    /// Print the ranked list instead of launching the interactive picker
+    /// Decode candidates that are not valid UTF-8 and have no byte order mark
+    /// with this encoding, such as latin1, windows-1252, or utf-16le. UTF-8 and
+    /// UTF-16 files with a byte order mark are always decoded
+    #[arg(long, value_name = "LABEL")]
+    encoding: Option<String>,
+
+    /// Replace malformed sequences with U+FFFD instead of skipping the file
     #[arg(long)]
-    no_interactive: bool,
+    lossy: bool,
+
+    /// What to do with binary files, those with a NUL byte in their first
+    /// 8000 bytes: skip them quietly, report each one on stderr, or compare
+    /// them byte by byte with the reference file
+    #[arg(long, value_name = "skip|report|compare", default_value_t)]
+    binary: BinaryPolicy,
+
+    /// Compare HTML, DOCX, and PDF files by their plain text, and Jupyter
+    /// notebooks by their cell sources, instead of as they are. A document
+    /// given as --ref-file-path is converted the same way
+    #[arg(long)]
+    extract_text: bool,
+
+    /// Compare the markdown cells of Jupyter notebooks too, not just their
+    /// code cells
+    #[arg(long, requires = "extract_text")]
+    notebook_markdown: bool,
 }
 
-impl InputArgs {
-    pub fn into_args(self) -> Result<Args, String> {
-        let reference_string = match self.ref_file_path {
-            Some(ref_file_path) => match ref_file_path.is_file() {
-                false => {
-                    return Err(format!(
-                        "The reference file path '{}' is not a file.",
-                        ref_file_path.display()
-                    ))
-                }
-                true => match fs::read_to_string(ref_file_path) {
-                    Err(e) => return Err(e.to_string()),
-                    Ok(s) => s,
-                },
-            },
-            None => get_piped_input()?,
+impl ReadArgs {
+    /// Applies the archive, decoding, and binary options to `args`, then reads
+    /// the reference from `reference_path` when given. Binary files are
+    /// compared with its bytes, so a reference that is itself binary works.
+    fn apply(&self, args: Args, reference_path: Option<&Path>) -> Result<Args, String> {
+        let args = match &self.encoding {
+            Some(label) => args
+                .with_fallback_encoding(label)
+                .map_err(|e| e.to_string())?,
+            None => args,
         };
+        let args = args
+            .with_archives(self.search_archives)
+            .with_lossy_decoding(self.lossy)
+            .with_binary_policy(self.binary)
+            .with_text_extraction(self.extract_text)
+            .with_notebook_markdown(self.notebook_markdown);
+        match reference_path {
+            Some(path) => args.with_reference_file(path).map_err(|e| e.to_string()),
+            None => Ok(args),
+        }
+    }
+}
+
+impl SearchArgs {
+    pub fn into_args(self) -> Result<Args, String> {
+        let reference_string = self.query.read_reference()?;
+        self.into_args_with_reference(reference_string)
+    }
+
+    /// Builds [`Args`] for a mode that never scores, so no reference file or
+    /// piped input is read.
+    fn into_listing_args(self) -> Result<Args, String> {
+        self.into_args_with_reference(String::new())
+    }
+
+    fn into_args_with_reference(self, reference_string: String) -> Result<Args, String> {
+        let (search_path, config) = self.query.resolve()?;
+        Args::new(
+            reference_string,
+            search_path,
+            Some(self.query.max_file_lines(&config)),
+            Some(self.count.or(config.count).unwrap_or(DEFAULT_COUNT)),
+            self.min_similarity_ratio.or(config.min_similarity_ratio),
+            self.query
+                .include_glob
+                .or(config.include_glob)
+                .unwrap_or_default(),
+            self.query
+                .exclude_glob
+                .or(config.exclude_glob)
+                .unwrap_or_default(),
+        )
+        .map_err(|e| e.to_string())
+        .and_then(|args| {
+            self.query
+                .read
+                .apply(args, self.query.ref_file_path.as_deref())
+        })
+        .and_then(|args| self.query.git.restrict(args))
+    }
+}
+
+impl QueryArgs {
+    /// Reads the piped input when `--ref-file-path` is unset. A reference file
+    /// is only checked here: [`ReadArgs::apply`] reads it once the decoding
+    /// options are set, so it is decoded as the candidates are.
+    fn read_reference(&self) -> Result<String, String> {
+        match &self.ref_file_path {
+            Some(ref_file_path) if !ref_file_path.is_file() => Err(format!(
+                "The reference file path '{}' is not a file.",
+                ref_file_path.display()
+            )),
+            Some(_) => Ok(String::new()),
+            None => get_piped_input(),
+        }
+    }
 
-        let search_path = match self.search_path {
+    /// The search root (CWD when unset) and the config layer that applies to
+    /// it. Precedence, highest first: flags, then config files (see
+    /// `Config::discover`), then the built-in defaults.
+    fn resolve(&self) -> Result<(PathBuf, Config), String> {
+        let search_path = match self.search_path.clone() {
             Some(p) => p,
-            None => match env::current_dir() {
-                Ok(p) => p,
-                Err(e) => return Err(e.to_string()),
-            },
+            None => env::current_dir().map_err(|e| e.to_string())?,
         };
+        let config = self.load_config(&search_path)?;
+        Ok((search_path, config))
+    }
+
+    fn max_file_lines(&self, config: &Config) -> usize {
+        self.max_file_lines
+            .or(config.max_file_lines)
+            .unwrap_or(DEFAULT_MAX_FILE_LINES)
+    }
 
+    /// Loads the config layer for this invocation: the `--config` file when
+    /// given, nothing under `--no-config`, and otherwise the user-level config
+    /// merged with the nearest `.busca.toml` at or above `search_path`.
+    fn load_config(&self, search_path: &Path) -> Result<Config, String> {
+        let config = match (&self.config, self.no_config) {
+            (Some(config_path), _) => Config::from_path(config_path, self.profile.as_deref()),
+            (None, true) => return Ok(Config::default()),
+            (None, false) => Config::discover(search_path, self.profile.as_deref()),
+        };
+        config.map_err(|e| e.to_string())
+    }
+}
+
+/// Options of `busca compare`.
+#[derive(clap::Args, Debug)]
+struct CompareArgs {
+    /// The reference file
+    a: PathBuf,
+
+    /// The file compared against the reference
+    b: PathBuf,
+
+    /// Print only the similarity ratio
+    #[arg(long)]
+    no_diff: bool,
+
+    #[command(flatten)]
+    diff_output: DiffOutputArgs,
+
+    #[command(flatten)]
+    read: ReadArgs,
+}
+
+/// Options of `busca diff`.
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    #[command(flatten)]
+    query: QueryArgs,
+
+    /// Exit 1 instead of printing a diff when the closest match's similarity
+    /// ratio is below this value (in [0.0, 1.0])
+    #[arg(long, value_parser = parse_similarity_ratio)]
+    min_similarity_ratio: Option<f32>,
+
+    #[command(flatten)]
+    diff_output: DiffOutputArgs,
+}
+
+impl DiffArgs {
+    /// Builds [`Args`] for the two best matches: the closest match may be the
+    /// reference file itself, which `busca diff` skips.
+    fn into_args(self) -> Result<Args, String> {
+        let reference_string = self.query.read_reference()?;
+        let (search_path, config) = self.query.resolve()?;
         Args::new(
             reference_string,
             search_path,
-            Some(self.max_file_lines),
-            Some(self.count),
-            self.min_similarity_ratio,
-            self.include_glob.unwrap_or_default(),
-            self.exclude_glob.unwrap_or_default(),
+            Some(self.query.max_file_lines(&config)),
+            Some(2),
+            self.min_similarity_ratio.or(config.min_similarity_ratio),
+            self.query
+                .include_glob
+                .or(config.include_glob)
+                .unwrap_or_default(),
+            self.query
+                .exclude_glob
+                .or(config.exclude_glob)
+                .unwrap_or_default(),
         )
         .map_err(|e| e.to_string())
+        .and_then(|args| {
+            self.query
+                .read
+                .apply(args, self.query.ref_file_path.as_deref())
+        })
+        .and_then(|args| self.query.git.restrict(args))
     }
 }
 
 #[cfg(test)]
 mod test_input_args_validation {
     use super::*;
+    use std::fs;
 
     fn get_valid_args() -> Args {
         Args::new(
@@ -217,17 +733,34 @@ mod test_input_args_validation {
         let valid_args = get_valid_args();
 
         // No changes are made to parameters
-        let input_args = InputArgs {
-            ref_file_path: Some(PathBuf::from("sample_dir_hello_world/file_3.py")),
-            search_path: Some(valid_args.search_path.clone()),
-            max_file_lines: valid_args.max_file_lines.unwrap(),
-            include_glob: Some(vec!["*.py".to_owned()]),
-            exclude_glob: Some(vec!["*.yml".to_owned()]),
-            count: valid_args.count.unwrap(),
+        let input_args = SearchArgs {
+            query: QueryArgs {
+                ref_file_path: Some(PathBuf::from("sample_dir_hello_world/file_3.py")),
+                search_path: Some(valid_args.search_path.clone()),
+                max_file_lines: valid_args.max_file_lines,
+                include_glob: Some(vec!["*.py".to_owned()]),
+                exclude_glob: Some(vec!["*.yml".to_owned()]),
+                config: None,
+                profile: None,
+                no_config: true,
+                read: ReadArgs::default(),
+                git: git::GitArgs::default(),
+            },
+            count: valid_args.count,
             min_similarity_ratio: None,
             format: OutputFormat::Human,
             with_content: false,
+            with_diffs: false,
+            diff: None,
+            diff_output: DiffOutputArgs::default(),
             no_interactive: false,
+            select: false,
+            multi: false,
+            watch: false,
+            list_candidates: false,
+            with_stats: false,
+            print0: false,
+            template: None,
         };
         assert_eq!(
             input_args.into_args(),
@@ -247,17 +780,34 @@ mod test_input_args_validation {
     #[test]
     fn missing_optional_args() {
         let valid_args = get_valid_args();
-        let input_args = InputArgs {
-            ref_file_path: Some(PathBuf::from("sample_dir_hello_world/file_3.py")),
-            search_path: None,
-            max_file_lines: valid_args.max_file_lines.unwrap(),
-            include_glob: None,
-            exclude_glob: None,
-            count: valid_args.count.unwrap(),
+        let input_args = SearchArgs {
+            query: QueryArgs {
+                ref_file_path: Some(PathBuf::from("sample_dir_hello_world/file_3.py")),
+                search_path: None,
+                max_file_lines: valid_args.max_file_lines,
+                include_glob: None,
+                exclude_glob: None,
+                config: None,
+                profile: None,
+                no_config: true,
+                read: ReadArgs::default(),
+                git: git::GitArgs::default(),
+            },
+            count: valid_args.count,
             min_similarity_ratio: None,
             format: OutputFormat::Human,
             with_content: false,
+            with_diffs: false,
+            diff: None,
+            diff_output: DiffOutputArgs::default(),
             no_interactive: false,
+            select: false,
+            multi: false,
+            watch: false,
+            list_candidates: false,
+            with_stats: false,
+            print0: false,
+            template: None,
         };
         assert_eq!(
             input_args.into_args(),
@@ -277,17 +827,34 @@ mod test_input_args_validation {
     #[test]
     fn nonexistent_reference_path() {
         let valid_args = get_valid_args();
-        let input_args_wrong_ref_file = InputArgs {
-            ref_file_path: Some(PathBuf::from("nonexistent_path")),
-            search_path: Some(valid_args.search_path.clone()),
-            max_file_lines: valid_args.max_file_lines.unwrap(),
-            include_glob: Some(vec!["*.py".to_owned()]),
-            exclude_glob: Some(vec!["*.yml".to_owned()]),
-            count: valid_args.count.unwrap(),
+        let input_args_wrong_ref_file = SearchArgs {
+            query: QueryArgs {
+                ref_file_path: Some(PathBuf::from("nonexistent_path")),
+                search_path: Some(valid_args.search_path.clone()),
+                max_file_lines: valid_args.max_file_lines,
+                include_glob: Some(vec!["*.py".to_owned()]),
+                exclude_glob: Some(vec!["*.yml".to_owned()]),
+                config: None,
+                profile: None,
+                no_config: true,
+                read: ReadArgs::default(),
+                git: git::GitArgs::default(),
+            },
+            count: valid_args.count,
             min_similarity_ratio: None,
             format: OutputFormat::Human,
             with_content: false,
+            with_diffs: false,
+            diff: None,
+            diff_output: DiffOutputArgs::default(),
             no_interactive: false,
+            select: false,
+            multi: false,
+            watch: false,
+            list_candidates: false,
+            with_stats: false,
+            print0: false,
+            template: None,
         };
         assert_eq!(
             input_args_wrong_ref_file.into_args(),
@@ -298,23 +865,130 @@ mod test_input_args_validation {
     #[test]
     fn nonexistent_search_path() {
         let valid_args = get_valid_args();
-        let input_args_wrong_ref_file = InputArgs {
-            ref_file_path: Some(PathBuf::from("sample_dir_hello_world/file_3.py")),
-            search_path: Some(PathBuf::from("nonexistent_path")),
-            max_file_lines: valid_args.max_file_lines.unwrap(),
-            include_glob: Some(vec!["*.py".to_owned()]),
-            exclude_glob: Some(vec!["*.yml".to_owned()]),
-            count: valid_args.count.unwrap(),
+        let input_args_wrong_ref_file = SearchArgs {
+            query: QueryArgs {
+                ref_file_path: Some(PathBuf::from("sample_dir_hello_world/file_3.py")),
+                search_path: Some(PathBuf::from("nonexistent_path")),
+                max_file_lines: valid_args.max_file_lines,
+                include_glob: Some(vec!["*.py".to_owned()]),
+                exclude_glob: Some(vec!["*.yml".to_owned()]),
+                config: None,
+                profile: None,
+                no_config: true,
+                read: ReadArgs::default(),
+                git: git::GitArgs::default(),
+            },
+            count: valid_args.count,
             min_similarity_ratio: None,
             format: OutputFormat::Human,
             with_content: false,
+            with_diffs: false,
+            diff: None,
+            diff_output: DiffOutputArgs::default(),
             no_interactive: false,
+            select: false,
+            multi: false,
+            watch: false,
+            list_candidates: false,
+            with_stats: false,
+            print0: false,
+            template: None,
         };
         assert_eq!(
             input_args_wrong_ref_file.into_args(),
             Err("search path not found: nonexistent_path".to_owned())
         );
     }
+
+    fn input_args_with_config(config: PathBuf, profile: Option<&str>) -> SearchArgs {
+        SearchArgs {
+            query: QueryArgs {
+                ref_file_path: Some(PathBuf::from("sample_dir_hello_world/file_3.py")),
+                search_path: Some(PathBuf::from("sample_dir_hello_world")),
+                max_file_lines: None,
+                include_glob: None,
+                exclude_glob: None,
+                config: Some(config),
+                profile: profile.map(str::to_owned),
+                no_config: false,
+                read: ReadArgs::default(),
+                git: git::GitArgs::default(),
+            },
+            count: None,
+            min_similarity_ratio: None,
+            format: OutputFormat::Human,
+            with_content: false,
+            with_diffs: false,
+            diff: None,
+            diff_output: DiffOutputArgs::default(),
+            no_interactive: false,
+            select: false,
+            multi: false,
+            watch: false,
+            list_candidates: false,
+            with_stats: false,
+            print0: false,
+            template: None,
+        }
+    }
+
+    fn write_config(tag: &str, text: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("busca_{tag}_{}.toml", std::process::id()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn config_fills_unset_options_and_flags_win() {
+        let config = write_config(
+            "cli_config",
+            "max_file_lines = 50\ncount = 3\ninclude_glob = [\"*.json\"]\n\n\
+             [profile.strict]\nmin_similarity_ratio = 0.9\n",
+        );
+
+        let from_config = input_args_with_config(config.clone(), None).into_args();
+        let mut flagged = input_args_with_config(config.clone(), Some("strict"));
+        flagged.count = Some(7);
+        flagged.query.include_glob = Some(vec!["*.py".to_owned()]);
+        let with_flags = flagged.into_args();
+        let _ = fs::remove_file(&config);
+
+        let reference_string = fs::read_to_string("sample_dir_hello_world/file_3.py").unwrap();
+        assert_eq!(
+            from_config,
+            Ok(Args::new(
+                reference_string.clone(),
+                PathBuf::from("sample_dir_hello_world"),
+                Some(50),
+                Some(3),
+                None,
+                vec!["*.json".into()],
+                vec![],
+            )
+            .unwrap())
+        );
+        assert_eq!(
+            with_flags,
+            Ok(Args::new(
+                reference_string,
+                PathBuf::from("sample_dir_hello_world"),
+                Some(50),
+                Some(7),
+                Some(0.9),
+                vec!["*.py".into()],
+                vec![],
+            )
+            .unwrap())
+        );
+    }
+
+    #[test]
+    fn unknown_profile_is_an_error() {
+        let config = write_config("cli_unknown_profile", "count = 3\n");
+        let result = input_args_with_config(config.clone(), Some("nope")).into_args();
+        let _ = fs::remove_file(&config);
+        assert_eq!(result, Err("config profile not found: nope".to_owned()));
+    }
 }
 
 fn get_piped_input() -> Result<String, String> {
@@ -344,7 +1018,14 @@ fn interactive_input_mode() -> bool {
     std::io::stdin().is_terminal()
 }
 
-fn cli_run_search(args: &Args) -> Result<Vec<FileComparison>, String> {
+/// Facts about a finished search that the ranked results alone do not carry.
+struct SearchStats {
+    walked_entries: u64,
+    elapsed: Duration,
+}
+
+fn cli_run_search(args: &Args) -> Result<(Vec<FileComparison>, SearchStats), String> {
+    let started = Instant::now();
     let style_result = ProgressStyle::with_template(
         "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] {human_pos} / {human_len} files ({percent}%)",
     );
@@ -361,91 +1042,622 @@ fn cli_run_search(args: &Args) -> Result<Vec<FileComparison>, String> {
         bar.set_position(done);
     });
     bar.finish_and_clear();
-    result.map_err(|e| e.to_string())
+    let stats = SearchStats {
+        walked_entries: bar.length().unwrap_or(0),
+        elapsed: started.elapsed(),
+    };
+    result.map(|r| (r, stats)).map_err(|e| e.to_string())
+}
+
+/// Handles `--list-candidates`: walks and filters like a search but scores
+/// nothing, so no reference is read. Exits `1` when no file qualifies, matching
+/// the empty-result code of a search.
+fn run_list_candidates(input_args: SearchArgs) {
+    let output_format = input_args.format;
+    let with_stats = input_args.with_stats;
+    let print0 = input_args.print0;
+
+    let args = match input_args.into_listing_args() {
+        Ok(args) => args,
+        Err(err_str) => graceful_panic(&err_str),
+    };
+
+    let candidates = match list_candidates(&args) {
+        Ok(candidates) => candidates,
+        Err(err) => graceful_panic(&err.to_string()),
+    };
+
+    if candidates.is_empty() {
+        eprintln!("No candidate files match the criteria.");
+        std::process::exit(1);
+    }
+
+    if print0 || output_format == OutputFormat::Paths {
+        let paths = candidates.iter().map(|c| c.path.display().to_string());
+        write_records(paths, print0);
+        return;
+    }
+
+    match output_format {
+        OutputFormat::Json | OutputFormat::JsonEnvelope => {
+            println!("{}", candidates_to_json(&candidates, with_stats))
+        }
+        OutputFormat::Ndjson => print!("{}", candidates_to_ndjson(&candidates, with_stats)),
+        OutputFormat::Csv | OutputFormat::Tsv => {
+            let (header, rows) = candidate_rows(&candidates, with_stats);
+            print!("{}", to_delimited(&header, &rows, output_format));
+        }
+        OutputFormat::Human | OutputFormat::Paths => {
+            println!("{}", format_candidates(&candidates, with_stats))
+        }
+        OutputFormat::Html | OutputFormat::Markdown | OutputFormat::Sarif => graceful_panic(
+            "--format html, markdown, and sarif are not supported with --list-candidates.",
+        ),
+    }
+}
+
+/// Handles `busca compare`: the similarity ratio of two explicit files and,
+/// unless `--no-diff` is set, their diff. Both files are read as a search reads
+/// its reference and candidates, so the decoding, binary, and extraction
+/// options apply. A binary pair gets no diff. Exits `2` if either file cannot
+/// be read.
+fn run_compare(compare_args: CompareArgs, path_display: Option<PathDisplay>) {
+    // The search path is unused: only `b` is scored.
+    let args = Args::new(
+        String::new(),
+        PathBuf::from("."),
+        None,
+        None,
+        None,
+        vec![],
+        vec![],
+    )
+    .map_err(|e| e.to_string())
+    .and_then(|args| compare_args.read.apply(args, Some(&compare_args.a)))
+    .unwrap_or_else(|e| graceful_panic(&e));
+    let comparison = compare_candidate(&args, &compare_args.b).unwrap_or_else(|| {
+        graceful_panic(&format!(
+            "cannot read '{}': it is missing, binary, or not valid in its encoding",
+            compare_args.b.display()
+        ))
+    });
+    let reference_string = &args.reference_string;
+    let candidate_content = &comparison.content;
+
+    let label = |path: &Path| match path_display {
+        Some(path_display) => path_display.apply(path).display().to_string(),
+        None => path.display().to_string(),
+    };
+    let (a_label, b_label) = (label(&compare_args.a), label(&compare_args.b));
+
+    let summary = format!(
+        "{a_label} vs {b_label}: {:.1}%",
+        comparison.similarity_ratio * 100.0
+    );
+    if compare_args.no_diff || comparison.encoding == BINARY {
+        println!("{summary}");
+        return;
+    }
+    pager::output(&format!(
+        "{summary}\n{}",
+        diff::render(
+            compare_args.diff_output,
+            &a_label,
+            &b_label,
+            reference_string,
+            candidate_content,
+        )
+    ));
+}
+
+/// Handles `busca diff`: prints the diff of the reference against its closest
+/// match without prompting. The reference file itself is skipped when the
+/// search root contains it. Exits `1` when nothing matches, like a search.
+fn run_diff(diff_args: DiffArgs, path_display: Option<PathDisplay>) {
+    let ref_file_path = diff_args.query.ref_file_path.clone();
+    let diff_output = diff_args.diff_output;
+    let args = match diff_args.into_args() {
+        Ok(args) => args,
+        Err(err_str) => graceful_panic(&err_str),
+    };
+    let (mut file_comparisons, _) = match cli_run_search(&args) {
+        Ok(search_results) => search_results,
+        Err(err_str) => graceful_panic(&err_str),
+    };
+    terminal::display_paths(&mut file_comparisons, path_display);
+
+    let top = file_comparisons.iter().find(|fc| {
+        ref_file_path
+            .as_deref()
+            .is_none_or(|reference| !same_file(&fc.path, reference))
+    });
+    let Some(top) = top else {
+        eprintln!("No files found that match the criteria.");
+        std::process::exit(1);
+    };
+
+    let reference_label = match &ref_file_path {
+        Some(path) => path.display().to_string(),
+        None => "(piped input)".to_owned(),
+    };
+    pager::output(&format!(
+        "{}  {:.1}%\n{}",
+        top.path.display(),
+        top.similarity_ratio * 100.0,
+        result_diff(diff_output, &reference_label, &args.reference_string, top)
+    ));
+}
+
+/// Whether two paths name the same file, comparing canonical paths so that
+/// `./a.py` and `a.py` match.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Formats the candidate listing for the terminal: one path per line, or a
+/// grid of path, line count, and size when `with_stats` is set.
+fn format_candidates(candidates: &[Candidate], with_stats: bool) -> String {
+    if !with_stats {
+        return candidates
+            .iter()
+            .map(|c| c.path.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    let mut grid = Grid::new(GridOptions {
+        filling: Filling::Spaces(2),
+        direction: Direction::LeftToRight,
+    });
+    for candidate in candidates {
+        grid.add(Cell::from(candidate.path.display().to_string()));
+
+        let mut lines_cell = Cell::from(format!("{} lines", candidate.line_count));
+        lines_cell.alignment = Alignment::Right;
+        grid.add(lines_cell);
+
+        let mut size_cell = Cell::from(format!("{} B", candidate.size_bytes));
+        size_cell.alignment = Alignment::Right;
+        grid.add(size_cell);
+    }
+
+    let mut display_string = grid.fit_into_columns(3).to_string();
+    if display_string.ends_with('\n') {
+        display_string.pop();
+    }
+    display_string
 }
 
-/// One row of `--format json` output. Built in the CLI so the library and the
-/// Python module never reference `serde`, which keeps it dead-code-eliminated
-/// from the wheel (see ADR-0002).
+/// One row of `--list-candidates --format json` output.
+#[derive(serde::Serialize)]
+struct JsonCandidate {
+    path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line_count: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_bytes: Option<u64>,
+}
+
+fn json_candidates(candidates: &[Candidate], with_stats: bool) -> Vec<JsonCandidate> {
+    candidates
+        .iter()
+        .map(|c| JsonCandidate {
+            path: c.path.display().to_string(),
+            line_count: with_stats.then_some(c.line_count),
+            size_bytes: with_stats.then_some(c.size_bytes),
+        })
+        .collect()
+}
+
+/// Serialize the candidate listing as a pretty JSON array. `line_count` and
+/// `size_bytes` are included only when `with_stats` is set.
+fn candidates_to_json(candidates: &[Candidate], with_stats: bool) -> String {
+    let rows = json_candidates(candidates, with_stats);
+    serde_json::to_string_pretty(&rows).expect("JSON serialization of candidates cannot fail")
+}
+
+/// Serialize the candidate listing as NDJSON: one compact object per line,
+/// each terminated by a newline.
+fn candidates_to_ndjson(candidates: &[Candidate], with_stats: bool) -> String {
+    to_ndjson(&json_candidates(candidates, with_stats))
+}
+
+/// Header and string cells of the candidate listing for CSV and TSV.
+fn candidate_rows(
+    candidates: &[Candidate],
+    with_stats: bool,
+) -> (Vec<&'static str>, Vec<Vec<String>>) {
+    let mut header = vec!["path"];
+    if with_stats {
+        header.extend(["line_count", "size_bytes"]);
+    }
+    let rows = candidates
+        .iter()
+        .map(|c| {
+            let mut row = vec![c.path.display().to_string()];
+            if with_stats {
+                row.extend([c.line_count.to_string(), c.size_bytes.to_string()]);
+            }
+            row
+        })
+        .collect();
+    (header, rows)
+}
+
+/// One row of `--format json` output. Built in the CLI so the library defines
+/// no serde impls of its own (see ADR-0002 and ADR-0004).
 #[derive(serde::Serialize)]
 struct JsonComparison {
     path: String,
     similarity_ratio: f32,
+    encoding: &'static str,
     #[serde(skip_serializing_if = "Option::is_none")]
     content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cell: Option<usize>,
 }
 
-/// Serialize the ranked comparisons as a pretty JSON array. `content` is
-/// included only when `with_content` is set. Serialization of these plain
-/// fields cannot fail.
-fn comparisons_to_json(file_comparisons: &[FileComparison], with_content: bool) -> String {
-    let rows: Vec<JsonComparison> = file_comparisons
+fn json_comparisons(
+    file_comparisons: &[FileComparison],
+    with_content: bool,
+) -> Vec<JsonComparison> {
+    file_comparisons
         .iter()
         .map(|fc| JsonComparison {
             path: fc.path.display().to_string(),
             similarity_ratio: fc.similarity_ratio,
+            encoding: fc.encoding,
             content: with_content.then(|| fc.content.clone()),
+            cell: fc.cell,
         })
-        .collect();
+        .collect()
+}
+
+/// Serialize the ranked comparisons as a pretty JSON array. `content` is
+/// included only when `with_content` is set. Serialization of these plain
+/// fields cannot fail.
+fn comparisons_to_json(file_comparisons: &[FileComparison], with_content: bool) -> String {
+    let rows = json_comparisons(file_comparisons, with_content);
     serde_json::to_string_pretty(&rows).expect("JSON serialization of comparisons cannot fail")
 }
 
-fn output_detailed_diff(reference_string: &str, candidate_content: &str) {
-    let diff = TextDiff::from_lines(reference_string, candidate_content);
+/// Serialize the ranked comparisons as NDJSON, one `--format json` row per
+/// line, so each result can be consumed as soon as its line arrives.
+fn comparisons_to_ndjson(file_comparisons: &[FileComparison], with_content: bool) -> String {
+    to_ndjson(&json_comparisons(file_comparisons, with_content))
+}
 
-    let grouped_operations = diff.grouped_ops(3);
+/// Version of the `--format json-envelope` shape. Bump it on any change that
+/// could break a consumer, and ship a new schema under `docs/schema/`.
+const JSON_ENVELOPE_SCHEMA_VERSION: u32 = 2;
 
-    if grouped_operations.is_empty() {
-        println!("The sequences are identical.");
-        return;
+/// `--format json-envelope` output, described by
+/// `docs/schema/busca-results-v2.schema.json`. Version 1 is the bare array of
+/// `--format json`.
+#[derive(serde::Serialize)]
+struct JsonEnvelope<'a> {
+    schema_version: u32,
+    query: JsonQuery<'a>,
+    stats: JsonStats,
+    results: Vec<JsonComparison>,
+}
+
+#[derive(serde::Serialize)]
+struct JsonQuery<'a> {
+    reference: JsonReference,
+    search_path: String,
+    include_glob: Vec<&'a str>,
+    exclude_glob: Vec<&'a str>,
+    max_file_lines: Option<usize>,
+    count: Option<usize>,
+    min_similarity_ratio: Option<f32>,
+}
+
+/// Where the reference came from. `path` is `null` for piped input.
+#[derive(serde::Serialize)]
+struct JsonReference {
+    source: &'static str,
+    path: Option<String>,
+    line_count: usize,
+}
+
+#[derive(serde::Serialize)]
+struct JsonStats {
+    walked_entries: u64,
+    result_count: usize,
+    elapsed_ms: u64,
+}
+
+/// Serialize the ranked comparisons inside a versioned envelope that also
+/// records the query that produced them and how the search went.
+fn json_envelope(
+    args: &Args,
+    ref_file_path: Option<&Path>,
+    stats: &SearchStats,
+    file_comparisons: &[FileComparison],
+    with_content: bool,
+) -> String {
+    let envelope = JsonEnvelope {
+        schema_version: JSON_ENVELOPE_SCHEMA_VERSION,
+        query: JsonQuery {
+            reference: JsonReference {
+                source: if ref_file_path.is_some() {
+                    "file"
+                } else {
+                    "stdin"
+                },
+                path: ref_file_path.map(|p| p.display().to_string()),
+                line_count: args.reference_string.lines().count(),
+            },
+            search_path: args.search_path.display().to_string(),
+            include_glob: args.include_glob(),
+            exclude_glob: args.exclude_glob(),
+            max_file_lines: args.max_file_lines,
+            count: args.count,
+            min_similarity_ratio: args.min_similarity_ratio,
+        },
+        stats: JsonStats {
+            walked_entries: stats.walked_entries,
+            result_count: file_comparisons.len(),
+            elapsed_ms: stats.elapsed.as_millis() as u64,
+        },
+        results: json_comparisons(file_comparisons, with_content),
+    };
+    serde_json::to_string_pretty(&envelope).expect("JSON serialization of the envelope cannot fail")
+}
+
+fn to_ndjson<T: serde::Serialize>(rows: &[T]) -> String {
+    let mut out = String::new();
+    for row in rows {
+        out.push_str(&serde_json::to_string(row).expect("JSON serialization cannot fail"));
+        out.push('\n');
+    }
+    out
+}
+
+/// Header and string cells of the ranked comparisons for CSV and TSV. The
+/// ratio uses the same shortest round-trip form as the JSON output.
+fn comparison_rows(
+    file_comparisons: &[FileComparison],
+    with_content: bool,
+) -> (Vec<&'static str>, Vec<Vec<String>>) {
+    let mut header = vec!["path", "similarity_ratio", "encoding", "cell"];
+    if with_content {
+        header.push("content");
     }
+    let rows = file_comparisons
+        .iter()
+        .map(|fc| {
+            let mut row = vec![
+                fc.path.display().to_string(),
+                fc.similarity_ratio.to_string(),
+                fc.encoding.to_owned(),
+                fc.cell.map(|cell| cell.to_string()).unwrap_or_default(),
+            ];
+            if with_content {
+                row.push(fc.content.clone());
+            }
+            row
+        })
+        .collect();
+    (header, rows)
+}
+
+/// Renders a header and rows as CSV or TSV, one record per line. CSV quotes a
+/// field only when it holds a comma, quote, or line break (RFC 4180). TSV
+/// cannot quote, so tabs, line breaks, and backslashes are backslash-escaped.
+fn to_delimited(header: &[&str], rows: &[Vec<String>], format: OutputFormat) -> String {
+    let (separator, escape): (&str, fn(&str) -> String) = match format {
+        OutputFormat::Tsv => ("\t", tsv_field),
+        _ => (",", csv_field),
+    };
+    let mut out = header.join(separator);
+    out.push('\n');
+    for row in rows {
+        let fields: Vec<String> = row.iter().map(|field| escape(field)).collect();
+        out.push_str(&fields.join(separator));
+        out.push('\n');
+    }
+    out
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
 
-    for (idx, group) in grouped_operations.iter().enumerate() {
-        if idx > 0 {
-            println!("{:-^1$}", "-", 80);
+fn tsv_field(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            _ => out.push(c),
         }
-        for op in group {
-            for change in diff.iter_inline_changes(op) {
-                let (sign, s) = match change.tag() {
-                    ChangeTag::Delete => ("-", Style::new().red()),
-                    ChangeTag::Insert => ("+", Style::new().green()),
-                    ChangeTag::Equal => (" ", Style::new().dim()),
-                };
-                print!(
-                    "{} {} {} |",
-                    style(Line(change.old_index())).dim(),
-                    style(Line(change.new_index())).dim(),
-                    s.apply_to(sign).bold(),
-                );
-                for (emphasized, value) in change.iter_strings_lossy() {
-                    if emphasized {
-                        print!("{}", s.apply_to(value).underlined().on_black());
-                    } else {
-                        print!("{}", s.apply_to(value));
+    }
+    out
+}
+
+/// Writes each record followed by a newline, or by NUL when `print0` is set.
+fn write_records(records: impl Iterator<Item = String>, print0: bool) {
+    let terminator = if print0 { '\0' } else { '\n' };
+    let mut stdout = std::io::stdout().lock();
+    for record in records {
+        if let Err(err) = write!(stdout, "{record}{terminator}") {
+            graceful_panic(&err.to_string());
+        }
+    }
+}
+
+/// A parsed `--template`: literal text interleaved with result fields.
+#[derive(Clone, Debug, PartialEq)]
+struct Template {
+    segments: Vec<TemplateSegment>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TemplateSegment {
+    Literal(String),
+    Field {
+        field: TemplateField,
+        precision: Option<usize>,
+    },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum TemplateField {
+    Rank,
+    Path,
+    SimilarityRatio,
+    Content,
+    Encoding,
+    Cell,
+}
+
+impl Template {
+    /// Renders one result. `rank` is the 1-based position in the ranking.
+    fn render(&self, rank: usize, fc: &FileComparison) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                TemplateSegment::Literal(text) => out.push_str(text),
+                TemplateSegment::Field { field, precision } => {
+                    let value = match (field, precision) {
+                        (TemplateField::Rank, _) => rank.to_string(),
+                        (TemplateField::Path, _) => fc.path.display().to_string(),
+                        (TemplateField::SimilarityRatio, Some(p)) => {
+                            format!("{:.*}", p, fc.similarity_ratio)
+                        }
+                        (TemplateField::SimilarityRatio, None) => fc.similarity_ratio.to_string(),
+                        (TemplateField::Content, _) => fc.content.clone(),
+                        (TemplateField::Encoding, _) => fc.encoding.to_owned(),
+                        (TemplateField::Cell, _) => {
+                            fc.cell.map(|cell| cell.to_string()).unwrap_or_default()
+                        }
+                    };
+                    out.push_str(&value);
+                }
+            }
+        }
+        out
+    }
+}
+
+fn parse_template(s: &str) -> Result<Template, String> {
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => literal.push('\t'),
+                Some('n') => literal.push('\n'),
+                Some('0') => literal.push('\0'),
+                Some('\\') => literal.push('\\'),
+                Some(other) => {
+                    literal.push('\\');
+                    literal.push(other);
+                }
+                None => literal.push('\\'),
+            },
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '}' => return Err("unmatched '}'; write '}}' for a literal brace".to_owned()),
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => {
+                            return Err("unclosed '{'; write '{{' for a literal brace".to_owned())
+                        }
                     }
                 }
-                if change.missing_newline() {
-                    println!();
+                if !literal.is_empty() {
+                    segments.push(TemplateSegment::Literal(std::mem::take(&mut literal)));
                 }
+                segments.push(parse_placeholder(&placeholder)?);
             }
+            _ => literal.push(c),
         }
     }
+    if !literal.is_empty() {
+        segments.push(TemplateSegment::Literal(literal));
+    }
+    Ok(Template { segments })
 }
 
-struct Line(Option<usize>);
-
-impl fmt::Display for Line {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.0 {
-            None => write!(f, "    "),
-            Some(idx) => write!(f, "{:<4}", idx + 1),
+fn parse_placeholder(placeholder: &str) -> Result<TemplateSegment, String> {
+    let (name, spec) = match placeholder.split_once(':') {
+        Some((name, spec)) => (name, Some(spec)),
+        None => (placeholder, None),
+    };
+    let field = match name {
+        "rank" => TemplateField::Rank,
+        "path" => TemplateField::Path,
+        "similarity_ratio" | "ratio" => TemplateField::SimilarityRatio,
+        "content" => TemplateField::Content,
+        "encoding" => TemplateField::Encoding,
+        "cell" => TemplateField::Cell,
+        _ => {
+            return Err(format!(
+                "unknown placeholder '{{{name}}}'; expected rank, path, similarity_ratio, ratio, content, encoding, or cell"
+            ))
         }
-    }
+    };
+    let precision = match spec {
+        None => None,
+        Some(spec) if field == TemplateField::SimilarityRatio => Some(
+            spec.strip_prefix('.')
+                .and_then(|digits| digits.parse::<usize>().ok())
+                .ok_or_else(|| {
+                    format!("invalid format spec ':{spec}'; expected a precision like ':.3'")
+                })?,
+        ),
+        Some(_) => return Err(format!("'{{{name}}}' does not take a format spec")),
+    };
+    Ok(TemplateSegment::Field { field, precision })
+}
+
+/// The diff of the reference against one result. Reuses the content
+/// captured during the search rather than reading the file again, which keeps
+/// the diff consistent with the ranked ratio and avoids a second read that
+/// could fail if the file changed meanwhile.
+fn result_diff(
+    options: DiffOutputArgs,
+    reference_label: &str,
+    reference_string: &str,
+    file_comparison: &FileComparison,
+) -> String {
+    diff::render(
+        options,
+        reference_label,
+        &file_comparison.path.display().to_string(),
+        reference_string,
+        &file_comparison.content,
+    )
 }
 
 #[cfg(test)]
 mod test_cli_run_search {
     use super::*;
+    use std::fs;
 
     fn get_valid_args() -> Args {
         Args::new(
@@ -465,18 +1677,18 @@ mod test_cli_run_search {
         let valid_args = get_valid_args();
 
         let expected = vec![
-            FileComparison {
-                path: PathBuf::from("sample_dir_hello_world/nested_dir/ref_B.py"),
-                similarity_ratio: 1.0,
-                content: fs::read_to_string("sample_dir_hello_world/nested_dir/ref_B.py").unwrap(),
-            },
-            FileComparison {
-                path: PathBuf::from("sample_dir_hello_world/file_1.py"),
-                similarity_ratio: 2.0 / 9.0,
-                content: fs::read_to_string("sample_dir_hello_world/file_1.py").unwrap(),
-            },
+            FileComparison::new(
+                PathBuf::from("sample_dir_hello_world/nested_dir/ref_B.py"),
+                1.0,
+                fs::read_to_string("sample_dir_hello_world/nested_dir/ref_B.py").unwrap(),
+            ),
+            FileComparison::new(
+                PathBuf::from("sample_dir_hello_world/file_1.py"),
+                2.0 / 9.0,
+                fs::read_to_string("sample_dir_hello_world/file_1.py").unwrap(),
+            ),
         ];
-        assert_eq!(cli_run_search(&valid_args).unwrap(), expected);
+        assert_eq!(cli_run_search(&valid_args).unwrap().0, expected);
     }
 
     #[test]
@@ -492,13 +1704,12 @@ mod test_cli_run_search {
         )
         .unwrap();
 
-        let expected = vec![FileComparison {
-            path: PathBuf::from("sample_dir_hello_world/nested_dir/sample_json.json"),
-            similarity_ratio: 0.0,
-            content: fs::read_to_string("sample_dir_hello_world/nested_dir/sample_json.json")
-                .unwrap(),
-        }];
-        assert_eq!(cli_run_search(&valid_args).unwrap(), expected);
+        let expected = vec![FileComparison::new(
+            PathBuf::from("sample_dir_hello_world/nested_dir/sample_json.json"),
+            0.0,
+            fs::read_to_string("sample_dir_hello_world/nested_dir/sample_json.json").unwrap(),
+        )];
+        assert_eq!(cli_run_search(&valid_args).unwrap().0, expected);
     }
 
     #[test]
@@ -515,18 +1726,18 @@ mod test_cli_run_search {
         .unwrap();
 
         let expected = vec![
-            FileComparison {
-                path: PathBuf::from("sample_dir_hello_world/nested_dir/ref_B.py"),
-                similarity_ratio: 1.0,
-                content: fs::read_to_string("sample_dir_hello_world/nested_dir/ref_B.py").unwrap(),
-            },
-            FileComparison {
-                path: PathBuf::from("sample_dir_hello_world/file_1.py"),
-                similarity_ratio: 2.0 / 9.0,
-                content: fs::read_to_string("sample_dir_hello_world/file_1.py").unwrap(),
-            },
+            FileComparison::new(
+                PathBuf::from("sample_dir_hello_world/nested_dir/ref_B.py"),
+                1.0,
+                fs::read_to_string("sample_dir_hello_world/nested_dir/ref_B.py").unwrap(),
+            ),
+            FileComparison::new(
+                PathBuf::from("sample_dir_hello_world/file_1.py"),
+                2.0 / 9.0,
+                fs::read_to_string("sample_dir_hello_world/file_1.py").unwrap(),
+            ),
         ];
-        assert_eq!(cli_run_search(&valid_args).unwrap(), expected);
+        assert_eq!(cli_run_search(&valid_args).unwrap().0, expected);
     }
 }
 
@@ -587,3 +1798,85 @@ mod test_parse_count {
         assert!(parse_count("").is_err());
     }
 }
+
+#[cfg(test)]
+mod test_output_formats {
+    use super::*;
+
+    fn fc(path: &str, similarity_ratio: f32, content: &str) -> FileComparison {
+        FileComparison::new(PathBuf::from(path), similarity_ratio, content.to_owned())
+    }
+
+    #[test]
+    fn template_renders_every_field() {
+        let template =
+            parse_template(r"{rank}\t{path}\t{ratio:.3}\t{similarity_ratio}\t{encoding}|{content}")
+                .unwrap();
+        assert_eq!(
+            template.render(2, &fc("a/b.py", 0.5, "x\n")),
+            "2\ta/b.py\t0.500\t0.5\tUTF-8|x\n"
+        );
+        let mut notebook = fc("n.ipynb", 1.0, "");
+        notebook.cell = Some(3);
+        assert_eq!(
+            parse_template("{path}#{cell}")
+                .unwrap()
+                .render(1, &notebook),
+            "n.ipynb#3"
+        );
+        assert_eq!(
+            parse_template("{cell}")
+                .unwrap()
+                .render(1, &fc("a.py", 1.0, "")),
+            ""
+        );
+    }
+
+    #[test]
+    fn template_escapes_braces() {
+        let template = parse_template("{{{path}}}").unwrap();
+        assert_eq!(template.render(1, &fc("p", 1.0, "")), "{p}");
+    }
+
+    #[test]
+    fn template_rejects_bad_placeholders() {
+        for bad in [
+            "{nope}",
+            "{path",
+            "path}",
+            "{path:.3}",
+            "{ratio:3}",
+            "{ratio:.x}",
+        ] {
+            assert!(parse_template(bad).is_err(), "accepted {bad:?}");
+        }
+    }
+
+    #[test]
+    fn csv_quotes_only_when_needed() {
+        assert_eq!(csv_field("plain.py"), "plain.py");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn tsv_escapes_separators() {
+        assert_eq!(tsv_field("a\tb\nc\\d"), "a\\tb\\nc\\\\d");
+    }
+
+    #[test]
+    fn delimited_has_header_and_one_line_per_row() {
+        let comparisons = [fc("a.py", 1.0, "x,y"), fc("b.py", 0.25, "z")];
+        let (header, rows) = comparison_rows(&comparisons, true);
+        assert_eq!(
+            to_delimited(&header, &rows, OutputFormat::Csv),
+            "path,similarity_ratio,encoding,cell,content\na.py,1,UTF-8,,\"x,y\"\nb.py,0.25,UTF-8,,z\n"
+        );
+        let (header, rows) = comparison_rows(&comparisons, false);
+        assert_eq!(
+            to_delimited(&header, &rows, OutputFormat::Tsv),
+            "path\tsimilarity_ratio\tencoding\tcell\na.py\t1\tUTF-8\t\nb.py\t0.25\tUTF-8\t\n"
+        );
+    }
+}
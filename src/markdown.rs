@@ -0,0 +1,151 @@
+//! `--format markdown`: a GitHub-flavored Markdown table of the ranked
+//! comparisons, sized for a pull-request comment. With `--with-diffs` each
+//! result also gets a unified diff collapsed under `<details>`, so the comment
+//! stays short until a reviewer expands it.
+
+use busca::FileComparison;
+use similar::{DiffTag, TextDiff};
+use std::fmt::Write;
+
+/// Renders the results table, followed by one collapsed diff per result when
+/// `with_diffs` is `Some`, holding the lines of context around each hunk.
+/// `reference_label` names the reference in the heading and the diff headers:
+/// its path, or a placeholder for piped input.
+pub fn render(
+    reference_label: &str,
+    reference_string: &str,
+    search_path: &str,
+    file_comparisons: &[FileComparison],
+    with_diffs: Option<usize>,
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "### busca: {} similar file{}\n",
+        file_comparisons.len(),
+        if file_comparisons.len() == 1 { "" } else { "s" },
+    );
+    let _ = writeln!(
+        out,
+        "Reference {} searched in {}.\n",
+        code_span(reference_label),
+        code_span(search_path),
+    );
+    out.push_str("| Rank | Path | Similarity |\n| ---: | --- | ---: |\n");
+    for (index, fc) in file_comparisons.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {:.1}% |",
+            index + 1,
+            code_span(&fc.path.display().to_string()).replace('|', "\\|"),
+            fc.similarity_ratio * 100.0,
+        );
+    }
+
+    if let Some(context_lines) = with_diffs {
+        for (index, fc) in file_comparisons.iter().enumerate() {
+            let path = fc.path.display().to_string();
+            let _ = writeln!(
+                out,
+                "\n<details>\n<summary>{}. {} ({:.1}%)</summary>\n",
+                index + 1,
+                escape_html(&path),
+                fc.similarity_ratio * 100.0,
+            );
+            out.push_str(&diff_block(
+                reference_label,
+                reference_string,
+                &path,
+                &fc.content,
+                context_lines,
+            ));
+            out.push_str("\n</details>\n");
+        }
+    }
+    out
+}
+
+/// A fenced ```` ```diff ```` block with the unified diff of the reference
+/// against one candidate, or a note when they are identical.
+fn diff_block(
+    reference_label: &str,
+    reference_string: &str,
+    candidate_label: &str,
+    candidate_content: &str,
+    context_lines: usize,
+) -> String {
+    let diff = TextDiff::from_lines(reference_string, candidate_content);
+    if diff.ops().iter().all(|op| op.tag() == DiffTag::Equal) {
+        return "The sequences are identical.\n".to_owned();
+    }
+    let mut unified = diff
+        .unified_diff()
+        .context_radius(context_lines)
+        .header(reference_label, candidate_label)
+        .to_string();
+    if !unified.ends_with('\n') {
+        unified.push('\n');
+    }
+    let fence = "`".repeat(longest_backtick_run(&unified).max(2) + 1);
+    format!("{fence}diff\n{unified}{fence}\n")
+}
+
+/// Wraps text in a code span, lengthening the delimiter past any backtick run
+/// inside it as CommonMark requires.
+fn code_span(text: &str) -> String {
+    let ticks = "`".repeat(longest_backtick_run(text) + 1);
+    if text.starts_with('`') || text.ends_with('`') {
+        format!("{ticks} {text} {ticks}")
+    } else {
+        format!("{ticks}{text}{ticks}")
+    }
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+/// Escapes the characters that would otherwise start markup inside the
+/// `<summary>` element.
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod test_markdown {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn comparisons() -> Vec<FileComparison> {
+        vec![
+            FileComparison::new(PathBuf::from("same.py"), 1.0, "x = 1\n".to_owned()),
+            FileComparison::new(PathBuf::from("a|b.py"), 0.5, "x = 2\n".to_owned()),
+        ]
+    }
+
+    #[test]
+    fn table_escapes_pipes_in_paths() {
+        let out = render("ref.py", "x = 1\n", "src", &comparisons(), None);
+        assert!(out.starts_with("### busca: 2 similar files\n"));
+        assert!(out.contains("| 1 | `same.py` | 100.0% |\n"));
+        assert!(out.contains("| 2 | `a\\|b.py` | 50.0% |\n"));
+        assert!(!out.contains("<details>"));
+    }
+
+    #[test]
+    fn diffs_are_collapsed_unified_diffs() {
+        let out = render("ref.py", "x = 1\n", "src", &comparisons(), Some(3));
+        assert_eq!(out.matches("<details>").count(), 2);
+        assert!(out.contains("The sequences are identical."));
+        assert!(out.contains("```diff\n--- ref.py\n+++ a|b.py\n@@ -1 +1 @@\n-x = 1\n+x = 2\n```\n"));
+    }
+
+    #[test]
+    fn code_spans_outgrow_backticks() {
+        assert_eq!(code_span("a.py"), "`a.py`");
+        assert_eq!(code_span("a`b"), "``a`b``");
+        assert_eq!(code_span("`a"), "`` `a ``");
+    }
+}
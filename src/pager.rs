@@ -0,0 +1,193 @@
+//! Shows long output through a pager when stdout is a terminal, as `git` does:
+//! `$BUSCA_PAGER`, then `$PAGER`, then `less -R`.
+
+use std::env;
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Used when neither `$BUSCA_PAGER` nor `$PAGER` is set. `-R` passes color
+/// escape codes through.
+const DEFAULT_PAGER: &str = "less -R";
+
+/// `less` options set when `$LESS` is unset: quit if the text fits on one
+/// screen, pass colors through, and leave the text on screen after quitting.
+const DEFAULT_LESS: &str = "FRX";
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Disables paging for the rest of the process, for `--no-pager`.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Prints `text` to stdout, through the pager when paging is enabled and
+/// stdout is a terminal. Colors were already decided for stdout, which is a
+/// terminal whenever the pager runs, so the escape codes reach the pager as
+/// they would the terminal.
+pub fn output(text: &str) {
+    let pager = pager_command(env::var("BUSCA_PAGER").ok(), env::var("PAGER").ok());
+    match pager {
+        Some(pager) if ENABLED.load(Ordering::Relaxed) && std::io::stdout().is_terminal() => {
+            page(&pager, text, Terminal::Stdout)
+        }
+        _ => print!("{text}"),
+    }
+}
+
+/// Shows `text` on the terminal without touching stdout unless it is the
+/// terminal: like [`output`] when stdout is a terminal, and otherwise on
+/// stderr, paged when stderr is a terminal. Used by the picker, whose stdout
+/// may be captured by the shell.
+pub fn show(text: &str) {
+    if std::io::stdout().is_terminal() {
+        output(text);
+        return;
+    }
+    let pager = pager_command(env::var("BUSCA_PAGER").ok(), env::var("PAGER").ok());
+    match pager {
+        Some(pager) if ENABLED.load(Ordering::Relaxed) && std::io::stderr().is_terminal() => {
+            page(&pager, text, Terminal::Stderr)
+        }
+        _ => eprint!("{text}"),
+    }
+}
+
+/// The pager command line: `$BUSCA_PAGER`, then `$PAGER`, then `less -R`. A
+/// variable that is set but empty, or set to `cat`, turns paging off.
+fn pager_command(busca_pager: Option<String>, pager: Option<String>) -> Option<String> {
+    let command = busca_pager
+        .or(pager)
+        .unwrap_or_else(|| DEFAULT_PAGER.to_owned());
+    let command = command.trim();
+    match command {
+        "" | "cat" => None,
+        _ => Some(command.to_owned()),
+    }
+}
+
+/// The stream a pager writes to, and that its text is printed to instead when
+/// the pager cannot be started.
+#[derive(Clone, Copy)]
+enum Terminal {
+    Stdout,
+    Stderr,
+}
+
+impl Terminal {
+    fn stdio(self) -> Stdio {
+        match self {
+            Terminal::Stdout => Stdio::inherit(),
+            Terminal::Stderr => Stdio::from(std::io::stderr()),
+        }
+    }
+
+    fn print(self, text: &str) {
+        match self {
+            Terminal::Stdout => print!("{text}"),
+            Terminal::Stderr => eprint!("{text}"),
+        }
+    }
+}
+
+/// Writes `text` to the pager's stdin and waits for it to exit. The pager
+/// writes to `terminal`. Falls back to printing directly to `terminal` if the
+/// pager cannot be started.
+fn page(pager: &str, text: &str, terminal: Terminal) {
+    let Some(mut command) = pager_process(pager) else {
+        terminal.print(text);
+        return;
+    };
+    command.stdin(Stdio::piped()).stdout(terminal.stdio());
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", DEFAULT_LESS);
+    }
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(_) => {
+            terminal.print(text);
+            return;
+        }
+    };
+    // Ctrl-C belongs to the pager while it runs, as in `git log`; without this
+    // it would also kill busca and leave the terminal in the pager's mode.
+    let _sigint = IgnoreInterrupts::new();
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager closes its end when the user quits before reading
+        // everything; that broken pipe is not an error.
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let _ = child.wait();
+}
+
+/// The process that runs the pager command line. On Unix the shell runs it, as
+/// `git` does, so quoting, variables, and pipes in `$PAGER` work.
+#[cfg(unix)]
+fn pager_process(pager: &str) -> Option<Command> {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(pager);
+    Some(command)
+}
+
+/// The process that runs the pager command line. Without a POSIX shell, the
+/// line is split on whitespace into a program and its arguments.
+#[cfg(not(unix))]
+fn pager_process(pager: &str) -> Option<Command> {
+    let mut words = pager.split_whitespace();
+    let mut command = Command::new(words.next()?);
+    command.args(words);
+    Some(command)
+}
+
+/// Ignores SIGINT until dropped, then restores the previous handler.
+struct IgnoreInterrupts {
+    #[cfg(unix)]
+    previous: libc::sighandler_t,
+}
+
+impl IgnoreInterrupts {
+    fn new() -> Self {
+        Self {
+            // SAFETY: installing SIG_IGN has no handler code to be unsafe in;
+            // the previous disposition is restored on drop.
+            #[cfg(unix)]
+            previous: unsafe { libc::signal(libc::SIGINT, libc::SIG_IGN) },
+        }
+    }
+}
+
+impl Drop for IgnoreInterrupts {
+    fn drop(&mut self) {
+        // SAFETY: reinstates the disposition that `signal` returned in `new`.
+        #[cfg(unix)]
+        unsafe {
+            libc::signal(libc::SIGINT, self.previous);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_pager {
+    use super::*;
+
+    #[test]
+    fn busca_pager_wins_and_empty_or_cat_disables() {
+        let some = |s: &str| Some(s.to_owned());
+        assert_eq!(pager_command(some("delta"), some("more")), some("delta"));
+        assert_eq!(pager_command(None, some("more")), some("more"));
+        assert_eq!(pager_command(None, None), some("less -R"));
+        assert_eq!(pager_command(some(""), some("more")), None);
+        assert_eq!(pager_command(None, some("cat")), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_shell_runs_the_pager_command_line() {
+        let out = std::env::temp_dir().join(format!("busca_pager_{}", std::process::id()));
+        let pager = format!("tr a b | sed 's/b b/c/' > '{}'", out.display());
+        page(&pager, "a a\n", Terminal::Stdout);
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "c\n");
+        let _ = std::fs::remove_file(out);
+    }
+}
@@ -0,0 +1,193 @@
+//! The interactive picker of the human format. The ranked results stay in
+//! memory while the user picks one, views its diff in a pager, opens it in an
+//! editor, or prints its path, and then returns to the list. The picker draws
+//! on stderr; only a printed path goes to stdout.
+
+use crate::diff::{self, DiffOutputArgs};
+use crate::{graceful_panic, pager, terminal};
+use busca::FileComparison;
+use console::Term;
+use inquire::{InquireError, MultiSelect, Select};
+use std::env;
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+/// Lines of the first differing hunk shown before the action menu.
+const PREVIEW_LINES: usize = 12;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Action {
+    ViewDiff,
+    OpenInEditor,
+    PrintPath,
+    Back,
+    Quit,
+}
+
+impl Action {
+    const ALL: [Action; 5] = [
+        Action::ViewDiff,
+        Action::OpenInEditor,
+        Action::PrintPath,
+        Action::Back,
+        Action::Quit,
+    ];
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Action::ViewDiff => "View diff",
+            Action::OpenInEditor => "Open in $EDITOR",
+            Action::PrintPath => "Print path and exit",
+            Action::Back => "Back to the list",
+            Action::Quit => "Quit",
+        })
+    }
+}
+
+/// Runs the picker until the user quits or prints a path. Escape in the list
+/// quits; escape in the action menu returns to the list.
+pub fn run(
+    file_comparisons: &[FileComparison],
+    reference_label: &str,
+    reference_string: &str,
+    diff_output: DiffOutputArgs,
+) {
+    let grid = grid(file_comparisons);
+    let grid_options: Vec<&str> = grid.split('\n').collect();
+
+    let mut cursor = 0;
+    loop {
+        let answer = match Select::new("Select a file to compare:", grid_options.clone())
+            .with_page_size(10)
+            .with_starting_cursor(cursor)
+            .raw_prompt()
+        {
+            Ok(answer) => answer,
+            Err(InquireError::OperationCanceled) => return,
+            Err(err) => graceful_panic(&err.to_string()),
+        };
+        cursor = answer.index;
+
+        // Reuse the content captured during the search rather than reading the
+        // file again. That keeps the diff consistent with the ranked ratio and
+        // avoids a second read that could fail if the file changed meanwhile.
+        let selected = &file_comparisons[answer.index];
+        eprint!(
+            "{}",
+            diff::first_hunk(
+                diff_output.context_lines,
+                reference_string,
+                &selected.content,
+                PREVIEW_LINES,
+            )
+        );
+
+        let prompt = format!("{}:", selected.path.display());
+        let action = match Select::new(&prompt, Action::ALL.to_vec()).prompt() {
+            Ok(action) => action,
+            Err(InquireError::OperationCanceled) => Action::Back,
+            Err(err) => graceful_panic(&err.to_string()),
+        };
+        match action {
+            Action::ViewDiff => pager::show(&diff::render(
+                diff_output,
+                reference_label,
+                &selected.path.display().to_string(),
+                reference_string,
+                &selected.content,
+            )),
+            Action::OpenInEditor => {
+                if let Err(err) = open_in_editor(&selected.path) {
+                    eprintln!("{err}");
+                }
+            }
+            Action::PrintPath => {
+                println!("{}", selected.path.display());
+                return;
+            }
+            Action::Back => {}
+            Action::Quit => return,
+        }
+    }
+}
+
+/// The `--select` picker: returns the chosen results, or none if the user
+/// cancels. With `multiple`, space toggles results and enter confirms.
+pub fn select(file_comparisons: &[FileComparison], multiple: bool) -> Vec<&FileComparison> {
+    let grid = grid(file_comparisons);
+    let grid_options: Vec<&str> = grid.split('\n').collect();
+
+    let indices = if multiple {
+        MultiSelect::new("Select files:", grid_options)
+            .with_page_size(10)
+            .raw_prompt()
+            .map(|answers| answers.iter().map(|answer| answer.index).collect())
+    } else {
+        Select::new("Select a file:", grid_options)
+            .with_page_size(10)
+            .raw_prompt()
+            .map(|answer| vec![answer.index])
+    };
+    match indices {
+        Ok(indices) => indices
+            .into_iter()
+            .map(|index| &file_comparisons[index])
+            .collect(),
+        Err(InquireError::OperationCanceled) => Vec::new(),
+        Err(err) => graceful_panic(&err.to_string()),
+    }
+}
+
+/// The ranked grid as picker options. The picker draws on stderr and indents
+/// each option by two columns.
+fn grid(file_comparisons: &[FileComparison]) -> String {
+    let width = terminal::width(&Term::stderr()).map(|width| width.saturating_sub(2));
+    terminal::format_grid(file_comparisons, width)
+}
+
+/// Opens `path` in `$VISUAL` or `$EDITOR` (default `vi`) and waits for it to
+/// exit.
+fn open_in_editor(path: &Path) -> Result<(), String> {
+    let editor = editor_command(env::var("VISUAL").ok(), env::var("EDITOR").ok());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()
+        .map_err(|e| format!("cannot start editor '{editor}': {e}"))?;
+    if !status.success() {
+        return Err(format!("editor '{editor}' exited with {status}"));
+    }
+    Ok(())
+}
+
+/// The editor command line: `$VISUAL`, then `$EDITOR`, skipping empty values.
+fn editor_command(visual: Option<String>, editor: Option<String>) -> String {
+    [visual, editor]
+        .into_iter()
+        .flatten()
+        .find(|command| !command.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_owned())
+}
+
+#[cfg(test)]
+mod test_picker {
+    use super::*;
+
+    #[test]
+    fn editor_prefers_visual_then_editor() {
+        assert_eq!(
+            editor_command(Some("code -w".into()), Some("nano".into())),
+            "code -w"
+        );
+        assert_eq!(
+            editor_command(Some(" ".into()), Some("nano".into())),
+            "nano"
+        );
+        assert_eq!(editor_command(None, None), "vi");
+    }
+}
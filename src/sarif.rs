@@ -0,0 +1,191 @@
+//! `--format sarif`: a SARIF 2.1.0 log for code-scanning dashboards and CI
+//! duplicate-code gates. Every ranked comparison becomes one result; the search
+//! has already dropped those below `--min-similarity-ratio`, so that flag is
+//! the gate's threshold. Each result points at the region of the candidate that
+//! matches the reference.
+
+use busca::FileComparison;
+use serde_json::{json, Value};
+use similar::{DiffTag, TextDiff};
+use std::ops::Range;
+use std::path::Path;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const RULE_ID: &str = "busca/similar-file";
+const INFORMATION_URI: &str = "https://github.com/noahbaculi/busca";
+
+/// Renders the SARIF log. `reference_path` is `None` for piped input, in which
+/// case results carry no related location for the reference.
+pub fn render(
+    reference_path: Option<&Path>,
+    reference_string: &str,
+    file_comparisons: &[FileComparison],
+) -> String {
+    let reference_label = match reference_path {
+        Some(path) => format!("'{}'", path.display()),
+        None => "the piped reference".to_owned(),
+    };
+    let results: Vec<Value> = file_comparisons
+        .iter()
+        .enumerate()
+        .map(|(index, fc)| {
+            let matched = matched_lines(reference_string, &fc.content);
+            let mut location = json!({
+                "physicalLocation": {
+                    "artifactLocation": { "uri": path_to_uri(&fc.path) },
+                },
+            });
+            if let Some((_, new_lines)) = &matched {
+                location["physicalLocation"]["region"] = region(new_lines);
+            }
+            let mut result = json!({
+                "ruleId": RULE_ID,
+                "ruleIndex": 0,
+                "level": "warning",
+                "message": {
+                    "text": format!(
+                        "{:.1}% similar to {reference_label}.",
+                        fc.similarity_ratio * 100.0
+                    ),
+                },
+                "locations": [location],
+                "properties": {
+                    "rank": index + 1,
+                    "similarity_ratio": fc.similarity_ratio,
+                },
+            });
+            if let Some(reference_path) = reference_path {
+                let mut related = json!({
+                    "id": 0,
+                    "message": { "text": "Reference" },
+                    "physicalLocation": {
+                        "artifactLocation": { "uri": path_to_uri(reference_path) },
+                    },
+                });
+                if let Some((old_lines, _)) = &matched {
+                    related["physicalLocation"]["region"] = region(old_lines);
+                }
+                result["relatedLocations"] = json!([related]);
+            }
+            result
+        })
+        .collect();
+
+    let log = json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "busca",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": INFORMATION_URI,
+                    "rules": [{
+                        "id": RULE_ID,
+                        "name": "SimilarFile",
+                        "shortDescription": {
+                            "text": "File is similar to the reference.",
+                        },
+                        "fullDescription": {
+                            "text": "The file's lines closely match the reference file, \
+                                     which may indicate duplicated code.",
+                        },
+                        "defaultConfiguration": { "level": "warning" },
+                    }],
+                },
+            },
+            "results": results,
+        }],
+    });
+    serde_json::to_string_pretty(&log).expect("a serde_json::Value always serializes")
+}
+
+/// The 0-based, end-exclusive line spans of the reference and the candidate
+/// that the diff matches: from the first to the last unchanged line on each
+/// side. `None` when the files share no line.
+pub fn matched_lines(reference: &str, candidate: &str) -> Option<(Range<usize>, Range<usize>)> {
+    let diff = TextDiff::from_lines(reference, candidate);
+    let mut equal_ops = diff.ops().iter().filter(|op| op.tag() == DiffTag::Equal);
+    let first = equal_ops.next()?;
+    let last = equal_ops.next_back().unwrap_or(first);
+    Some((
+        first.old_range().start..last.old_range().end,
+        first.new_range().start..last.new_range().end,
+    ))
+}
+
+/// A SARIF region for a 0-based, end-exclusive line span. SARIF lines are
+/// 1-based and `endLine` is inclusive.
+fn region(lines: &Range<usize>) -> Value {
+    json!({ "startLine": lines.start + 1, "endLine": lines.end })
+}
+
+/// A relative URI reference for `path`, with `/` separators and the characters
+/// that are not allowed in a URI percent-encoded. Absolute paths become
+/// `file://` URIs.
+pub fn path_to_uri(path: &Path) -> String {
+    let slashed = path.display().to_string().replace('\\', "/");
+    let mut uri = String::with_capacity(slashed.len());
+    if path.is_absolute() {
+        uri.push_str("file://");
+        if !slashed.starts_with('/') {
+            uri.push('/');
+        }
+    }
+    for byte in slashed.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod test_sarif {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn matched_lines_span_the_unchanged_lines() {
+        assert_eq!(
+            matched_lines("a\nb\nc\n", "x\na\nb\ny\nc\nz\n"),
+            Some((0..3, 1..5))
+        );
+        assert_eq!(matched_lines("a\n", "b\n"), None);
+    }
+
+    #[test]
+    fn uris_are_slashed_and_encoded() {
+        assert_eq!(path_to_uri(Path::new("src/my file.py")), "src/my%20file.py");
+        assert_eq!(path_to_uri(Path::new("a#b.py")), "a%23b.py");
+    }
+
+    #[test]
+    fn one_result_per_comparison() {
+        let comparisons = vec![FileComparison::new(
+            PathBuf::from("dup.py"),
+            0.75,
+            "a\nb\nx\n".to_owned(),
+        )];
+        let log: Value = serde_json::from_str(&render(
+            Some(Path::new("ref.py")),
+            "a\nb\nc\n",
+            &comparisons,
+        ))
+        .unwrap();
+        assert_eq!(log["version"], "2.1.0");
+        let results = log["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 1);
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "dup.py");
+        assert_eq!(location["region"], json!({ "startLine": 1, "endLine": 2 }));
+        assert_eq!(
+            results[0]["relatedLocations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "ref.py"
+        );
+        assert_eq!(results[0]["message"]["text"], "75.0% similar to 'ref.py'.");
+    }
+}
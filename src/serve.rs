@@ -0,0 +1,585 @@
+//! `busca serve`: answers searches, comparisons, and explanations over a local
+//! HTTP/JSON API, so that editors and scripts can query a tree repeatedly
+//! without paying for a cold walk and reread each time. Candidate contents and
+//! line multisets live in a [`ContentCache`] shared by every connection and
+//! revalidated by modification time (see ADR-0007). Each connection gets its
+//! own thread, up to [`MAX_CONNECTIONS`]; scoring runs on rayon's global pool,
+//! which concurrent requests share.
+//!
+//! The API has no authentication and reads whatever paths a request names, so
+//! it listens only on loopback addresses unless `--allow-remote` is given.
+
+use crate::diff::{self, DiffFormat, DiffOutputArgs, DEFAULT_CONTEXT_LINES};
+use crate::{
+    graceful_panic, json_comparisons, JsonComparison, DEFAULT_COUNT, DEFAULT_MAX_FILE_LINES,
+};
+use busca::{get_similarity_ratio, run_search_cached, Args, ContentCache};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Requests larger than this are rejected before their body is read.
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// The request line and each header must fit in this many bytes.
+const MAX_LINE_BYTES: usize = 8 * 1024;
+
+/// Requests with more headers than this are rejected.
+const MAX_HEADERS: usize = 100;
+
+/// How long a connection may stay idle while its request is read.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connections served at once. Further connections wait in the listen backlog
+/// until one finishes.
+const MAX_CONNECTIONS: usize = 64;
+
+#[derive(clap::Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on. Port 0 picks a free port; the address actually
+    /// bound is printed on stderr
+    #[arg(long, default_value = "127.0.0.1:7878")]
+    listen: String,
+
+    /// Allow --listen on an address other than loopback. The API has no
+    /// authentication and can read any file busca can, so anyone who can reach
+    /// the address can too
+    #[arg(long)]
+    allow_remote: bool,
+}
+
+/// Handles `busca serve`. Runs until interrupted; exits `2` if the address
+/// cannot be bound.
+pub fn run(serve_args: ServeArgs) {
+    // Diffs in responses are plain text, whatever the terminal supports.
+    console::set_colors_enabled(false);
+    let listener = TcpListener::bind(&serve_args.listen).unwrap_or_else(|e| {
+        graceful_panic(&format!("cannot listen on '{}': {e}", serve_args.listen))
+    });
+    let address = listener
+        .local_addr()
+        .unwrap_or_else(|e| graceful_panic(&e.to_string()));
+    if !address.ip().is_loopback() && !serve_args.allow_remote {
+        graceful_panic(&format!(
+            "refusing to listen on non-loopback address {address}: the API has no \
+             authentication and reads any file busca can. Pass --allow-remote to do it anyway."
+        ));
+    }
+    eprintln!("Listening on http://{address}");
+
+    let cache = Arc::new(ContentCache::new());
+    let slots = Arc::new(Slots::default());
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let cache = Arc::clone(&cache);
+        let slot = Slots::acquire(&slots);
+        thread::spawn(move || {
+            serve_connection(stream, &cache);
+            drop(slot);
+        });
+    }
+}
+
+/// Counts the connections being served, for [`MAX_CONNECTIONS`].
+#[derive(Default)]
+struct Slots {
+    active: Mutex<usize>,
+    freed: Condvar,
+}
+
+/// One served connection. Dropping it frees the slot.
+struct Slot(Arc<Slots>);
+
+impl Slots {
+    /// Waits until fewer than [`MAX_CONNECTIONS`] connections are served.
+    fn acquire(slots: &Arc<Slots>) -> Slot {
+        let active = slots.active.lock().unwrap_or_else(|e| e.into_inner());
+        let mut active = slots
+            .freed
+            .wait_while(active, |active| *active >= MAX_CONNECTIONS)
+            .unwrap_or_else(|e| e.into_inner());
+        *active += 1;
+        Slot(Arc::clone(slots))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        *self.0.active.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        self.0.freed.notify_one();
+    }
+}
+
+/// Reads one request from `stream`, answers it, and closes the connection.
+fn serve_connection(stream: TcpStream, cache: &ContentCache) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let response = match read_request(&mut BufReader::new(&stream)) {
+        Ok(request) => handle(&request, cache),
+        Err(message) => Response::error(400, message),
+    };
+    let _ = response.write_to(&stream);
+}
+
+/// The parts of an HTTP/1.1 request the API uses.
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+/// Parses a request line, headers, and a `Content-Length` body. Chunked
+/// bodies are not supported; every client this serves sends a length.
+fn read_request(reader: &mut impl BufRead) -> Result<Request, String> {
+    let request_line = read_line(reader)?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err("malformed request line".to_owned());
+    };
+    // The query string is ignored: every parameter is in the JSON body.
+    let path = target.split('?').next().unwrap_or(target);
+
+    let mut content_length = 0;
+    for header_count in 0.. {
+        if header_count > MAX_HEADERS {
+            return Err(format!("more than {MAX_HEADERS} headers"));
+        }
+        let header = read_line(reader)?;
+        if header.is_empty() {
+            return Err("connection closed before the end of the headers".to_owned());
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(format!("malformed header '{header}'"));
+        };
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid Content-Length '{}'", value.trim()))?;
+        }
+    }
+    if content_length > MAX_BODY_BYTES {
+        return Err(format!("request body exceeds {MAX_BODY_BYTES} bytes"));
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+    Ok(Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        body,
+    })
+}
+
+/// Reads one line of at most [`MAX_LINE_BYTES`], including its line ending.
+/// Empty at the end of the input.
+fn read_line(reader: &mut impl BufRead) -> Result<String, String> {
+    let mut line = String::new();
+    io::Read::take(reader, MAX_LINE_BYTES as u64 + 1)
+        .read_line(&mut line)
+        .map_err(|e| e.to_string())?;
+    if line.len() > MAX_LINE_BYTES {
+        return Err(format!(
+            "request line or header exceeds {MAX_LINE_BYTES} bytes"
+        ));
+    }
+    Ok(line)
+}
+
+/// A JSON response. Every response closes its connection.
+#[derive(Debug)]
+struct Response {
+    status: u16,
+    body: String,
+}
+
+#[derive(Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+impl Response {
+    fn json(body: &impl Serialize) -> Self {
+        Self {
+            status: 200,
+            body: serde_json::to_string(body).expect("JSON serialization of responses cannot fail"),
+        }
+    }
+
+    fn error(status: u16, error: String) -> Self {
+        Self {
+            status,
+            ..Self::json(&ErrorResponse { error })
+        }
+    }
+
+    fn write_to(&self, mut stream: impl Write) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Error",
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            self.status,
+            self.body.len(),
+            self.body
+        )?;
+        stream.flush()
+    }
+}
+
+fn handle(request: &Request, cache: &ContentCache) -> Response {
+    let result = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/health") => Ok(Response::json(&Health {
+            status: "ok",
+            cached_files: cache.len(),
+        })),
+        ("POST", "/search") => parse(&request.body).and_then(|query| search(query, cache)),
+        ("POST", "/compare") => parse(&request.body).and_then(compare),
+        ("POST", "/explain") => parse(&request.body).and_then(explain),
+        (_, "/health" | "/search" | "/compare" | "/explain") => {
+            return Response::error(
+                405,
+                format!("{} is not allowed on {}", request.method, request.path),
+            )
+        }
+        _ => return Response::error(404, format!("no endpoint at {}", request.path)),
+    };
+    result.unwrap_or_else(|message| Response::error(400, message))
+}
+
+fn parse<'a, T: Deserialize<'a>>(body: &'a [u8]) -> Result<T, String> {
+    serde_json::from_slice(body).map_err(|e| format!("invalid request body: {e}"))
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+    cached_files: usize,
+}
+
+/// The body of `POST /search`, also one line of `busca batch`. Config files
+/// are not read: each request states its options, and omitted ones take the
+/// built-in defaults. `globs` is accepted for `include_glob`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SearchRequest {
+    reference: Option<String>,
+    ref_file_path: Option<PathBuf>,
+    /// Defaults to the working directory.
+    search_path: Option<PathBuf>,
+    count: Option<usize>,
+    min_similarity_ratio: Option<f32>,
+    #[serde(default, alias = "globs")]
+    include_glob: Vec<String>,
+    #[serde(default)]
+    exclude_glob: Vec<String>,
+    max_file_lines: Option<usize>,
+    #[serde(default)]
+    with_content: bool,
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    results: Vec<JsonComparison>,
+}
+
+fn search(query: SearchRequest, cache: &ContentCache) -> Result<Response, String> {
+    Ok(Response::json(&SearchResponse {
+        results: query.run(cache)?,
+    }))
+}
+
+impl SearchRequest {
+    /// Runs the search against `cache` and returns the ranked results.
+    pub fn run(self, cache: &ContentCache) -> Result<Vec<JsonComparison>, String> {
+        let reference_string = reference(self.reference, self.ref_file_path.as_deref())?;
+        let search_path = match self.search_path {
+            Some(search_path) => search_path,
+            None => env::current_dir().map_err(|e| e.to_string())?,
+        };
+        let count = self.count.unwrap_or(DEFAULT_COUNT);
+        if count == 0 {
+            return Err("count must be at least 1".to_owned());
+        }
+        let args = Args::new(
+            reference_string,
+            search_path,
+            Some(self.max_file_lines.unwrap_or(DEFAULT_MAX_FILE_LINES)),
+            Some(count),
+            self.min_similarity_ratio,
+            self.include_glob,
+            self.exclude_glob,
+        )
+        .map_err(|e| e.to_string())?;
+        let file_comparisons = run_search_cached(&args, cache).map_err(|e| e.to_string())?;
+        Ok(json_comparisons(&file_comparisons, self.with_content))
+    }
+}
+
+/// The reference text: `reference` inline, or the content of `ref_file_path`.
+fn reference(reference: Option<String>, ref_file_path: Option<&Path>) -> Result<String, String> {
+    match (reference, ref_file_path) {
+        (Some(reference), None) => Ok(reference),
+        (None, Some(path)) => read(path),
+        _ => Err("give exactly one of reference and ref_file_path".to_owned()),
+    }
+}
+
+fn read(path: &Path) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("cannot read '{}': {e}", path.display()))
+}
+
+/// The body of `POST /compare`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CompareRequest {
+    a: PathBuf,
+    b: PathBuf,
+    /// Include a unified diff of `a` against `b`.
+    #[serde(default)]
+    diff: bool,
+    context_lines: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct CompareResponse {
+    similarity_ratio: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<String>,
+}
+
+fn compare(request: CompareRequest) -> Result<Response, String> {
+    let (a, b) = (read(&request.a)?, read(&request.b)?);
+    let diff = request.diff.then(|| {
+        unified_diff(
+            &request.a.display().to_string(),
+            &request.b.display().to_string(),
+            &a,
+            &b,
+            request.context_lines,
+        )
+    });
+    Ok(Response::json(&CompareResponse {
+        similarity_ratio: get_similarity_ratio(&a, &b),
+        diff,
+    }))
+}
+
+/// The body of `POST /explain`: why `path` scores as it does against the
+/// reference.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExplainRequest {
+    reference: Option<String>,
+    ref_file_path: Option<PathBuf>,
+    path: PathBuf,
+    context_lines: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct ExplainResponse {
+    path: String,
+    similarity_ratio: f32,
+    /// The runs of lines both files share, in order.
+    matches: Vec<LineMatch>,
+    diff: String,
+}
+
+/// A run of shared lines, as 1-based inclusive line numbers in each file.
+#[derive(Debug, PartialEq, Serialize)]
+struct LineMatch {
+    reference_start: usize,
+    reference_end: usize,
+    candidate_start: usize,
+    candidate_end: usize,
+}
+
+fn explain(request: ExplainRequest) -> Result<Response, String> {
+    let reference_string = reference(request.reference, request.ref_file_path.as_deref())?;
+    let candidate_content = read(&request.path)?;
+    let reference_label = match &request.ref_file_path {
+        Some(path) => path.display().to_string(),
+        None => "(reference)".to_owned(),
+    };
+    let path = request.path.display().to_string();
+    Ok(Response::json(&ExplainResponse {
+        similarity_ratio: get_similarity_ratio(&reference_string, &candidate_content),
+        matches: line_matches(&reference_string, &candidate_content),
+        diff: unified_diff(
+            &reference_label,
+            &path,
+            &reference_string,
+            &candidate_content,
+            request.context_lines,
+        ),
+        path,
+    }))
+}
+
+fn line_matches(reference_string: &str, candidate_content: &str) -> Vec<LineMatch> {
+    diff::shared_runs(reference_string, candidate_content)
+        .into_iter()
+        .map(|(reference, candidate)| LineMatch {
+            reference_start: reference.start + 1,
+            reference_end: reference.end,
+            candidate_start: candidate.start + 1,
+            candidate_end: candidate.end,
+        })
+        .collect()
+}
+
+fn unified_diff(
+    reference_label: &str,
+    candidate_label: &str,
+    reference_string: &str,
+    candidate_content: &str,
+    context_lines: Option<usize>,
+) -> String {
+    let options = DiffOutputArgs {
+        diff_format: DiffFormat::Unified,
+        context_lines: context_lines.unwrap_or(DEFAULT_CONTEXT_LINES),
+    };
+    diff::render(
+        options,
+        reference_label,
+        candidate_label,
+        reference_string,
+        candidate_content,
+    )
+}
+
+#[cfg(test)]
+mod test_serve {
+    use super::*;
+
+    fn request(method: &str, path: &str, body: &str) -> Request {
+        Request {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    fn json(response: &Response) -> serde_json::Value {
+        serde_json::from_str(&response.body).unwrap()
+    }
+
+    #[test]
+    fn requests_are_read_up_to_their_content_length() {
+        let raw =
+            "POST /search?x=1 HTTP/1.1\r\nHost: localhost\r\ncontent-length: 2\r\n\r\n{}trailing";
+        assert_eq!(
+            read_request(&mut raw.as_bytes()).unwrap(),
+            request("POST", "/search", "{}")
+        );
+        assert!(read_request(&mut "GET\r\n\r\n".as_bytes()).is_err());
+        let oversized = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_BYTES + 1
+        );
+        assert!(read_request(&mut oversized.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn long_lines_and_many_headers_are_rejected() {
+        let long_header = format!(
+            "GET /health HTTP/1.1\r\nX: {}\r\n\r\n",
+            "a".repeat(MAX_LINE_BYTES)
+        );
+        assert!(read_request(&mut long_header.as_bytes()).is_err());
+        let unterminated = "G".repeat(10 * MAX_LINE_BYTES);
+        assert!(read_request(&mut unterminated.as_bytes()).is_err());
+        let many_headers = format!(
+            "GET /health HTTP/1.1\r\n{}\r\n",
+            "X: 1\r\n".repeat(MAX_HEADERS + 1)
+        );
+        assert!(read_request(&mut many_headers.as_bytes()).is_err());
+        let enough_headers = format!(
+            "GET /health HTTP/1.1\r\n{}\r\n",
+            "X: 1\r\n".repeat(MAX_HEADERS)
+        );
+        assert!(read_request(&mut enough_headers.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn search_ranks_like_the_cli_and_rejects_bad_bodies() {
+        let cache = ContentCache::new();
+        let body = r#"{"ref_file_path": "sample_dir_hello_world/file_1.py",
+            "search_path": "sample_dir_hello_world", "count": 2}"#;
+        let response = handle(&request("POST", "/search", body), &cache);
+        assert_eq!(response.status, 200);
+        let results = &json(&response)["results"];
+        assert_eq!(results.as_array().unwrap().len(), 2);
+        assert_eq!(results[0]["path"], "sample_dir_hello_world/file_1.py");
+        assert_eq!(results[0]["similarity_ratio"], 1.0);
+        assert!(!cache.is_empty());
+
+        for body in [
+            r#"{"search_path": "sample_dir_hello_world"}"#,
+            r#"{"reference": "x", "count": 0}"#,
+            r#"{"reference": "x", "colour": true}"#,
+            "not json",
+        ] {
+            assert_eq!(
+                handle(&request("POST", "/search", body), &cache).status,
+                400
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_paths_and_methods_are_rejected() {
+        let cache = ContentCache::new();
+        assert_eq!(handle(&request("GET", "/health", ""), &cache).status, 200);
+        assert_eq!(handle(&request("GET", "/search", ""), &cache).status, 405);
+        assert_eq!(handle(&request("POST", "/nope", ""), &cache).status, 404);
+    }
+
+    #[test]
+    fn explain_reports_the_shared_line_runs() {
+        assert_eq!(
+            line_matches("a\nb\nc\n", "a\nx\nb\nc\n"),
+            [
+                LineMatch {
+                    reference_start: 1,
+                    reference_end: 1,
+                    candidate_start: 1,
+                    candidate_end: 1,
+                },
+                LineMatch {
+                    reference_start: 2,
+                    reference_end: 3,
+                    candidate_start: 3,
+                    candidate_end: 4,
+                },
+            ]
+        );
+        let body = r#"{"reference": "print(\"Hello World\")\n",
+            "path": "sample_dir_hello_world/file_1.py"}"#;
+        let response = handle(&request("POST", "/explain", body), &ContentCache::new());
+        assert_eq!(response.status, 200);
+        let explained = json(&response);
+        assert_eq!(explained["path"], "sample_dir_hello_world/file_1.py");
+        assert!(explained["diff"]
+            .as_str()
+            .unwrap()
+            .starts_with("--- (reference)"));
+    }
+}
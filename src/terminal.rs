@@ -0,0 +1,278 @@
+//! Terminal presentation for the CLI: whether to emit colors, how result paths
+//! are displayed, and the width-aware ranked grid.
+//! `busca::format_file_comparisons` keeps its untruncated layout for library
+//! callers.
+
+use busca::FileComparison;
+use console::{measure_text_width, Term};
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use term_grid::{Alignment, Cell, Direction, Filling, Grid, GridOptions};
+
+/// Columns the grid needs besides the path: two separators, the ten-wide
+/// similarity bar, and the right-aligned percentage.
+const GRID_FIXED_WIDTH: usize = 2 + 10 + 2 + "100.0%".len();
+
+/// Paths are never truncated below this many columns, however narrow the
+/// terminal.
+const MIN_PATH_WIDTH: usize = 16;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal, unless NO_COLOR is set or
+    /// CLICOLOR_FORCE forces it
+    #[default]
+    Auto,
+    /// Always emit color escape codes
+    Always,
+    /// Never emit color escape codes
+    Never,
+}
+
+impl ColorChoice {
+    /// Applies the choice to everything styled through `console` on stdout
+    /// and stderr, and to the interactive picker.
+    pub fn apply(self) {
+        let no_color = env::var_os("NO_COLOR");
+        let clicolor_force = env::var_os("CLICOLOR_FORCE");
+        let clicolor = env::var_os("CLICOLOR");
+        let enabled = |term: Term| {
+            let supported =
+                term.features().colors_supported() && clicolor.as_deref() != Some(OsStr::new("0"));
+            colors_enabled(
+                self,
+                supported,
+                no_color.as_deref(),
+                clicolor_force.as_deref(),
+            )
+        };
+
+        console::set_colors_enabled(enabled(Term::stdout()));
+        let stderr_colors = enabled(Term::stderr());
+        console::set_colors_enabled_stderr(stderr_colors);
+        if !stderr_colors {
+            inquire::set_global_render_config(inquire::ui::RenderConfig::empty());
+        }
+    }
+}
+
+/// Resolves a color choice. `--color always|never` wins; under `auto`, a
+/// non-empty `NO_COLOR` disables color, then a `CLICOLOR_FORCE` other than
+/// `0` enables it, and otherwise color follows whether the stream is a color
+/// terminal.
+fn colors_enabled(
+    choice: ColorChoice,
+    terminal_supports_color: bool,
+    no_color: Option<&OsStr>,
+    clicolor_force: Option<&OsStr>,
+) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            if no_color.is_some_and(|value| !value.is_empty()) {
+                false
+            } else if clicolor_force.is_some_and(|value| !value.is_empty() && value != "0") {
+                true
+            } else {
+                terminal_supports_color
+            }
+        }
+    }
+}
+
+/// How result paths are printed. Without `--path-display`, paths are shown as
+/// the walk produced them: relative when the search path is relative.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum PathDisplay {
+    /// Relative to the current directory where possible
+    Relative,
+    /// Absolute, without resolving symlinks
+    Absolute,
+}
+
+impl PathDisplay {
+    pub fn apply(self, path: &Path) -> PathBuf {
+        let Ok(current_dir) = env::current_dir() else {
+            return path.to_path_buf();
+        };
+        match self {
+            PathDisplay::Absolute => {
+                std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+            }
+            PathDisplay::Relative => {
+                let relative = match path.strip_prefix(&current_dir) {
+                    Ok(relative) if path.is_absolute() => relative,
+                    _ => path,
+                };
+                relative.strip_prefix(".").unwrap_or(relative).to_path_buf()
+            }
+        }
+    }
+}
+
+/// Rewrites every result's path for display under `--path-display`.
+pub fn display_paths(file_comparisons: &mut [FileComparison], path_display: Option<PathDisplay>) {
+    if let Some(path_display) = path_display {
+        for fc in file_comparisons {
+            fc.path = path_display.apply(&fc.path);
+        }
+    }
+}
+
+/// The width of `term`, or `None` when it is not a terminal and lines should
+/// not be truncated.
+pub fn width(term: &Term) -> Option<usize> {
+    if !term.is_term() {
+        return None;
+    }
+    term.size_checked().map(|(_, columns)| usize::from(columns))
+}
+
+/// The ranked grid of `busca::format_file_comparisons`, with paths shortened
+/// by a middle ellipsis so every line fits in `max_width` columns.
+pub fn format_grid(file_comparisons: &[FileComparison], max_width: Option<usize>) -> String {
+    let path_width =
+        max_width.map(|width| width.saturating_sub(GRID_FIXED_WIDTH).max(MIN_PATH_WIDTH));
+
+    let mut grid = Grid::new(GridOptions {
+        filling: Filling::Spaces(2),
+        direction: Direction::LeftToRight,
+    });
+    for file_comparison in file_comparisons {
+        let path = file_comparison.path.display().to_string();
+        let path = match path_width {
+            Some(path_width) => middle_ellipsis(&path, path_width),
+            None => path,
+        };
+        grid.add(Cell::from(path));
+        grid.add(Cell::from("+".repeat(
+            (file_comparison.similarity_ratio * 10.0).round() as usize,
+        )));
+        let mut perc_cell = Cell::from(format!("{:.1}%", file_comparison.similarity_ratio * 100.0));
+        perc_cell.alignment = Alignment::Right;
+        grid.add(perc_cell);
+    }
+
+    let mut display_string = grid.fit_into_columns(3).to_string();
+    if display_string.ends_with('\n') {
+        display_string.pop();
+    }
+    display_string
+}
+
+/// Shortens `text` to at most `width` columns by replacing its middle with
+/// `…`, keeping the start of the path and, with a little more room, the file
+/// name at its end.
+pub fn middle_ellipsis(text: &str, width: usize) -> String {
+    if measure_text_width(text) <= width {
+        return text.to_owned();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let budget = width - 1;
+    let head_budget = budget / 2;
+    let tail_budget = budget - head_budget;
+
+    let mut head = String::new();
+    let mut head_width = 0;
+    for c in text.chars() {
+        let char_width = measure_text_width(c.encode_utf8(&mut [0; 4]));
+        if head_width + char_width > head_budget {
+            break;
+        }
+        head.push(c);
+        head_width += char_width;
+    }
+
+    let mut tail: Vec<char> = Vec::new();
+    let mut tail_width = 0;
+    for c in text.chars().rev() {
+        let char_width = measure_text_width(c.encode_utf8(&mut [0; 4]));
+        if tail_width + char_width > tail_budget {
+            break;
+        }
+        tail.push(c);
+        tail_width += char_width;
+    }
+
+    let tail: String = tail.into_iter().rev().collect();
+    format!("{head}…{tail}")
+}
+
+#[cfg(test)]
+mod test_terminal {
+    use super::*;
+
+    #[test]
+    fn color_precedence() {
+        let set = Some(OsStr::new("1"));
+        let empty = Some(OsStr::new(""));
+        let zero = Some(OsStr::new("0"));
+
+        assert!(colors_enabled(ColorChoice::Always, false, set, None));
+        assert!(!colors_enabled(ColorChoice::Never, true, None, set));
+        assert!(colors_enabled(ColorChoice::Auto, true, None, None));
+        assert!(!colors_enabled(ColorChoice::Auto, false, None, None));
+        assert!(!colors_enabled(ColorChoice::Auto, true, set, None));
+        assert!(!colors_enabled(ColorChoice::Auto, true, set, set));
+        // An empty NO_COLOR is ignored, per no-color.org.
+        assert!(colors_enabled(ColorChoice::Auto, true, empty, None));
+        assert!(colors_enabled(ColorChoice::Auto, false, None, set));
+        assert!(!colors_enabled(ColorChoice::Auto, false, None, zero));
+    }
+
+    #[test]
+    fn middle_ellipsis_keeps_both_ends() {
+        assert_eq!(middle_ellipsis("short.py", 20), "short.py");
+        assert_eq!(
+            middle_ellipsis("src/deeply/nested/module.py", 15),
+            "src/dee…dule.py"
+        );
+        assert_eq!(
+            measure_text_width(&middle_ellipsis("src/deeply/nested/module.py", 15)),
+            15
+        );
+        assert_eq!(middle_ellipsis("ab", 1), "…");
+    }
+
+    #[test]
+    fn grid_fits_the_width() {
+        let file_comparisons = vec![
+            FileComparison::new(
+                PathBuf::from("a/very/long/directory/name/that/goes/on/file.py"),
+                0.5,
+                String::new(),
+            ),
+            FileComparison::new(PathBuf::from("b.py"), 0.25, String::new()),
+        ];
+        let grid = format_grid(&file_comparisons, Some(40));
+        for line in grid.lines() {
+            assert!(measure_text_width(line) <= 40, "{line:?}");
+        }
+        assert!(grid.contains('…'));
+        assert_eq!(
+            format_grid(&file_comparisons, None),
+            busca::format_file_comparisons(&file_comparisons)
+        );
+    }
+
+    #[test]
+    fn relative_display_strips_the_current_directory() {
+        let current_dir = env::current_dir().unwrap();
+        assert_eq!(
+            PathDisplay::Relative.apply(&current_dir.join("src/lib.rs")),
+            PathBuf::from("src/lib.rs")
+        );
+        assert_eq!(
+            PathDisplay::Relative.apply(Path::new("./src/lib.rs")),
+            PathBuf::from("src/lib.rs")
+        );
+        assert_eq!(
+            PathDisplay::Absolute.apply(Path::new("src/lib.rs")),
+            current_dir.join("src/lib.rs")
+        );
+    }
+}
@@ -0,0 +1,577 @@
+//! `busca tui`: a full-screen view of a search for large trees. Results appear
+//! as they are scored, and the path filter, `--count`, and
+//! `--min-similarity-ratio` can be changed without rescanning, because every
+//! comparison is kept in memory and the view is only a filter over them. The
+//! selected file's diff is shown in a split pane. The screen is drawn on stderr
+//! so that the path printed by Enter is the only thing written to stdout.
+
+use crate::diff::DEFAULT_CONTEXT_LINES;
+use crate::terminal::PathDisplay;
+use crate::{graceful_panic, parse_count, parse_similarity_ratio, QueryArgs, DEFAULT_COUNT};
+use busca::{run_search_streaming, Args, FileComparison};
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Gauge, List, ListItem, ListState, Paragraph};
+use ratatui::{Frame, Terminal};
+use similar::{ChangeTag, TextDiff};
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for a key before draining newly scored results.
+const TICK: Duration = Duration::from_millis(50);
+
+/// The step of `[` and `]` on the similarity floor.
+const RATIO_STEP: f32 = 0.05;
+
+#[derive(clap::Args, Debug)]
+pub struct TuiArgs {
+    #[command(flatten)]
+    query: QueryArgs,
+
+    /// Initial number of results shown; change it with + and -
+    #[arg(short, long, value_parser = parse_count)]
+    count: Option<usize>,
+
+    /// Initial similarity floor (in [0.0, 1.0]); change it with [ and ]
+    #[arg(long, value_parser = parse_similarity_ratio)]
+    min_similarity_ratio: Option<f32>,
+
+    /// Lines of unchanged context around each hunk of the diff pane
+    #[arg(long, default_value_t = DEFAULT_CONTEXT_LINES)]
+    context_lines: usize,
+}
+
+/// Messages from the scoring thread to the interface.
+enum Progress {
+    Walked { done: u64, total: u64 },
+    Scored(FileComparison),
+    Failed(String),
+}
+
+/// The state of the screen. `results` holds every comparison scored so far,
+/// ranked by descending ratio; `count`, `min_similarity_ratio`, and `filter`
+/// only decide which of them are shown. `diff` keeps the diff pane of the
+/// selected path, so it is not recomputed on every redraw.
+struct App {
+    results: Vec<FileComparison>,
+    walked: Option<(u64, u64)>,
+    error: Option<String>,
+    filter: String,
+    editing_filter: bool,
+    count: usize,
+    min_similarity_ratio: f32,
+    list_state: ListState,
+    diff_scroll: u16,
+    diff: Option<(PathBuf, Vec<Line<'static>>)>,
+}
+
+impl App {
+    fn new(count: usize, min_similarity_ratio: f32) -> Self {
+        let mut list_state = ListState::default();
+        list_state.select(Some(0));
+        Self {
+            results: Vec::new(),
+            walked: None,
+            error: None,
+            filter: String::new(),
+            editing_filter: false,
+            count,
+            min_similarity_ratio,
+            list_state,
+            diff_scroll: 0,
+            diff: None,
+        }
+    }
+
+    /// Inserts a comparison at its rank. Ties keep arrival order.
+    fn insert(&mut self, file_comparison: FileComparison) {
+        let index = self
+            .results
+            .partition_point(|fc| fc.similarity_ratio >= file_comparison.similarity_ratio);
+        self.results.insert(index, file_comparison);
+    }
+
+    /// The ranked comparisons that pass the floor and the path filter, at most
+    /// `count` of them.
+    fn visible(&self) -> Vec<&FileComparison> {
+        let filter = self.filter.to_lowercase();
+        self.results
+            .iter()
+            .filter(|fc| fc.similarity_ratio >= self.min_similarity_ratio)
+            .filter(|fc| {
+                filter.is_empty()
+                    || fc
+                        .path
+                        .display()
+                        .to_string()
+                        .to_lowercase()
+                        .contains(&filter)
+            })
+            .take(self.count)
+            .collect()
+    }
+
+    fn receive(&mut self, progress: Progress) {
+        match progress {
+            Progress::Walked { done, total } => self.walked = Some((done, total)),
+            Progress::Scored(file_comparison) => self.insert(file_comparison),
+            Progress::Failed(error) => self.error = Some(error),
+        }
+    }
+
+    /// The selected path and its diff against the reference, computed only
+    /// when the selection moves to another path. `None` when nothing is
+    /// visible.
+    fn selected_diff(
+        &mut self,
+        reference_string: &str,
+        context_lines: usize,
+        colors: bool,
+    ) -> Option<&(PathBuf, Vec<Line<'static>>)> {
+        let selected = self.list_state.selected().unwrap_or(0);
+        let fresh = match self.visible().get(selected) {
+            None => return None,
+            Some(fc) if self.diff.as_ref().is_some_and(|(path, _)| *path == fc.path) => None,
+            Some(fc) => Some((
+                fc.path.clone(),
+                diff_lines(reference_string, &fc.content, context_lines, colors),
+            )),
+        };
+        if fresh.is_some() {
+            self.diff = fresh;
+        }
+        self.diff.as_ref()
+    }
+
+    fn select(&mut self, index: usize) {
+        if self.list_state.selected() != Some(index) {
+            self.diff_scroll = 0;
+        }
+        self.list_state.select(Some(index));
+    }
+
+    /// Keeps the selection inside the visible list after it shrinks.
+    fn clamp_selection(&mut self) {
+        let visible = self.visible().len();
+        let selected = self.list_state.selected().unwrap_or(0);
+        self.select(selected.min(visible.saturating_sub(1)));
+    }
+
+    /// Applies a key. Returns `Some` to leave the interface, with the path to
+    /// print if a result was chosen.
+    fn handle_key(&mut self, key: KeyEvent) -> Option<Option<String>> {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Some(None);
+        }
+        if self.editing_filter {
+            match key.code {
+                KeyCode::Enter | KeyCode::Esc => self.editing_filter = false,
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                }
+                KeyCode::Char(c) => self.filter.push(c),
+                _ => {}
+            }
+            self.clamp_selection();
+            return None;
+        }
+
+        let selected = self.list_state.selected().unwrap_or(0);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Some(None),
+            KeyCode::Enter => {
+                let path = self
+                    .visible()
+                    .get(selected)
+                    .map(|fc| fc.path.display().to_string());
+                if path.is_some() {
+                    return Some(path);
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                let last = self.visible().len().saturating_sub(1);
+                self.select((selected + 1).min(last));
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.select(selected.saturating_sub(1)),
+            KeyCode::PageDown | KeyCode::Char(' ') => {
+                self.diff_scroll = self.diff_scroll.saturating_add(10)
+            }
+            KeyCode::PageUp => self.diff_scroll = self.diff_scroll.saturating_sub(10),
+            KeyCode::Char('/') => self.editing_filter = true,
+            KeyCode::Char('+') => self.count = self.count.saturating_add(1),
+            KeyCode::Char('-') => self.count = self.count.saturating_sub(1).max(1),
+            KeyCode::Char(']') => {
+                self.min_similarity_ratio = step_ratio(self.min_similarity_ratio, RATIO_STEP)
+            }
+            KeyCode::Char('[') => {
+                self.min_similarity_ratio = step_ratio(self.min_similarity_ratio, -RATIO_STEP)
+            }
+            _ => {}
+        }
+        self.clamp_selection();
+        None
+    }
+}
+
+/// Moves the floor by `step`, rounded to the step so repeated presses do not
+/// drift, and kept within `[0.0, 1.0]`.
+fn step_ratio(ratio: f32, step: f32) -> f32 {
+    (((ratio + step) / RATIO_STEP).round() * RATIO_STEP).clamp(0.0, 1.0)
+}
+
+/// Handles `busca tui`. Exits `0` when the user quits, printing the chosen
+/// path if Enter picked one, and `2` on error.
+pub fn run(tui_args: TuiArgs, path_display: Option<PathDisplay>) {
+    let TuiArgs {
+        query,
+        count,
+        min_similarity_ratio,
+        context_lines,
+    } = tui_args;
+
+    let reference_string = query
+        .read_reference()
+        .unwrap_or_else(|e| graceful_panic(&e));
+    let (search_path, config) = query.resolve().unwrap_or_else(|e| graceful_panic(&e));
+    // Every candidate is scored and kept, so the count and floor are applied
+    // only by the view.
+    let args = Args::new(
+        reference_string.clone(),
+        search_path,
+        Some(query.max_file_lines(&config)),
+        None,
+        None,
+        query
+            .include_glob
+            .clone()
+            .or(config.include_glob)
+            .unwrap_or_default(),
+        query
+            .exclude_glob
+            .clone()
+            .or(config.exclude_glob)
+            .unwrap_or_default(),
+    )
+    .map_err(|e| e.to_string())
+    .and_then(|args| query.read.apply(args, query.ref_file_path.as_deref()))
+    .and_then(|args| query.git.restrict(args))
+    .unwrap_or_else(|e| graceful_panic(&e));
+    let mut app = App::new(
+        count.or(config.count).unwrap_or(DEFAULT_COUNT),
+        min_similarity_ratio
+            .or(config.min_similarity_ratio)
+            .unwrap_or(0.0),
+    );
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || score(args, path_display, sender));
+
+    let chosen = match interact(&mut app, &receiver, &reference_string, context_lines) {
+        Ok(chosen) => chosen,
+        Err(e) => graceful_panic(&format!("terminal error: {e}")),
+    };
+    if let Some(path) = chosen {
+        println!("{path}");
+    }
+}
+
+/// Runs the search, sending the walk's progress and each comparison as soon
+/// as it is scored, since [`busca::run_search`] returns nothing until every
+/// file is.
+fn score(args: Args, path_display: Option<PathDisplay>, sender: Sender<Progress>) {
+    let result = run_search_streaming(
+        &args,
+        |done, total| {
+            let _ = sender.send(Progress::Walked { done, total });
+        },
+        |mut fc| {
+            if let Some(path_display) = path_display {
+                fc.path = path_display.apply(&fc.path);
+            }
+            let _ = sender.send(Progress::Scored(fc));
+        },
+    );
+    if let Err(e) = result {
+        let _ = sender.send(Progress::Failed(e.to_string()));
+    }
+}
+
+/// Restores the terminal when dropped, including on a panic unwinding through
+/// the event loop.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        enable_raw_mode()?;
+        execute!(io::stderr(), EnterAlternateScreen)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+fn interact(
+    app: &mut App,
+    receiver: &Receiver<Progress>,
+    reference_string: &str,
+    context_lines: usize,
+) -> io::Result<Option<String>> {
+    let _guard = TerminalGuard::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+    let colors = console::colors_enabled_stderr();
+
+    loop {
+        while let Ok(progress) = receiver.try_recv() {
+            app.receive(progress);
+        }
+        app.clamp_selection();
+        terminal.draw(|frame| draw(frame, app, reference_string, context_lines, colors))?;
+
+        if event::poll(TICK)? {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    if let Some(chosen) = app.handle_key(key) {
+                        return Ok(chosen);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn draw(
+    frame: &mut Frame,
+    app: &mut App,
+    reference_string: &str,
+    context_lines: usize,
+    colors: bool,
+) {
+    let [status_area, main_area, help_area] = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [list_area, diff_area] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+            .areas(main_area);
+
+    let label = match (&app.error, app.walked) {
+        (Some(error), _) => error.clone(),
+        (None, None) => "Walking…".to_owned(),
+        (None, Some((done, total))) => format!(
+            "{done}/{total} scanned · top {} ≥ {:.0}%{}",
+            app.count,
+            app.min_similarity_ratio * 100.0,
+            match (app.editing_filter, app.filter.is_empty()) {
+                (true, _) => format!(" · filter: {}▏", app.filter),
+                (false, false) => format!(" · filter: {}", app.filter),
+                (false, true) => String::new(),
+            }
+        ),
+    };
+    let ratio = match app.walked {
+        Some((_, 0)) => 1.0,
+        Some((done, total)) => done as f64 / total as f64,
+        None => 0.0,
+    };
+    frame.render_widget(
+        Gauge::default()
+            .ratio(ratio.min(1.0))
+            .label(label)
+            .gauge_style(styled(colors, Style::new().fg(Color::Cyan))),
+        status_area,
+    );
+
+    let visible = app.visible();
+    let items: Vec<ListItem> = visible
+        .iter()
+        .map(|fc| {
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("{:>5.1}% ", fc.similarity_ratio * 100.0)),
+                Span::raw(fc.path.display().to_string()),
+            ]))
+        })
+        .collect();
+    let (diff_title, diff_text) = match app.selected_diff(reference_string, context_lines, colors) {
+        Some((path, lines)) => (path.display().to_string(), lines.clone()),
+        None => (String::new(), Vec::new()),
+    };
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Results"))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, list_area, &mut app.list_state);
+    frame.render_widget(
+        Paragraph::new(diff_text)
+            .block(Block::default().borders(Borders::ALL).title(diff_title))
+            .scroll((app.diff_scroll, 0)),
+        diff_area,
+    );
+    frame.render_widget(
+        Paragraph::new(
+            "↑↓ select · / filter · +/- count · [/] min ratio · PgUp/PgDn scroll · Enter print path · q quit",
+        )
+        .style(styled(colors, Style::new().add_modifier(Modifier::DIM))),
+        help_area,
+    );
+}
+
+/// The diff pane: the same hunks, line numbers, and word-level emphasis as the
+/// inline terminal diff, as styled text lines.
+fn diff_lines(
+    reference_string: &str,
+    candidate_content: &str,
+    context_lines: usize,
+    colors: bool,
+) -> Vec<Line<'static>> {
+    let diff = TextDiff::from_lines(reference_string, candidate_content);
+    let groups = diff.grouped_ops(context_lines);
+    if groups.is_empty() {
+        return vec![Line::raw("The sequences are identical.")];
+    }
+
+    let mut lines = Vec::new();
+    for (index, group) in groups.iter().enumerate() {
+        if index > 0 {
+            lines.push(Line::styled(
+                "┄".repeat(40),
+                styled(colors, Style::new().add_modifier(Modifier::DIM)),
+            ));
+        }
+        for op in group {
+            for change in diff.iter_inline_changes(op) {
+                let (sign, style) = match change.tag() {
+                    ChangeTag::Delete => ("-", Style::new().fg(Color::Red)),
+                    ChangeTag::Insert => ("+", Style::new().fg(Color::Green)),
+                    ChangeTag::Equal => (" ", Style::new().add_modifier(Modifier::DIM)),
+                };
+                let style = styled(colors, style);
+                let number = |index: Option<usize>| match index {
+                    Some(index) => format!("{:<4}", index + 1),
+                    None => "    ".to_owned(),
+                };
+                let mut spans = vec![
+                    Span::styled(
+                        format!(
+                            "{} {} ",
+                            number(change.old_index()),
+                            number(change.new_index())
+                        ),
+                        styled(colors, Style::new().add_modifier(Modifier::DIM)),
+                    ),
+                    Span::styled(format!("{sign} |"), style.add_modifier(Modifier::BOLD)),
+                ];
+                for (emphasized, value) in change.iter_strings_lossy() {
+                    let text = value.trim_end_matches(['\n', '\r']).to_owned();
+                    if emphasized && change.tag() != ChangeTag::Equal {
+                        spans.push(Span::styled(text, style.add_modifier(Modifier::UNDERLINED)));
+                    } else {
+                        spans.push(Span::styled(text, style));
+                    }
+                }
+                lines.push(Line::from(spans));
+            }
+        }
+    }
+    lines
+}
+
+/// `style`, or no styling at all when colors are disabled.
+fn styled(colors: bool, style: Style) -> Style {
+    if colors {
+        style
+    } else {
+        Style::new()
+    }
+}
+
+#[cfg(test)]
+mod test_tui {
+    use super::*;
+
+    fn comparison(path: &str, similarity_ratio: f32) -> FileComparison {
+        FileComparison::new(PathBuf::from(path), similarity_ratio, String::new())
+    }
+
+    fn visible_paths(app: &App) -> Vec<String> {
+        app.visible()
+            .iter()
+            .map(|fc| fc.path.display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn results_are_ranked_as_they_arrive_and_filtered_without_rescanning() {
+        let mut app = App::new(2, 0.0);
+        app.receive(Progress::Scored(comparison("src/b.py", 0.5)));
+        app.receive(Progress::Scored(comparison("src/a.py", 0.9)));
+        app.receive(Progress::Walked { done: 3, total: 4 });
+        app.receive(Progress::Scored(comparison("tests/c.py", 0.7)));
+        assert_eq!(app.walked, Some((3, 4)));
+        assert_eq!(visible_paths(&app), ["src/a.py", "tests/c.py"]);
+
+        app.count = 10;
+        app.filter = "SRC/".to_owned();
+        assert_eq!(visible_paths(&app), ["src/a.py", "src/b.py"]);
+
+        app.min_similarity_ratio = 0.6;
+        assert_eq!(visible_paths(&app), ["src/a.py"]);
+    }
+
+    #[test]
+    fn ratio_steps_stay_in_range_without_drifting() {
+        assert_eq!(step_ratio(0.0, -RATIO_STEP), 0.0);
+        assert_eq!(step_ratio(0.98, RATIO_STEP), 1.0);
+        let mut ratio = 0.0;
+        for _ in 0..6 {
+            ratio = step_ratio(ratio, RATIO_STEP);
+        }
+        assert!((ratio - 0.3).abs() < 1e-6, "{ratio}");
+    }
+
+    #[test]
+    fn diff_pane_follows_the_selection() {
+        let mut app = App::new(2, 0.0);
+        let mut a = comparison("a.py", 0.9);
+        a.content = "x\n".to_owned();
+        app.receive(Progress::Scored(a));
+        app.receive(Progress::Scored(comparison("b.py", 0.5)));
+        let path = |app: &mut App| {
+            app.selected_diff("x\n", 3, false)
+                .map(|(path, _)| path.clone())
+        };
+        assert_eq!(path(&mut app), Some(PathBuf::from("a.py")));
+        app.select(1);
+        assert_eq!(path(&mut app), Some(PathBuf::from("b.py")));
+        app.filter = "none".to_owned();
+        app.clamp_selection();
+        assert_eq!(path(&mut app), None);
+    }
+
+    #[test]
+    fn diff_pane_shows_changed_lines() {
+        let lines = diff_lines("a\nb\n", "a\nc\n", 3, false);
+        let text: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+        assert_eq!(text, ["1    1      |a", "2         - |b", "     2    + |c"]);
+        assert_eq!(
+            diff_lines("a\n", "a\n", 3, false)[0].to_string(),
+            "The sequences are identical."
+        );
+    }
+}
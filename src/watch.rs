@@ -0,0 +1,360 @@
+//! `--watch`: keeps a ranking open and updates it as files change. Every
+//! candidate is scored once up front and kept in memory, so a change rescores
+//! only the changed paths, unless the reference itself changed. Changes are
+//! debounced, then the human grid is redrawn or an NDJSON `ranking` event is
+//! written.
+
+use crate::terminal::{self, PathDisplay};
+use crate::{cli_run_search, graceful_panic, json_comparisons, JsonComparison, OutputFormat};
+use busca::{run_search, Args, FileComparison};
+use console::Term;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+/// Quiet period after the last change before rescoring, so that an editor's
+/// save or a `git checkout` is handled as one update.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// One `--format ndjson` line of `--watch`. The first event has no changed
+/// paths; each later one lists the candidate paths that changed since the
+/// previous event, and whether the reference changed, which rescores every
+/// candidate. `results` is always the complete ranking.
+#[derive(Serialize)]
+struct RankingEvent {
+    event: &'static str,
+    changed: Vec<String>,
+    reference_changed: bool,
+    results: Vec<JsonComparison>,
+}
+
+/// Every scored candidate, keyed by its path as the walk produced it, and the
+/// `--count` and `--min-similarity-ratio` that decide which are shown.
+struct Ranking {
+    comparisons: HashMap<PathBuf, FileComparison>,
+    count: Option<usize>,
+    min_similarity_ratio: Option<f32>,
+}
+
+impl Ranking {
+    /// The shown comparisons, ranked by descending ratio. Ties are ordered by
+    /// path so the output does not flicker between redraws.
+    fn ranked(&self) -> Vec<FileComparison> {
+        let mut ranked: Vec<FileComparison> = self
+            .comparisons
+            .values()
+            .filter(|fc| {
+                self.min_similarity_ratio
+                    .is_none_or(|min| fc.similarity_ratio >= min)
+            })
+            .cloned()
+            .collect();
+        ranked.sort_by(|a, b| {
+            b.similarity_ratio
+                .partial_cmp(&a.similarity_ratio)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        if let Some(count) = self.count {
+            ranked.truncate(count);
+        }
+        ranked
+    }
+
+    /// Replaces everything at or under `path` with `comparisons`. A deleted
+    /// directory takes its files with it, and an archive its members.
+    fn replace(&mut self, path: &Path, comparisons: Vec<FileComparison>) {
+        let mut members = path.as_os_str().to_owned();
+        members.push("!/");
+        let members = members.to_string_lossy().into_owned();
+        self.comparisons.retain(|candidate, _| {
+            !candidate.starts_with(path) && !candidate.to_string_lossy().starts_with(&members)
+        });
+        for fc in comparisons {
+            self.comparisons.insert(fc.path.clone(), fc);
+        }
+    }
+}
+
+/// Handles `--watch`. Runs until interrupted; exits `2` if the search root
+/// cannot be watched.
+pub fn run(
+    args: Args,
+    reference_path: Option<PathBuf>,
+    format: OutputFormat,
+    with_content: bool,
+    path_display: Option<PathDisplay>,
+) {
+    if !matches!(format, OutputFormat::Human | OutputFormat::Ndjson) {
+        graceful_panic("--watch supports --format human and ndjson.");
+    }
+
+    // Score every candidate: the count and floor apply only when rendering,
+    // so a file can climb into the shown ranking when it changes.
+    let mut args = args;
+    let mut ranking = Ranking {
+        comparisons: HashMap::new(),
+        count: args.count.take(),
+        min_similarity_ratio: args.min_similarity_ratio.take(),
+    };
+    rescore_all(&args, &mut ranking);
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(sender).unwrap_or_else(|e| graceful_panic(&e.to_string()));
+    let root = args
+        .search_path
+        .canonicalize()
+        .unwrap_or_else(|e| graceful_panic(&e.to_string()));
+    let watch_error = |path: &Path, e: &dyn std::fmt::Display| {
+        graceful_panic(&format!("cannot watch '{}': {e}", path.display()))
+    };
+    if let Err(e) = watcher.watch(&root, RecursiveMode::Recursive) {
+        watch_error(&root, &e);
+    }
+    // Editors often save by renaming a new file over the old one, which a
+    // watch on the file itself would miss, so watch its directory instead.
+    let reference = reference_path.and_then(|path| path.canonicalize().ok());
+    if let Some(parent) = reference.as_deref().and_then(Path::parent) {
+        if !parent.starts_with(&root) {
+            if let Err(e) = watcher.watch(parent, RecursiveMode::NonRecursive) {
+                watch_error(parent, &e);
+            }
+        }
+    }
+
+    render(
+        &ranking,
+        format,
+        with_content,
+        path_display,
+        &args,
+        &[],
+        false,
+    );
+    // The reference's directory may hold other files, such as redirected
+    // output, whose changes must not count.
+    let relevant = |path: &Path| path.starts_with(&root) || reference.as_deref() == Some(path);
+    while let Some(changed) = next_changes(&receiver, relevant) {
+        let reference_changed = reference.as_ref().is_some_and(|r| changed.contains(r));
+        if let (true, Some(reference)) = (reference_changed, &reference) {
+            match args.clone().with_reference_file(reference) {
+                Ok(reread) => {
+                    args = reread;
+                    rescore_all(&args, &mut ranking);
+                }
+                Err(e) => eprintln!("busca: {e}"),
+            }
+        }
+        let mut changed_paths = Vec::new();
+        for path in &changed {
+            let Ok(relative) = path.strip_prefix(&root) else {
+                continue;
+            };
+            // The walk's spelling of the path, so globs match as in a search.
+            let path = args.search_path.join(relative);
+            ranking.replace(&path, rescore(&args, &path));
+            changed_paths.push(path);
+        }
+        if reference_changed || !changed_paths.is_empty() {
+            render(
+                &ranking,
+                format,
+                with_content,
+                path_display,
+                &args,
+                &changed_paths,
+                reference_changed,
+            );
+        }
+    }
+}
+
+/// Waits for a change, then collects changes until none has arrived for
+/// [`DEBOUNCE`]. Only paths for which `relevant` holds are collected. Returns
+/// `None` when the watcher has stopped.
+fn next_changes(
+    receiver: &Receiver<notify::Result<Event>>,
+    relevant: impl Fn(&Path) -> bool,
+) -> Option<BTreeSet<PathBuf>> {
+    let mut changed = BTreeSet::new();
+    while changed.is_empty() {
+        collect(receiver.recv().ok()?, &relevant, &mut changed);
+    }
+    let mut deadline = Instant::now() + DEBOUNCE;
+    while let Ok(result) = receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+    {
+        if collect(result, &relevant, &mut changed) {
+            deadline = Instant::now() + DEBOUNCE;
+        }
+    }
+    Some(changed)
+}
+
+/// Adds the relevant paths of an event that changes content to `changed`,
+/// returning whether there were any. Reads are ignored: scoring reads every
+/// changed file, and counting those reads as changes would rescore forever.
+fn collect(
+    result: notify::Result<Event>,
+    relevant: impl Fn(&Path) -> bool,
+    changed: &mut BTreeSet<PathBuf>,
+) -> bool {
+    match result {
+        Ok(event) if is_change(&event.kind) => {
+            let before = changed.len();
+            changed.extend(event.paths.into_iter().filter(|path| relevant(path)));
+            changed.len() > before
+        }
+        Ok(_) => false,
+        Err(e) => {
+            eprintln!("busca: watch error: {e}");
+            false
+        }
+    }
+}
+
+fn is_change(kind: &EventKind) -> bool {
+    !(kind.is_access() || kind.is_other())
+}
+
+fn rescore_all(args: &Args, ranking: &mut Ranking) {
+    let (file_comparisons, _) = cli_run_search(args).unwrap_or_else(|e| graceful_panic(&e));
+    ranking.comparisons = file_comparisons
+        .into_iter()
+        .map(|fc| (fc.path.clone(), fc))
+        .collect();
+}
+
+/// Scores the candidates at or under `path` with the search's filters. A path
+/// that no longer exists has none.
+fn rescore(args: &Args, path: &Path) -> Vec<FileComparison> {
+    match args.clone().with_search_path(path.to_path_buf()) {
+        Ok(scoped) => run_search(&scoped).unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn render(
+    ranking: &Ranking,
+    format: OutputFormat,
+    with_content: bool,
+    path_display: Option<PathDisplay>,
+    args: &Args,
+    changed: &[PathBuf],
+    reference_changed: bool,
+) {
+    let mut ranked = ranking.ranked();
+    terminal::display_paths(&mut ranked, path_display);
+
+    if format == OutputFormat::Ndjson {
+        let event = RankingEvent {
+            event: "ranking",
+            changed: changed
+                .iter()
+                .map(|path| match path_display {
+                    Some(path_display) => path_display.apply(path).display().to_string(),
+                    None => path.display().to_string(),
+                })
+                .collect(),
+            reference_changed,
+            results: json_comparisons(&ranked, with_content),
+        };
+        println!(
+            "{}",
+            serde_json::to_string(&event).expect("JSON serialization of events cannot fail")
+        );
+        return;
+    }
+
+    let term = Term::stdout();
+    if term.is_term() {
+        let _ = term.clear_screen();
+    }
+    if ranked.is_empty() {
+        eprintln!("No files found that match the criteria.");
+    } else {
+        println!("{}", terminal::format_grid(&ranked, terminal::width(&term)));
+    }
+    eprintln!(
+        "Watching '{}' for changes. Press Ctrl-C to stop.",
+        args.search_path.display()
+    );
+}
+
+#[cfg(test)]
+mod test_watch {
+    use super::*;
+
+    fn comparison(path: &str, similarity_ratio: f32) -> FileComparison {
+        FileComparison::new(PathBuf::from(path), similarity_ratio, String::new())
+    }
+
+    fn ranked_paths(ranking: &Ranking) -> Vec<String> {
+        ranking
+            .ranked()
+            .iter()
+            .map(|fc| fc.path.display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn replacing_a_directory_drops_its_old_files() {
+        let mut ranking = Ranking {
+            comparisons: HashMap::new(),
+            count: Some(2),
+            min_similarity_ratio: Some(0.2),
+        };
+        ranking.replace(
+            Path::new("root"),
+            vec![
+                comparison("root/a.py", 0.5),
+                comparison("root/dir/b.py", 0.9),
+                comparison("root/dir/c.py", 0.5),
+                comparison("root/d.py", 0.1),
+            ],
+        );
+        assert_eq!(ranked_paths(&ranking), ["root/dir/b.py", "root/a.py"]);
+
+        ranking.replace(
+            Path::new("root/dir"),
+            vec![comparison("root/dir/c.py", 0.7)],
+        );
+        assert_eq!(ranked_paths(&ranking), ["root/dir/c.py", "root/a.py"]);
+
+        ranking.replace(Path::new("root/a.py"), Vec::new());
+        assert_eq!(ranked_paths(&ranking), ["root/dir/c.py"]);
+
+        ranking.replace(
+            Path::new("root/r.zip"),
+            vec![comparison("root/r.zip!/e.py", 0.8)],
+        );
+        assert_eq!(
+            ranked_paths(&ranking),
+            ["root/r.zip!/e.py", "root/dir/c.py"]
+        );
+        ranking.replace(Path::new("root/r.zip"), Vec::new());
+        assert_eq!(ranked_paths(&ranking), ["root/dir/c.py"]);
+    }
+
+    #[test]
+    fn rescoring_a_file_applies_the_search_filters() {
+        let args = Args::new(
+            "print(\"Hello World 1\")\n".to_owned(),
+            PathBuf::from("sample_dir_hello_world"),
+            None,
+            None,
+            None,
+            vec!["*.py".to_owned()],
+            vec![],
+        )
+        .unwrap();
+        let scored = rescore(&args, Path::new("sample_dir_hello_world/file_1.py"));
+        assert_eq!(scored.len(), 1);
+        assert!(scored[0].similarity_ratio > 0.0);
+        assert!(rescore(&args, Path::new("sample_dir_hello_world/missing.py")).is_empty());
+        assert!(rescore(&args, Path::new("README.md")).is_empty());
+    }
+}
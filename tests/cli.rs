@@ -2,9 +2,15 @@ use std::process::{Command, Stdio};
 
 /// Build a `busca` command with stdin detached, so the binary deterministically
 /// sees a non-TTY stdin (no interactive picker) regardless of the test runner.
+/// `XDG_CONFIG_HOME` points at a directory that does not exist, so a user-level
+/// config on the machine running the tests cannot change the results.
 fn busca() -> Command {
     let mut command = Command::new(env!("CARGO_BIN_EXE_busca"));
     command.stdin(Stdio::null());
+    command.env(
+        "XDG_CONFIG_HOME",
+        std::env::temp_dir().join("busca_cli_tests_no_user_config"),
+    );
     command
 }
 
@@ -222,3 +228,1415 @@ fn exit_two_on_error() {
         .expect("run busca");
     assert_eq!(status.code(), Some(2));
 }
+
+#[test]
+fn list_candidates_needs_no_reference() {
+    let output = busca()
+        .args([
+            "-s",
+            "sample_dir_hello_world",
+            "--include-glob",
+            "*.py",
+            "--list-candidates",
+        ])
+        .output()
+        .expect("run busca");
+    assert!(output.status.success(), "status: {:?}", output.status);
+
+    // Normalize separators so the expectation also holds on Windows.
+    let stdout = String::from_utf8(output.stdout)
+        .expect("utf-8 stdout")
+        .replace('\\', "/");
+    let mut paths: Vec<&str> = stdout.lines().collect();
+    paths.sort();
+    assert_eq!(
+        paths,
+        vec![
+            "sample_dir_hello_world/file_1.py",
+            "sample_dir_hello_world/file_2.py",
+            "sample_dir_hello_world/file_3.py",
+            "sample_dir_hello_world/nested_dir/ref_B.py",
+            "sample_dir_hello_world/nested_dir/sample_python_file_3.py",
+        ]
+    );
+}
+
+#[test]
+fn list_candidates_json_with_stats() {
+    let output = busca()
+        .args([
+            "-s",
+            "sample_dir_hello_world/file_1.py",
+            "--list-candidates",
+            "--with-stats",
+            "--format",
+            "json",
+        ])
+        .output()
+        .expect("run busca");
+    assert!(output.status.success(), "status: {:?}", output.status);
+
+    let stdout = String::from_utf8(output.stdout).expect("utf-8 stdout");
+    let value: serde_json::Value = serde_json::from_str(&stdout).expect("valid json array");
+    assert_eq!(
+        value,
+        serde_json::json!([{
+            "path": "sample_dir_hello_world/file_1.py",
+            "line_count": 9,
+            "size_bytes": 140,
+        }])
+    );
+}
+
+#[test]
+fn list_candidates_print0_separates_with_nul() {
+    let output = busca()
+        .args([
+            "-s",
+            "sample_dir_hello_world",
+            "--include-glob",
+            "*.json",
+            "--list-candidates",
+            "--print0",
+        ])
+        .output()
+        .expect("run busca");
+    assert!(output.status.success(), "status: {:?}", output.status);
+    let stdout = String::from_utf8(output.stdout)
+        .expect("utf-8 stdout")
+        .replace('\\', "/");
+    assert_eq!(
+        stdout,
+        "sample_dir_hello_world/nested_dir/sample_json.json\0"
+    );
+}
+
+#[test]
+fn list_candidates_exit_one_when_nothing_qualifies() {
+    let output = busca()
+        .args([
+            "-s",
+            "sample_dir_hello_world",
+            "--include-glob",
+            "*.no_such_ext",
+            "--list-candidates",
+        ])
+        .output()
+        .expect("run busca");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn project_config_is_discovered_above_the_search_root() {
+    let root = std::env::temp_dir().join(format!("busca_cli_config_{}", std::process::id()));
+    let nested = root.join("nested");
+    std::fs::create_dir_all(&nested).unwrap();
+    std::fs::write(
+        root.join(".busca.toml"),
+        "include_glob = \"*.py\"\n\n[profile.text]\ninclude_glob = \"*.txt\"\n",
+    )
+    .unwrap();
+    std::fs::write(nested.join("a.py"), "print(1)\n").unwrap();
+    std::fs::write(nested.join("b.txt"), "one\n").unwrap();
+
+    let list = |extra: &[&str]| {
+        let output = busca()
+            .arg("-s")
+            .arg(&nested)
+            .arg("--list-candidates")
+            .args(extra)
+            .output()
+            .expect("run busca");
+        assert!(output.status.success(), "status: {:?}", output.status);
+        String::from_utf8(output.stdout).expect("utf-8 stdout")
+    };
+    let default_listing = list(&[]);
+    let profile_listing = list(&["--profile", "text"]);
+    let flag_listing = list(&["--profile", "text", "-i", "*.py"]);
+    let no_config_listing = list(&["--no-config"]);
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(default_listing.contains("a.py") && !default_listing.contains("b.txt"));
+    assert!(profile_listing.contains("b.txt") && !profile_listing.contains("a.py"));
+    assert!(flag_listing.contains("a.py") && !flag_listing.contains("b.txt"));
+    assert!(no_config_listing.contains("a.py") && no_config_listing.contains("b.txt"));
+}
+
+/// Runs a search over the hello-world sample with `*.py` candidates and the
+/// extra flags, returning stdout after asserting success.
+fn search_stdout(extra: &[&str]) -> String {
+    let output = busca()
+        .args([
+            "-r",
+            "sample_dir_hello_world/file_1.py",
+            "-s",
+            "sample_dir_hello_world",
+            "--include-glob",
+            "*.py",
+        ])
+        .args(extra)
+        .output()
+        .expect("run busca");
+    assert!(output.status.success(), "status: {:?}", output.status);
+    String::from_utf8(output.stdout).expect("utf-8 stdout")
+}
+
+#[test]
+fn ndjson_format_emits_one_object_per_line() {
+    let stdout = search_stdout(&["--format", "ndjson", "--count", "3"]);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 3);
+    for line in lines {
+        let value: serde_json::Value = serde_json::from_str(line).expect("valid json line");
+        assert!(value.get("path").is_some(), "path field present");
+        assert!(value.get("similarity_ratio").is_some(), "ratio present");
+    }
+}
+
+#[test]
+fn csv_and_tsv_formats_have_header_rows() {
+    let csv = search_stdout(&["--format", "csv", "--count", "2"]);
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("path,similarity_ratio,encoding,cell"));
+    assert_eq!(lines.count(), 2);
+
+    let tsv = search_stdout(&["--format", "tsv", "--count", "2", "--with-content"]);
+    let mut lines = tsv.lines();
+    assert_eq!(
+        lines.next(),
+        Some("path\tsimilarity_ratio\tencoding\tcell\tcontent")
+    );
+    // Content newlines are escaped, so each result stays on one line.
+    assert_eq!(lines.count(), 2);
+}
+
+#[test]
+fn paths_format_supports_print0() {
+    let stdout = search_stdout(&["--format", "paths", "--count", "2", "--print0"]);
+    let records: Vec<&str> = stdout.split_terminator('\0').collect();
+    assert_eq!(records.len(), 2);
+    assert!(records[0].ends_with("file_1.py"), "records: {records:?}");
+}
+
+#[test]
+fn template_renders_each_result() {
+    let stdout = search_stdout(&["--count", "1", "--template", r"{rank}\t{ratio:.3}"]);
+    assert_eq!(stdout, "1\t1.000\n");
+}
+
+#[test]
+fn unknown_template_placeholder_is_rejected() {
+    let output = busca()
+        .args([
+            "-r",
+            "sample_dir_hello_world/file_1.py",
+            "--template",
+            "{nope}",
+        ])
+        .output()
+        .expect("run busca");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).expect("utf-8 stderr");
+    assert!(stderr.contains("unknown placeholder"), "stderr: {stderr}");
+}
+
+/// Checks `value` against the subset of JSON Schema that the files under
+/// `docs/schema/` use, returning the first violation. Panics on any keyword
+/// outside that subset, so a schema change cannot silently go unchecked.
+fn validate_schema(
+    schema: &serde_json::Value,
+    value: &serde_json::Value,
+    at: &str,
+) -> Result<(), String> {
+    use serde_json::Value;
+
+    let schema = schema.as_object().expect("schema node is an object");
+    for (keyword, rule) in schema {
+        match keyword.as_str() {
+            "$schema" | "$id" | "title" | "description" => {}
+            "type" => {
+                let allowed: Vec<&str> = match rule {
+                    Value::String(t) => vec![t.as_str()],
+                    Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+                    _ => panic!("unsupported type rule at {at}"),
+                };
+                let matches = allowed.iter().any(|t| match *t {
+                    "object" => value.is_object(),
+                    "array" => value.is_array(),
+                    "string" => value.is_string(),
+                    "integer" => value.is_u64() || value.is_i64(),
+                    "number" => value.is_number(),
+                    "null" => value.is_null(),
+                    other => panic!("unsupported type {other} at {at}"),
+                });
+                if !matches {
+                    return Err(format!("{at}: expected {allowed:?}, got {value}"));
+                }
+            }
+            "const" if value != rule => return Err(format!("{at}: expected {rule}, got {value}")),
+            "const" => {}
+            "enum" => {
+                if !rule.as_array().expect("enum array").contains(value) {
+                    return Err(format!("{at}: {value} not in {rule}"));
+                }
+            }
+            "minimum" | "maximum" => {
+                if let (Some(bound), Some(n)) = (rule.as_f64(), value.as_f64()) {
+                    let ok = if keyword == "minimum" {
+                        n >= bound
+                    } else {
+                        n <= bound
+                    };
+                    if !ok {
+                        return Err(format!("{at}: {n} violates {keyword} {bound}"));
+                    }
+                }
+            }
+            "required" => {
+                if let Some(object) = value.as_object() {
+                    for key in rule.as_array().expect("required array") {
+                        let key = key.as_str().expect("required key");
+                        if !object.contains_key(key) {
+                            return Err(format!("{at}: missing required '{key}'"));
+                        }
+                    }
+                }
+            }
+            "properties" | "additionalProperties" => {
+                let Some(object) = value.as_object() else {
+                    continue;
+                };
+                let properties = schema
+                    .get("properties")
+                    .and_then(Value::as_object)
+                    .expect("properties object");
+                for (key, child) in object {
+                    match properties.get(key) {
+                        Some(child_schema) if keyword == "properties" => {
+                            validate_schema(child_schema, child, &format!("{at}.{key}"))?
+                        }
+                        None if keyword == "additionalProperties" && rule == false => {
+                            return Err(format!("{at}: unexpected property '{key}'"))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            "items" => {
+                if let Some(items) = value.as_array() {
+                    for (index, item) in items.iter().enumerate() {
+                        validate_schema(rule, item, &format!("{at}[{index}]"))?;
+                    }
+                }
+            }
+            other => {
+                panic!("schema keyword '{other}' at {at} is not supported by the test validator")
+            }
+        }
+    }
+    Ok(())
+}
+
+fn results_v2_schema() -> serde_json::Value {
+    let path = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/docs/schema/busca-results-v2.schema.json"
+    );
+    let text = std::fs::read_to_string(path).expect("read schema");
+    serde_json::from_str(&text).expect("schema is valid json")
+}
+
+#[test]
+fn json_envelope_matches_schema() {
+    let schema = results_v2_schema();
+    for extra in [
+        &["--count", "2"][..],
+        &["--with-content", "--min-similarity-ratio", "0.2"],
+    ] {
+        let stdout = search_stdout(&[&["--format", "json-envelope"], extra].concat());
+        let value: serde_json::Value = serde_json::from_str(&stdout).expect("valid json");
+        validate_schema(&schema, &value, "$").unwrap();
+    }
+}
+
+#[test]
+fn json_envelope_records_the_query() {
+    let stdout = search_stdout(&[
+        "--format",
+        "json-envelope",
+        "--count",
+        "2",
+        "--max-file-lines",
+        "500",
+    ]);
+    let value: serde_json::Value = serde_json::from_str(&stdout).expect("valid json");
+    assert_eq!(value["schema_version"], 2);
+    assert_eq!(
+        value["query"]["reference"]["path"],
+        "sample_dir_hello_world/file_1.py"
+    );
+    assert_eq!(value["query"]["reference"]["source"], "file");
+    assert_eq!(value["query"]["search_path"], "sample_dir_hello_world");
+    assert_eq!(value["query"]["include_glob"], serde_json::json!(["*.py"]));
+    assert_eq!(value["query"]["max_file_lines"], 500);
+    assert_eq!(value["query"]["count"], 2);
+    assert!(value["query"]["min_similarity_ratio"].is_null());
+    assert_eq!(value["stats"]["result_count"], 2);
+    assert_eq!(value["results"].as_array().expect("results array").len(), 2);
+}
+
+#[test]
+fn schema_validator_rejects_a_bare_array() {
+    let bare = search_stdout(&["--format", "json"]);
+    let value: serde_json::Value = serde_json::from_str(&bare).expect("valid json");
+    assert!(validate_schema(&results_v2_schema(), &value, "$").is_err());
+}
+
+#[test]
+fn html_format_is_a_self_contained_report() {
+    let stdout = search_stdout(&["--format", "html", "--count", "3"]);
+    assert!(stdout.starts_with("<!DOCTYPE html>"), "html document");
+    assert_eq!(
+        stdout.matches("<details>").count(),
+        3,
+        "one entry per result"
+    );
+    assert!(stdout.contains("file_1.py"));
+    // Nothing is fetched when the report is opened.
+    for external in ["src=", "href=", "http://", "https://", "<script"] {
+        assert!(!stdout.contains(external), "report references {external}");
+    }
+}
+
+#[test]
+fn markdown_format_is_a_table_with_optional_diffs() {
+    let stdout = search_stdout(&["--format", "markdown", "--count", "3"]);
+    assert!(stdout.contains("| Rank | Path | Similarity |\n"));
+    assert_eq!(stdout.lines().filter(|l| l.starts_with("| ")).count(), 5);
+    assert!(!stdout.contains("<details>"));
+
+    let stdout = search_stdout(&["--format", "markdown", "--count", "3", "--with-diffs"]);
+    assert_eq!(stdout.matches("<details>").count(), 3);
+    assert!(stdout.contains("```diff\n--- sample_dir_hello_world"));
+}
+
+#[test]
+fn sarif_format_has_one_result_per_comparison() {
+    let stdout = search_stdout(&["--format", "sarif", "--min-similarity-ratio", "0.5"]);
+    let log: serde_json::Value = serde_json::from_str(&stdout).expect("valid JSON");
+    assert_eq!(log["version"], "2.1.0");
+    let results = log["runs"][0]["results"].as_array().unwrap();
+    assert!(!results.is_empty());
+    for result in results {
+        assert!(result["properties"]["similarity_ratio"].as_f64().unwrap() >= 0.5);
+        let location = &result["locations"][0]["physicalLocation"];
+        let uri = location["artifactLocation"]["uri"].as_str().unwrap();
+        assert!(uri.starts_with("sample_dir_hello_world/"), "{uri}");
+        assert!(location["region"]["startLine"].as_u64().unwrap() >= 1);
+    }
+}
+
+#[test]
+fn check_fails_above_threshold_until_the_pair_is_baselined() {
+    let baseline =
+        std::env::temp_dir().join(format!("busca_cli_baseline_{}.json", std::process::id()));
+    let check = |extra: &[&str]| {
+        busca()
+            .args([
+                "check",
+                "-r",
+                "sample_dir_hello_world/file_1.py",
+                "-s",
+                "sample_dir_hello_world",
+                "--include-glob",
+                "*.py",
+                "--fail-above",
+                "0.8",
+                "--baseline",
+            ])
+            .arg(&baseline)
+            .args(extra)
+            .output()
+            .expect("run busca")
+    };
+
+    let _ = std::fs::remove_file(&baseline);
+    let missing = check(&[]);
+    let updated = check(&["--update-baseline"]);
+    let baselined = check(&[]);
+    let baseline_text = std::fs::read_to_string(&baseline).unwrap_or_default();
+    let _ = std::fs::remove_file(&baseline);
+
+    // An explicitly named baseline must exist.
+    assert_eq!(missing.status.code(), Some(2));
+
+    assert!(updated.status.success(), "status: {:?}", updated.status);
+    assert!(baseline_text.contains("\"path\": \"sample_dir_hello_world/file_2.py\""));
+    assert!(!baseline_text.contains("\"path\": \"sample_dir_hello_world/file_1.py\""));
+
+    assert!(baselined.status.success(), "status: {:?}", baselined.status);
+    assert!(baselined.stdout.is_empty());
+}
+
+#[test]
+fn check_baselines_a_binary_pair_only_until_its_bytes_change() {
+    let root = std::env::temp_dir().join(format!("busca_cli_check_binary_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("assets")).unwrap();
+    let mut state: u32 = 1;
+    let image: Vec<u8> = (0..64 * 1024)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state as u8
+        })
+        .collect();
+    std::fs::write(root.join("ref.bin"), &image).unwrap();
+    std::fs::write(root.join("assets/copy.bin"), &image).unwrap();
+
+    let check = |extra: &[&str]| {
+        busca()
+            .arg("check")
+            .arg("-r")
+            .arg(root.join("ref.bin"))
+            .arg("-s")
+            .arg(root.join("assets"))
+            .args(["--binary", "compare", "--fail-above", "0.5", "--baseline"])
+            .arg(root.join("baseline.json"))
+            .args(extra)
+            .output()
+            .expect("run busca")
+    };
+    let updated = check(&["--update-baseline"]);
+    let baselined = check(&[]);
+    let mut patched = image.clone();
+    patched[60_000] ^= 0xFF;
+    std::fs::write(root.join("assets/copy.bin"), &patched).unwrap();
+    let changed = check(&[]);
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(updated.status.success(), "{updated:?}");
+    assert_eq!(baselined.status.code(), Some(0), "{baselined:?}");
+    assert_eq!(changed.status.code(), Some(1), "{changed:?}");
+    assert!(String::from_utf8_lossy(&changed.stdout).contains("copy.bin"));
+}
+
+#[test]
+fn check_exit_codes() {
+    let check = |fail_above: &str| {
+        busca()
+            .args([
+                "check",
+                "-r",
+                "sample_dir_hello_world/file_1.py",
+                "-s",
+                "sample_dir_hello_world",
+                "--include-glob",
+                "*.py",
+                "--fail-above",
+                fail_above,
+            ])
+            .output()
+            .expect("run busca")
+    };
+
+    let failing = check("0.8");
+    assert_eq!(failing.status.code(), Some(1));
+    let stdout = String::from_utf8(failing.stdout)
+        .unwrap()
+        .replace('\\', "/");
+    assert!(
+        stdout.contains("sample_dir_hello_world/file_2.py"),
+        "{stdout}"
+    );
+    // The reference is trivially identical to itself and never fails the gate.
+    assert!(!stdout.contains("file_1.py"), "{stdout}");
+
+    let passing = check("0.95");
+    assert_eq!(passing.status.code(), Some(0));
+    assert!(passing.stdout.is_empty());
+}
+
+#[test]
+fn search_subcommand_matches_the_flag_only_invocation() {
+    let args = [
+        "-r",
+        "sample_dir_hello_world/file_1.py",
+        "-s",
+        "sample_dir_hello_world",
+        "--format",
+        "json",
+    ];
+    let flag_only = busca().args(args).output().expect("run busca");
+    let subcommand = busca()
+        .arg("search")
+        .args(args)
+        .output()
+        .expect("run busca");
+    assert_eq!(flag_only.status.code(), Some(0));
+    assert_eq!(subcommand.status.code(), Some(0));
+    assert_eq!(flag_only.stdout, subcommand.stdout);
+
+    let nothing = busca()
+        .arg("search")
+        .args(args)
+        .args(["--include-glob", "*.nomatch"])
+        .output()
+        .expect("run busca");
+    assert_eq!(nothing.status.code(), Some(1));
+}
+
+#[test]
+fn compare_prints_the_ratio_and_diff_of_two_files() {
+    let output = busca()
+        .args([
+            "compare",
+            "sample_dir_hello_world/file_1.py",
+            "sample_dir_hello_world/file_2.py",
+        ])
+        .output()
+        .expect("run busca");
+    assert!(output.status.success(), "status: {:?}", output.status);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(
+        lines.next(),
+        Some("sample_dir_hello_world/file_1.py vs sample_dir_hello_world/file_2.py: 88.9%")
+    );
+    assert!(stdout.contains("Hello world 0"), "diff printed: {stdout}");
+
+    let ratio_only = busca()
+        .args([
+            "compare",
+            "sample_dir_hello_world/file_1.py",
+            "sample_dir_hello_world/file_2.py",
+            "--no-diff",
+        ])
+        .output()
+        .expect("run busca");
+    assert_eq!(
+        String::from_utf8(ratio_only.stdout)
+            .unwrap()
+            .lines()
+            .count(),
+        1
+    );
+
+    let missing = busca()
+        .args([
+            "compare",
+            "sample_dir_hello_world/file_1.py",
+            "nonexistent_path",
+        ])
+        .output()
+        .expect("run busca");
+    assert_eq!(missing.status.code(), Some(2));
+}
+
+#[test]
+fn compare_reads_files_like_a_search() {
+    let root = std::env::temp_dir().join(format!("busca_cli_compare_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("bom.py"), b"\xEF\xBB\xBFcaf\xC3\xA9 = 1\n").unwrap();
+    std::fs::write(root.join("latin1.py"), b"caf\xE9 = 1\n").unwrap();
+
+    let compare = |extra: &[&str]| {
+        busca()
+            .arg("compare")
+            .arg(root.join("bom.py"))
+            .arg(root.join("latin1.py"))
+            .arg("--no-diff")
+            .args(extra)
+            .output()
+            .expect("run busca")
+    };
+    let strict = compare(&[]);
+    let decoded = compare(&["--encoding", "latin1"]);
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(strict.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&strict.stderr).contains("latin1.py"));
+    assert!(decoded.status.success(), "{decoded:?}");
+    assert!(String::from_utf8_lossy(&decoded.stdout).ends_with(": 100.0%\n"));
+}
+
+#[test]
+fn diff_shows_the_closest_match_other_than_the_reference() {
+    let output = busca()
+        .args([
+            "diff",
+            "-r",
+            "sample_dir_hello_world/file_1.py",
+            "-s",
+            "sample_dir_hello_world",
+        ])
+        .output()
+        .expect("run busca");
+    assert!(output.status.success(), "status: {:?}", output.status);
+    let stdout = String::from_utf8(output.stdout).unwrap().replace('\\', "/");
+    assert!(
+        stdout.starts_with("sample_dir_hello_world/file_2.py  88.9%\n"),
+        "{stdout}"
+    );
+
+    let nothing = busca()
+        .args([
+            "diff",
+            "-r",
+            "sample_dir_hello_world/file_1.py",
+            "-s",
+            "sample_dir_hello_world",
+            "--min-similarity-ratio",
+            "0.95",
+        ])
+        .output()
+        .expect("run busca");
+    assert_eq!(nothing.status.code(), Some(1));
+    assert!(nothing.stdout.is_empty());
+}
+
+#[test]
+fn diff_flag_prints_unified_diffs_without_prompting() {
+    let stdout = search_stdout(&[
+        "--diff",
+        "all",
+        "--diff-format",
+        "unified",
+        "--context-lines",
+        "1",
+    ])
+    .replace('\\', "/");
+    // The reference is identical to itself and contributes no hunk.
+    assert!(
+        stdout.starts_with(
+            "--- sample_dir_hello_world/file_1.py\n+++ sample_dir_hello_world/file_2.py\n@@ "
+        ),
+        "{stdout}"
+    );
+    assert!(stdout.contains("\n+print(\"Hello world 0\")\n"));
+
+    let top = search_stdout(&["--diff", "top"]).replace('\\', "/");
+    assert!(top.starts_with("sample_dir_hello_world/file_1.py  100.0%\n"));
+    assert!(top.contains("The sequences are identical."));
+    assert!(!top.contains("file_2.py"));
+}
+
+#[test]
+fn diff_flag_conflicts_with_format() {
+    let output = busca()
+        .args([
+            "-r",
+            "sample_dir_hello_world/file_1.py",
+            "-s",
+            "sample_dir_hello_world",
+            "--diff",
+            "top",
+            "--format",
+            "json",
+        ])
+        .output()
+        .expect("run busca");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn color_flag_and_environment() {
+    let diff = |flags: &[&str], env: &[(&str, &str)]| {
+        let mut command = busca();
+        command.env_remove("NO_COLOR").env_remove("CLICOLOR_FORCE");
+        for (key, value) in env {
+            command.env(key, value);
+        }
+        let output = command
+            .args([
+                "compare",
+                "sample_dir_hello_world/file_1.py",
+                "sample_dir_hello_world/file_2.py",
+            ])
+            .args(flags)
+            .output()
+            .expect("run busca");
+        assert!(output.status.success(), "status: {:?}", output.status);
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("\u{1b}[")
+    };
+
+    // Piped stdout is uncolored unless forced.
+    assert!(!diff(&[], &[]));
+    assert!(diff(&["--color", "always"], &[]));
+    assert!(diff(&[], &[("CLICOLOR_FORCE", "1")]));
+    assert!(!diff(&[], &[("NO_COLOR", "1"), ("CLICOLOR_FORCE", "1")]));
+    assert!(!diff(&["--color", "never"], &[("CLICOLOR_FORCE", "1")]));
+    assert!(diff(&["--color", "always"], &[("NO_COLOR", "1")]));
+}
+
+#[test]
+fn path_display_absolute() {
+    let stdout = search_stdout(&["--format", "paths", "--path-display", "absolute"]);
+    let current_dir = std::env::current_dir().unwrap();
+    for line in stdout.lines() {
+        let path = std::path::Path::new(line);
+        assert!(path.is_absolute(), "{line}");
+        assert!(path.starts_with(&current_dir), "{line}");
+    }
+
+    // Global options are accepted after a subcommand too.
+    let output = busca()
+        .args([
+            "compare",
+            "sample_dir_hello_world/file_1.py",
+            "sample_dir_hello_world/file_2.py",
+            "--no-diff",
+            "--path-display",
+            "absolute",
+        ])
+        .output()
+        .expect("run busca");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.starts_with(&current_dir.display().to_string()),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn tui_rejects_bad_arguments_before_taking_over_the_terminal() {
+    let output = busca()
+        .args([
+            "tui",
+            "-r",
+            "sample_dir_hello_world/missing.py",
+            "-s",
+            "sample_dir_hello_world",
+        ])
+        .output()
+        .expect("run busca");
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("is not a file"), "{stderr}");
+    assert!(!stderr.contains("\x1b[?1049h"), "{stderr:?}");
+}
+
+#[test]
+fn piped_diffs_bypass_the_pager() {
+    // stdout is a pipe here, so a pager that would fail must never start.
+    let output = busca()
+        .args([
+            "compare",
+            "sample_dir_hello_world/file_1.py",
+            "sample_dir_hello_world/file_2.py",
+        ])
+        .env("BUSCA_PAGER", "definitely-not-a-pager-xyz")
+        .output()
+        .expect("run busca");
+    assert!(output.status.success(), "status: {:?}", output.status);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("88.9%"), "{stdout}");
+    assert!(stdout.contains("Hello world 0"), "{stdout}");
+}
+
+#[test]
+fn select_needs_a_terminal_and_multi_needs_select() {
+    let select = busca()
+        .args([
+            "-r",
+            "sample_dir_hello_world/file_1.py",
+            "-s",
+            "sample_dir_hello_world",
+            "--select",
+        ])
+        .output()
+        .expect("run busca");
+    assert_eq!(select.status.code(), Some(2));
+    assert!(select.stdout.is_empty());
+    let stderr = String::from_utf8(select.stderr).unwrap();
+    assert!(
+        stderr.contains("--select needs an interactive terminal"),
+        "{stderr}"
+    );
+
+    let multi = busca()
+        .args(["-r", "sample_dir_hello_world/file_1.py", "--multi"])
+        .output()
+        .expect("run busca");
+    assert_eq!(multi.status.code(), Some(2));
+}
+
+#[test]
+fn watch_emits_an_initial_ndjson_ranking_and_rejects_other_formats() {
+    use std::io::{BufRead, BufReader};
+
+    let mut child = busca()
+        .args([
+            "-r",
+            "sample_dir_hello_world/file_1.py",
+            "-s",
+            "sample_dir_hello_world",
+            "--include-glob",
+            "*.py",
+            "--count",
+            "2",
+            "--watch",
+            "--format",
+            "ndjson",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("run busca");
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap())
+        .read_line(&mut line)
+        .expect("read the first event");
+    child.kill().expect("stop watching");
+    let _ = child.wait();
+
+    let event: serde_json::Value = serde_json::from_str(&line).expect("one JSON event per line");
+    assert_eq!(event["event"], "ranking");
+    assert_eq!(event["changed"], serde_json::json!([]));
+    assert_eq!(event["reference_changed"], false);
+    assert_eq!(event["results"].as_array().unwrap().len(), 2);
+
+    let json = busca()
+        .args([
+            "-r",
+            "sample_dir_hello_world/file_1.py",
+            "--watch",
+            "--format",
+            "json",
+        ])
+        .output()
+        .expect("run busca");
+    assert_eq!(json.status.code(), Some(2));
+}
+
+#[test]
+fn serve_answers_searches_over_http_on_localhost() {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpStream;
+
+    let mut child = busca()
+        .args(["serve", "--listen", "127.0.0.1:0"])
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("run busca");
+    let mut line = String::new();
+    BufReader::new(child.stderr.take().unwrap())
+        .read_line(&mut line)
+        .expect("read the listening address");
+    let address = line
+        .trim()
+        .strip_prefix("Listening on http://")
+        .expect("the bound address on stderr")
+        .to_owned();
+
+    let post = |path: &str, body: &str| {
+        let mut stream = TcpStream::connect(&address).expect("connect to busca serve");
+        write!(
+            stream,
+            "POST {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    };
+    let search = post(
+        "/search",
+        r#"{"ref_file_path": "sample_dir_hello_world/file_1.py", "search_path": "sample_dir_hello_world", "include_glob": ["*.py"], "count": 2}"#,
+    );
+    let bad = post("/search", "{}");
+    child.kill().expect("stop serving");
+    let _ = child.wait();
+
+    let (head, body) = search.split_once("\r\n\r\n").expect("an HTTP response");
+    assert!(head.starts_with("HTTP/1.1 200 OK"), "{head}");
+    let response: serde_json::Value = serde_json::from_str(body).expect("a JSON body");
+    let results = response["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["path"], "sample_dir_hello_world/file_1.py");
+    assert!(bad.starts_with("HTTP/1.1 400 Bad Request"), "{bad}");
+}
+
+#[test]
+fn serve_refuses_non_loopback_addresses_without_allow_remote() {
+    let output = busca()
+        .args(["serve", "--listen", "0.0.0.0:0"])
+        .output()
+        .expect("run busca");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--allow-remote"));
+}
+
+#[test]
+fn lsp_initializes_over_stdio_and_exits_after_shutdown() {
+    use std::io::Write;
+
+    let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{body}", body.len());
+    let input = [
+        frame(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"rootUri":null,"capabilities":{}}}"#),
+        frame(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#),
+        frame(r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#),
+        frame(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+    ]
+    .concat();
+
+    let mut child = busca()
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("run busca");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().expect("wait for busca");
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let bodies: Vec<serde_json::Value> = stdout
+        .split("Content-Length: ")
+        .skip(1)
+        .map(|message| serde_json::from_str(message.split_once("\r\n\r\n").unwrap().1).unwrap())
+        .collect();
+    assert_eq!(bodies.len(), 2);
+    assert_eq!(
+        bodies[0]["result"]["capabilities"]["executeCommandProvider"]["commands"],
+        serde_json::json!(["busca.findSimilar"])
+    );
+    assert_eq!(bodies[1]["id"], 2);
+}
+
+#[test]
+fn batch_answers_one_line_per_query_and_exits_2_if_any_failed() {
+    use std::io::Write;
+
+    let input = concat!(
+        r#"{"id": "a", "ref_file_path": "sample_dir_hello_world/file_1.py", "search_path": "sample_dir_hello_world", "count": 1}"#,
+        "\n\n",
+        r#"{"id": "b", "reference": "x", "search_path": "missing_dir"}"#,
+        "\n",
+        r#"{"id": "c", "ref_file_path": "sample_dir_hello_world/file_2.py", "search_path": "sample_dir_hello_world", "count": 1}"#,
+        "\n",
+    );
+    let mut child = busca()
+        .arg("batch")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("run busca");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().expect("wait for busca");
+    assert_eq!(output.status.code(), Some(2));
+
+    let lines: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("one JSON object per line"))
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["id"], "a");
+    assert_eq!(
+        lines[0]["results"][0]["path"],
+        "sample_dir_hello_world/file_1.py"
+    );
+    assert_eq!(lines[1]["id"], "b");
+    assert!(lines[1]["error"].is_string());
+    assert_eq!(
+        lines[2]["results"][0]["path"],
+        "sample_dir_hello_world/file_2.py"
+    );
+}
+
+#[test]
+fn check_staged_only_reports_staged_duplicates() {
+    let root = std::env::temp_dir().join(format!("busca_cli_git_{}", std::process::id()));
+    let repo = root.join("repo");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&repo).unwrap();
+    let git = |arguments: &[&str]| {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&repo)
+            .args([
+                "-c",
+                "user.name=busca",
+                "-c",
+                "user.email=busca@example.com",
+            ])
+            .args(arguments)
+            .output()
+            .expect("run git")
+            .status;
+        assert!(status.success(), "git {arguments:?}");
+    };
+    let duplicate = "def f():\n    return 1\n";
+    std::fs::write(root.join("ref.py"), duplicate).unwrap();
+    git(&["init", "-q"]);
+    std::fs::write(repo.join("old.py"), duplicate).unwrap();
+    git(&["add", "old.py"]);
+    git(&["commit", "-q", "-m", "old"]);
+    std::fs::write(repo.join("new.py"), duplicate).unwrap();
+    git(&["add", "new.py"]);
+
+    let check = |flag: &str| {
+        busca()
+            .arg("check")
+            .arg("-r")
+            .arg(root.join("ref.py"))
+            .arg("-s")
+            .arg(&repo)
+            .args(["--fail-above", "0.9", "--no-config", flag])
+            .output()
+            .expect("run busca")
+    };
+    let staged = check("--staged");
+    let tracked = check("--tracked");
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(staged.status.code(), Some(1));
+    let stdout = String::from_utf8(staged.stdout).unwrap();
+    assert!(stdout.contains("new.py"), "{stdout}");
+    assert!(!stdout.contains("old.py"), "{stdout}");
+    let stdout = String::from_utf8(tracked.stdout).unwrap();
+    assert!(
+        stdout.contains("new.py") && stdout.contains("old.py"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn history_finds_the_commit_of_a_deleted_file() {
+    let root = std::env::temp_dir().join(format!("busca_cli_history_{}", std::process::id()));
+    let repo = root.join("repo");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&repo).unwrap();
+    let git = |arguments: &[&str]| {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&repo)
+            .args([
+                "-c",
+                "user.name=busca",
+                "-c",
+                "user.email=busca@example.com",
+            ])
+            .args(arguments)
+            .output()
+            .expect("run git");
+        assert!(output.status.success(), "git {arguments:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    git(&["init", "-q"]);
+    std::fs::write(repo.join("gone.py"), "def f():\n    return 1\n").unwrap();
+    git(&["add", "."]);
+    git(&["commit", "-q", "-m", "add"]);
+    let added = git(&["rev-parse", "HEAD"]).trim().to_owned();
+    std::fs::remove_file(repo.join("gone.py")).unwrap();
+    std::fs::write(repo.join("kept.py"), "x = 1\n").unwrap();
+    git(&["add", "-A"]);
+    git(&["commit", "-q", "-m", "remove"]);
+    std::fs::write(root.join("ref.py"), "def f():\n    return 2\n").unwrap();
+
+    let history = |extra: &[&str]| {
+        busca()
+            .arg("history")
+            .arg("-r")
+            .arg(root.join("ref.py"))
+            .arg("--repo")
+            .arg(&repo)
+            .args(extra)
+            .output()
+            .expect("run busca")
+    };
+    let json = history(&["--format", "json"]);
+    let human = history(&[]);
+    let csv = history(&["--format", "csv"]);
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(json.status.success());
+    let rows: serde_json::Value = serde_json::from_slice(&json.stdout).expect("JSON rows");
+    assert_eq!(rows[0]["commit"], added.as_str());
+    assert_eq!(rows[0]["path"], "gone.py");
+    assert_eq!(rows[0]["similarity_ratio"], 0.5);
+    let human = String::from_utf8(human.stdout).unwrap();
+    assert!(
+        human.starts_with(&format!("{}:gone.py", &added[..12])),
+        "{human}"
+    );
+    assert_eq!(csv.status.code(), Some(2));
+}
+
+#[test]
+fn search_archives_reports_virtual_paths_inside_a_gzip() {
+    use std::io::Write;
+    let root = std::env::temp_dir().join(format!("busca_cli_archives_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(b"def f():\n    return 1\n").unwrap();
+    std::fs::write(root.join("old.py.gz"), encoder.finish().unwrap()).unwrap();
+    std::fs::write(root.join("ref.py"), "def f():\n    return 1\n").unwrap();
+
+    let search = |extra: &[&str]| {
+        busca()
+            .arg("search")
+            .arg("-r")
+            .arg(root.join("ref.py"))
+            .arg("-s")
+            .arg(&root)
+            .args(["-i", "*old.py", "--format", "json"])
+            .args(extra)
+            .output()
+            .expect("run busca")
+    };
+    let without = search(&[]);
+    let with = search(&["--search-archives"]);
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(without.status.code(), Some(1));
+    assert!(with.status.success());
+    let rows: serde_json::Value = serde_json::from_slice(&with.stdout).expect("JSON rows");
+    let expected = format!("{}!/old.py", root.join("old.py.gz").display());
+    assert_eq!(rows[0]["path"], expected.as_str());
+    assert_eq!(rows[0]["similarity_ratio"], 1.0);
+}
+
+#[test]
+fn encoding_decodes_files_that_are_not_utf8() {
+    let root = std::env::temp_dir().join(format!("busca_cli_encoding_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    std::fs::write(root.join("src/legacy.txt"), b"caf\xE9 = 1\n").unwrap();
+    std::fs::write(root.join("ref.txt"), "caf\u{e9} = 1\n").unwrap();
+
+    let search = |extra: &[&str]| {
+        busca()
+            .arg("search")
+            .arg("-r")
+            .arg(root.join("ref.txt"))
+            .arg("-s")
+            .arg(root.join("src"))
+            .args(["--format", "json"])
+            .args(extra)
+            .output()
+            .expect("run busca")
+    };
+    let without = search(&[]);
+    let latin1 = search(&["--encoding", "latin1"]);
+    let unknown = search(&["--encoding", "klingon"]);
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(without.status.code(), Some(1));
+    assert!(latin1.status.success());
+    let rows: serde_json::Value = serde_json::from_slice(&latin1.stdout).expect("JSON rows");
+    assert_eq!(rows[0]["similarity_ratio"], 1.0);
+    assert_eq!(unknown.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("unknown encoding: klingon"));
+}
+
+#[test]
+fn references_are_decoded_like_candidates() {
+    let root = std::env::temp_dir().join(format!("busca_cli_reference_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("bom.py"), b"\xEF\xBB\xBFx = 1\ny = 2\n").unwrap();
+    std::fs::write(root.join("latin1.py"), b"caf\xE9 = 1\nth\xE9 = 2\n").unwrap();
+
+    let search = |reference: &str| {
+        busca()
+            .arg("search")
+            .arg("-r")
+            .arg(root.join(reference))
+            .arg("-s")
+            .arg(&root)
+            .args(["--encoding", "latin1", "--format", "json"])
+            .output()
+            .expect("run busca")
+    };
+    let bom = search("bom.py");
+    let latin1 = search("latin1.py");
+    let _ = std::fs::remove_dir_all(&root);
+
+    for (reference, output) in [("bom.py", bom), ("latin1.py", latin1)] {
+        assert!(output.status.success(), "{reference}: {:?}", output);
+        let rows: serde_json::Value = serde_json::from_slice(&output.stdout).expect("JSON rows");
+        assert!(rows[0]["path"].as_str().unwrap().ends_with(reference));
+        assert_eq!(rows[0]["similarity_ratio"], 1.0, "{reference}");
+    }
+}
+
+#[test]
+fn binary_files_are_skipped_reported_or_compared() {
+    let root = std::env::temp_dir().join(format!("busca_cli_binary_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("src")).unwrap();
+    let image: Vec<u8> = (0..4096u32).map(|i| (i * 31 % 256) as u8).collect();
+    std::fs::write(root.join("ref.bin"), &image).unwrap();
+    std::fs::write(root.join("src/copy.bin"), &image).unwrap();
+    std::fs::write(root.join("src/notes.txt"), "notes\n").unwrap();
+    std::fs::write(root.join("src/legacy.txt"), b"caf\xE9 = 1\n").unwrap();
+
+    let search = |reference: &str, extra: &[&str]| {
+        busca()
+            .arg("search")
+            .arg("-r")
+            .arg(root.join(reference))
+            .arg("-s")
+            .arg(root.join("src"))
+            .args(["--format", "json"])
+            .args(extra)
+            .output()
+            .expect("run busca")
+    };
+    let report = search("src/notes.txt", &["--binary", "report"]);
+    // A binary reference is read only when binary files are compared.
+    let skip = search("ref.bin", &[]);
+    let compare = search("ref.bin", &["--binary", "compare"]);
+    let text_reference = search(
+        "src/legacy.txt",
+        &["--binary", "compare", "--encoding", "latin1"],
+    );
+    let unknown = search("ref.bin", &["--binary", "sometimes"]);
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert!(report.status.success());
+    assert!(String::from_utf8_lossy(&report.stderr).contains("skipping binary file"));
+    assert_eq!(skip.status.code(), Some(2));
+    assert!(compare.status.success());
+    let rows: serde_json::Value = serde_json::from_slice(&compare.stdout).expect("JSON rows");
+    assert!(rows[0]["path"].as_str().unwrap().ends_with("copy.bin"));
+    assert_eq!(rows[0]["similarity_ratio"], 1.0);
+    // A text reference is still compared as text with text files.
+    let rows: serde_json::Value =
+        serde_json::from_slice(&text_reference.stdout).expect("JSON rows");
+    assert!(rows[0]["path"].as_str().unwrap().ends_with("legacy.txt"));
+    assert_eq!(rows[0]["similarity_ratio"], 1.0);
+    assert_eq!(unknown.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("unknown binary policy"));
+}
+
+#[cfg(feature = "html")]
+#[test]
+fn html_candidates_are_compared_as_text_with_extract_text() {
+    let root = std::env::temp_dir().join(format!("busca_cli_extract_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("docs")).unwrap();
+    std::fs::write(
+        root.join("docs/guide.html"),
+        "<html><body>\n<h1>Setup</h1>\n<p>Install the tool &amp;\n  run it.</p>\n</body></html>\n",
+    )
+    .unwrap();
+    std::fs::write(root.join("ref.txt"), "Setup\nInstall the tool & run it.\n").unwrap();
+
+    let search = |extra: &[&str]| {
+        let output = busca()
+            .arg("search")
+            .arg("-r")
+            .arg(root.join("ref.txt"))
+            .arg("-s")
+            .arg(root.join("docs"))
+            .args(["--format", "json", "--with-content"])
+            .args(extra)
+            .output()
+            .expect("run busca");
+        assert!(output.status.success(), "status: {:?}", output.status);
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).expect("JSON rows")
+    };
+    let extracted = search(&["--extract-text"]);
+    let raw = search(&[]);
+
+    // An HTML reference is extracted like the candidates, so a copy of it is a
+    // duplicate.
+    std::fs::copy(root.join("docs/guide.html"), root.join("docs/copy.html")).unwrap();
+    let check = busca()
+        .arg("check")
+        .arg("-r")
+        .arg(root.join("docs/guide.html"))
+        .arg("-s")
+        .arg(root.join("docs"))
+        .args(["--fail-above", "0.5", "--extract-text"])
+        .output()
+        .expect("run busca");
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(extracted[0]["similarity_ratio"], 1.0);
+    assert_eq!(
+        extracted[0]["content"],
+        "Setup\nInstall the tool & run it.\n"
+    );
+    assert!(raw[0]["similarity_ratio"].as_f64().unwrap() < 0.5);
+    assert_eq!(check.status.code(), Some(1), "{check:?}");
+    assert!(String::from_utf8_lossy(&check.stdout).contains("copy.html"));
+}
+
+#[cfg(feature = "ipynb")]
+#[test]
+fn notebooks_report_the_best_matching_cell_in_json() {
+    let root = std::env::temp_dir().join(format!("busca_cli_notebook_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(
+        root.join("analysis.ipynb"),
+        r#"{"cells": [
+            {"cell_type": "code", "metadata": {}, "outputs": [], "source": ["import os\n"]},
+            {"cell_type": "code", "metadata": {}, "outputs": [], "source": ["total = sum(values)\n", "print(total)\n"]}
+        ], "metadata": {}, "nbformat": 4, "nbformat_minor": 5}"#,
+    )
+    .unwrap();
+    std::fs::write(root.join("ref.py"), "total = sum(values)\nprint(total)\n").unwrap();
+
+    let search = |reference: &str, extra: &[&str]| {
+        let output = busca()
+            .arg("search")
+            .arg("-r")
+            .arg(root.join(reference))
+            .arg("-s")
+            .arg(&root)
+            .args(["--include-glob", "*.ipynb", "--format", "json"])
+            .args(extra)
+            .output()
+            .expect("run busca");
+        assert!(output.status.success(), "status: {:?}", output.status);
+        serde_json::from_slice::<serde_json::Value>(&output.stdout).expect("JSON rows")
+    };
+    let from_script = search("ref.py", &["--extract-text"]);
+    let from_itself = search("analysis.ipynb", &["--extract-text"]);
+    let unextracted = search("ref.py", &[]);
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(from_script[0]["cell"], 1);
+    assert_eq!(from_script[0]["similarity_ratio"], 0.8);
+    assert_eq!(from_itself[0]["similarity_ratio"], 1.0);
+    // Without --extract-text the notebook is compared as its JSON.
+    assert!(unextracted[0].get("cell").is_none());
+    assert!(unextracted[0]["similarity_ratio"].as_f64().unwrap() < 0.8);
+}
+
+#[test]
+fn notebook_markdown_requires_extract_text() {
+    let output = busca()
+        .args([
+            "search",
+            "-s",
+            "sample_dir_hello_world",
+            "--notebook-markdown",
+        ])
+        .output()
+        .expect("run busca");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--extract-text"));
+}